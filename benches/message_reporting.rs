@@ -1,4 +1,4 @@
-use cerberus::UserId;
+use cerberus::{Envelope, Platform, Ristretto255, TokenRequest, UserId};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use std::{thread, time::Duration, vec};
 
@@ -31,19 +31,32 @@ fn bench_message_reporting(c: &mut Criterion) {
     for (n, t) in n_moderators.into_iter().zip(thresholds) {
         // create benchmark coordinator
         let mut coordinator = tokio_runtime
-            .block_on(cerberus::Coordinator::init(n, t, t, batch_size))
+            .block_on(cerberus::Coordinator::<Ristretto255>::init(
+                n, t, t, batch_size,
+            ))
             .unwrap();
 
-        // get a token to report
-        let user_ids: Vec<_> =
-            (0..batch_size).map(|_| UserId::random(&mut rng)).collect();
+        // get a token and use it to send a message to report
+        let (token_request, sk_e) =
+            TokenRequest::new(UserId::random(&mut rng), &mut rng);
 
         let token = tokio_runtime
-            .block_on(coordinator.create_tokens(&user_ids))
+            .block_on(coordinator.create_tokens(&vec![token_request]))
             .unwrap()
             .pop()
             .unwrap();
 
+        // deliver the message via the platform
+        let platform =
+            Platform::new(coordinator.group_verifying_key(), &mut rng);
+        tokio_runtime
+            .block_on(coordinator.register_platform(&platform.public_key()))
+            .unwrap();
+
+        let message = b"some abusive message";
+        let envelope = Envelope::seal(token, &sk_e, message).unwrap();
+        let envelope = platform.stamp(&envelope).unwrap();
+
         let id = BenchmarkId::from_parameter(format!("{n}-{t}"));
         message_reporting.bench_with_input(
             id,
@@ -51,7 +64,10 @@ fn bench_message_reporting(c: &mut Criterion) {
             |b, _batch_size| {
                 b.iter(|| {
                     tokio_runtime
-                        .block_on(coordinator.request_token_decryption(&token))
+                        .block_on(
+                            coordinator
+                                .request_token_decryption(&envelope, message),
+                        )
                         .unwrap();
                 })
             },
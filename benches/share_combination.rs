@@ -0,0 +1,36 @@
+use cerberus::{bench::ShareCombination, Ristretto255};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn bench_share_combination(c: &mut Criterion) {
+    let n_moderators = vec![7, 31, 101];
+
+    let mut share_combination = c.benchmark_group("share_combination");
+
+    for n in n_moderators {
+        // a majority of the moderators answer
+        let t = n / 2 + 1;
+        let shares = ShareCombination::<Ristretto255>::new(n, t);
+
+        // fill the cache
+        let combined = shares.combine_cached().unwrap();
+        assert_eq!(shares.combine_without_cache().unwrap(), combined);
+        assert_eq!(shares.combine_uncached().unwrap(), combined);
+
+        let parameter = format!("{n}-{t}");
+        share_combination.bench_function(
+            BenchmarkId::new("without_cache", &parameter),
+            |b| b.iter(|| shares.combine_without_cache()),
+        );
+        share_combination
+            .bench_function(BenchmarkId::new("multiscalar", &parameter), |b| {
+                b.iter(|| shares.combine_uncached())
+            });
+        share_combination.bench_function(
+            BenchmarkId::new("cached_multiscalar", &parameter),
+            |b| b.iter(|| shares.combine_cached()),
+        );
+    }
+}
+
+criterion_group!(benches, bench_share_combination);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use cerberus::UserId;
+use cerberus::{Ristretto255, TokenRequest, UserId};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use std::{thread, time::Duration, vec};
 
@@ -32,12 +32,20 @@ fn bench_token_creation(c: &mut Criterion) {
         for batch_size in &batch_sizes {
             // create benchmark coordinator
             let mut coordinator = tokio_runtime
-                .block_on(cerberus::Coordinator::init(n, t, t, *batch_size))
+                .block_on(cerberus::Coordinator::<Ristretto255>::init(
+                    n,
+                    t,
+                    t,
+                    *batch_size,
+                ))
                 .unwrap();
 
-            //create random batch of user ids
-            let user_ids: Vec<_> =
-                (0..*batch_size).map(|_| UserId::random(&mut rng)).collect();
+            //create random batch of token requests
+            let token_requests: Vec<_> = (0..*batch_size)
+                .map(|_| {
+                    TokenRequest::new(UserId::random(&mut rng), &mut rng).0
+                })
+                .collect();
 
             let id =
                 BenchmarkId::from_parameter(format!("{n}-{t}-{batch_size}"));
@@ -47,7 +55,9 @@ fn bench_token_creation(c: &mut Criterion) {
                 |b, _batch_size| {
                     b.iter(|| {
                         let _tokens = tokio_runtime
-                            .block_on(coordinator.create_tokens(&user_ids))
+                            .block_on(
+                                coordinator.create_tokens(&token_requests),
+                            )
                             .expect("Failed to create tokens.");
                     })
                 },
@@ -1,11 +1,17 @@
-use cerberus::{Coordinator, UserId};
+use cerberus::{
+    Client, Coordinator, Platform, Ristretto255, TimestampPolicy, TokenWallet,
+    UserId,
+};
 use std::{error::Error, thread, time};
 
 /// This function does run through of all the main functionality of the protocol.
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn Error>> {
     let batch_size = 1;
-    let n_moderators = 5; // must be less than the number of moderator containers
+    // resharing grows the committee by one moderator, and docker-compose.yaml
+    // must run a container for every moderator of the larger committee
+    let n_moderators = 5;
+    let n_moderators_after_reshare = n_moderators + 1;
     let decryption_threshold = 3;
     let signing_threshold = 3;
 
@@ -14,7 +20,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // setup moderators
     println!("Initializing moderators...");
-    let mut coordinator = Coordinator::init(
+    let mut coordinator = Coordinator::<Ristretto255>::init(
         n_moderators,
         signing_threshold,
         decryption_threshold,
@@ -23,32 +29,94 @@ async fn main() -> Result<(), Box<dyn Error>> {
     .await?;
 
     let mut rng = rand::thread_rng();
-    let user_ids = (0..batch_size).map(|_| UserId::random(&mut rng)).collect();
+
+    // set up the platform that relays messages
+    println!("Registering platform...");
+    let platform = Platform::new(coordinator.group_verifying_key(), &mut rng);
+    coordinator
+        .register_platform(&platform.public_key())
+        .await?;
+
+    // set up the users
+    let client = |rng: &mut _| {
+        Client::new(
+            UserId::random(rng),
+            coordinator.group_verifying_key(),
+            coordinator.group_elgamal_key(),
+            platform.public_key(),
+        )
+    };
+    let sender = client(&mut rng);
+    let recipient = client(&mut rng);
+
+    let mut wallet = TokenWallet::new(TimestampPolicy::default());
 
     println!("Creating token batch 1...");
-    coordinator.create_tokens(&user_ids).await?;
+    sender
+        .refill_wallet(&mut coordinator, &mut wallet, batch_size)
+        .await?;
 
     // sign another batch to make sure that nonces are being properly kept in sync
     println!("Creating token batch 2...");
-    let tokens = coordinator.create_tokens(&user_ids).await?;
+    sender
+        .refill_wallet(&mut coordinator, &mut wallet, batch_size)
+        .await?;
+
+    // send a message via the platform
+    println!("Sending message...");
+    let message = b"some abusive message";
+    let envelope =
+        platform.stamp(&sender.seal_from_wallet(&mut wallet, message)?)?;
+    recipient.verify_incoming(&envelope, message)?;
 
     // request decryption shares
-    println!("Decrypting token...");
+    println!("Reporting message...");
     let decrypted_user_id =
-        coordinator.request_token_decryption(&tokens[0]).await?;
+        recipient.report(&coordinator, &envelope, message).await?;
 
     assert_eq!(
-        user_ids[0], decrypted_user_id,
+        sender.user_id(),
+        decrypted_user_id,
         "Decrypted user ID is incorrect."
     );
 
+    // refresh the decryption key shares and make sure
+    // the message can still be reported
+    println!("Refreshing decryption key shares...");
+    coordinator.refresh_decryption_shares().await?;
+
+    println!("Reporting message again...");
+    assert_eq!(
+        sender.user_id(),
+        recipient.report(&coordinator, &envelope, message).await?,
+        "Decrypted user ID is incorrect after refreshing key shares."
+    );
+
+    // move the keys to a larger committee with a higher threshold
+    // and make sure the message can still be reported
+    println!("Resharing keys to a new committee...");
+    coordinator
+        .reshare(
+            n_moderators_after_reshare,
+            signing_threshold + 1,
+            decryption_threshold + 1,
+        )
+        .await?;
+
+    println!("Reporting message after resharing...");
+    assert_eq!(
+        sender.user_id(),
+        recipient.report(&coordinator, &envelope, message).await?,
+        "Decrypted user ID is incorrect after resharing keys."
+    );
+
     // shut down moderators
     println!("Shutting down moderators...");
     coordinator.shutdown_moderators().await?;
 
     // start them back up again to make sure it works
     println!("Restarting moderators...");
-    Coordinator::init(
+    Coordinator::<Ristretto255>::init(
         n_moderators,
         signing_threshold,
         decryption_threshold,
@@ -0,0 +1,34 @@
+use cerberus::{ModeratorIdentity, Ristretto255};
+use std::{env, error::Error, fs, path::Path};
+
+/// Generates the identity keys of a committee of moderators, writing the
+/// identity of moderator `i` to `{dir}/{host}-{i}.bin`.
+///
+/// Usage: `gen_identities <dir> <host> <n_moderators>`
+///
+/// Committees on different hosts need their own identities, so run this once
+/// per committee. `n_moderators` should cover every moderator that may ever
+/// join the committee, including through a reshare.
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let [dir, host, n_moderators] = &args[..] else {
+        return Err("Usage: gen_identities <dir> <host> <n_moderators>".into());
+    };
+    let n_moderators: usize = n_moderators.parse()?;
+
+    fs::create_dir_all(dir)?;
+
+    let identities = ModeratorIdentity::<Ristretto255>::generate_committee(
+        &mut rand::thread_rng(),
+        n_moderators,
+    );
+    for identity in identities {
+        let path = Path::new(dir)
+            .join(format!("{host}-{}.bin", identity.identifier()));
+        identity.save(&path)?;
+
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
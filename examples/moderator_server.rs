@@ -1,7 +1,15 @@
 fn main() -> cerberus::Result<()> {
     let server = tiny_http::Server::http("0.0.0.0:80").unwrap();
 
+    // the identity key, along with the identity keys of the rest of the
+    // committee, is provisioned when the moderator is deployed
+    let identity_path = std::env::var("CERBERUS_IDENTITY")
+        .unwrap_or_else(|_| "identity.bin".into());
+    let identity = cerberus::ModeratorIdentity::<cerberus::Ristretto255>::load(
+        identity_path,
+    )?;
+
     loop {
-        cerberus::Moderator::run_server(&server)?;
+        cerberus::Moderator::run_server(&server, &identity)?;
     }
 }
@@ -0,0 +1,131 @@
+//! Internals exposed for the benchmarks in `benches/`.
+//!
+//! Nothing here is part of the public API.
+
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    elgamal::{
+        self, DecryptionError, DecryptionShare, EncryptedUserId,
+        VerificationKey,
+    },
+    shamir::{lagrange_coefficients, LagrangeCache},
+    Result, UserId,
+};
+
+/// Decryption shares of a user id from `threshold` out of `n_moderators`
+/// moderators, ready to be combined.
+pub struct ShareCombination<C: Ciphersuite = Ristretto255> {
+    x_1: EncryptedUserId<C>,
+    shares: Vec<DecryptionShare<C>>,
+    verification_keys: HashMap<u16, VerificationKey<C>>,
+    threshold: usize,
+    lagrange_cache: LagrangeCache<C>,
+}
+
+impl<C: Ciphersuite> ShareCombination<C> {
+    /// Shares from a random subset of `threshold` moderators, in the order
+    /// they happened to answer.
+    pub fn new(n_moderators: usize, threshold: usize) -> Self {
+        let mut rng = rand::thread_rng();
+
+        let (pk, key_shares, _) = elgamal::generate_private_key_shares::<C, _>(
+            &mut rng,
+            n_moderators,
+            threshold,
+        );
+        let x_1 = pk.encrypt(&UserId([7; 32]), &C::random_scalar(&mut rng));
+
+        let shares = key_shares
+            .choose_multiple(&mut rng, threshold)
+            .map(|key_share| key_share.decryption_share(&x_1, &mut rng))
+            .collect();
+        let verification_keys = key_shares
+            .iter()
+            .map(|key_share| {
+                (key_share.identifier(), key_share.verification_key())
+            })
+            .collect();
+
+        Self {
+            x_1,
+            shares,
+            verification_keys,
+            threshold,
+            lagrange_cache: LagrangeCache::default(),
+        }
+    }
+
+    /// Combines the shares as the coordinator did before coefficients were
+    /// cached: the Lagrange coefficients are recomputed with a single
+    /// inversion every time, followed by a scalar multiplication per share.
+    pub fn combine_without_cache(&self) -> Result<C::Element> {
+        let mut valid_shares: Vec<&DecryptionShare<C>> =
+            Vec::with_capacity(self.threshold);
+        for share in &self.shares {
+            if valid_shares.len() == self.threshold {
+                break;
+            }
+
+            let is_duplicate = valid_shares
+                .iter()
+                .any(|valid| valid.identifier == share.identifier);
+            if is_duplicate {
+                continue;
+            }
+
+            match self.verification_keys.get(&share.identifier) {
+                Some(key) if share.verify(&self.x_1, key) => {
+                    valid_shares.push(share)
+                }
+                _ => {}
+            }
+        }
+
+        if valid_shares.len() < self.threshold {
+            return Err(DecryptionError::NotEnoughShares {
+                valid: valid_shares.len(),
+                threshold: self.threshold,
+                invalid_moderators: Vec::new(),
+            }
+            .into());
+        }
+
+        let identifiers: Vec<_> = valid_shares
+            .iter()
+            .map(|share| C::scalar_from_u64(share.identifier as u64))
+            .collect();
+
+        Ok(valid_shares
+            .iter()
+            .zip(lagrange_coefficients::<C>(&identifiers))
+            .map(|(share, lambda)| lambda * share.share)
+            .sum())
+    }
+
+    /// Combines the shares as the coordinator does for a set of moderators
+    /// it hasn't seen before.
+    pub fn combine_uncached(&self) -> Result<C::Element> {
+        elgamal::combine_shares(
+            &self.x_1,
+            &self.shares,
+            &self.verification_keys,
+            self.threshold,
+            &LagrangeCache::default(),
+        )
+    }
+
+    /// Combines the shares as the coordinator does when the same moderators
+    /// answer again.
+    pub fn combine_cached(&self) -> Result<C::Element> {
+        elgamal::combine_shares(
+            &self.x_1,
+            &self.shares,
+            &self.verification_keys,
+            self.threshold,
+            &self.lagrange_cache,
+        )
+    }
+}
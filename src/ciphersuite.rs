@@ -0,0 +1,498 @@
+//! The groups that the protocol can be instantiated over.
+//!
+//! A [`Ciphersuite`] ties the FROST ciphersuite used to sign tokens to the
+//! group used for ElGamal encryption, key generation, and their proofs. Both
+//! halves use the same group so that the moderators' signing and decryption
+//! keys can be generated, refreshed, and reshared together.
+
+use curve25519_dalek::{
+    constants::{ED25519_BASEPOINT_TABLE, RISTRETTO_BASEPOINT_TABLE},
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::{Identity, VartimeMultiscalarMul},
+};
+use frost_core::{
+    frost::keys::{SigningShare, VerifyingShare},
+    SigningKey, VerifyingKey,
+};
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha512};
+use std::{
+    fmt::Debug,
+    iter::{Product, Sum},
+    ops::{Add, AddAssign, Mul, Sub},
+};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+use crate::Result;
+
+/// A fixed-length byte encoding of a scalar or group element,
+/// e.g., `[u8; 32]`.
+pub trait Encoding:
+    AsRef<[u8]>
+    + AsMut<[u8]>
+    + Default
+    + Copy
+    + Debug
+    + Eq
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+{
+    /// Copies `bytes` into an encoding, failing if it has the wrong length.
+    fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let mut encoding = Self::default();
+        if encoding.as_ref().len() != bytes.len() {
+            return Err("Encoding has the wrong length.".into());
+        }
+        encoding.as_mut().copy_from_slice(bytes);
+
+        Ok(encoding)
+    }
+}
+
+impl<T> Encoding for T where
+    T: AsRef<[u8]>
+        + AsMut<[u8]>
+        + Default
+        + Copy
+        + Debug
+        + Eq
+        + Serialize
+        + DeserializeOwned
+        + Send
+        + Sync
+{
+}
+
+/// A FROST ciphersuite together with the prime-order group that it signs over.
+///
+/// The encoding of a FROST verifying key MUST be that of the corresponding
+/// group element.
+pub trait Ciphersuite:
+    Copy + Debug + Default + PartialEq + Eq + Send + Sync + 'static
+{
+    /// The FROST ciphersuite used to sign tokens.
+    type Frost: frost_core::Ciphersuite;
+
+    /// An integer modulo the order of the group.
+    type Scalar: Copy
+        + Debug
+        + Eq
+        + Add<Output = Self::Scalar>
+        + Sub<Output = Self::Scalar>
+        + Mul<Output = Self::Scalar>
+        + Mul<Self::Element, Output = Self::Element>
+        + AddAssign
+        + Sum
+        + Product
+        + ConstantTimeEq
+        + Zeroize
+        + Serialize
+        + DeserializeOwned
+        + Send
+        + Sync;
+
+    /// An element of the group.
+    type Element: Copy
+        + Debug
+        + Eq
+        + Add<Output = Self::Element>
+        + Sub<Output = Self::Element>
+        + Mul<Self::Scalar, Output = Self::Element>
+        + AddAssign
+        + Sum
+        + ConstantTimeEq
+        + Serialize
+        + DeserializeOwned
+        + Send
+        + Sync;
+
+    /// The canonical encoding of a scalar.
+    type ScalarBytes: Encoding;
+
+    /// The canonical encoding of a group element, and of a FROST
+    /// verifying key.
+    type ElementBytes: Encoding;
+
+    fn scalar_from_u64(x: u64) -> Self::Scalar;
+
+    fn random_scalar<R: CryptoRng + RngCore>(rng: &mut R) -> Self::Scalar;
+
+    /// The multiplicative inverse of a non-zero scalar.
+    fn invert(scalar: &Self::Scalar) -> Self::Scalar;
+
+    /// Hashes the concatenation of `inputs` to a scalar.
+    fn hash_to_scalar(inputs: &[&[u8]]) -> Self::Scalar;
+
+    fn scalar_to_bytes(scalar: &Self::Scalar) -> Self::ScalarBytes;
+
+    /// Interprets `bytes` as an integer and reduces it modulo the group order.
+    fn scalar_from_bytes_mod_order(bytes: Self::ScalarBytes) -> Self::Scalar;
+
+    fn identity() -> Self::Element;
+
+    /// Computes `scalar * G`, where `G` is the group's generator.
+    fn base_mul(scalar: &Self::Scalar) -> Self::Element;
+
+    /// Hashes `input` to a group element whose discrete log is unknown.
+    fn hash_to_element(input: &[u8]) -> Self::Element;
+
+    /// Computes `Σ scalars[i] * elements[i]` in variable time.
+    ///
+    /// MUST only be used when the scalars are public.
+    fn vartime_multiscalar_mul(
+        scalars: &[Self::Scalar],
+        elements: &[Self::Element],
+    ) -> Self::Element;
+
+    fn element_to_bytes(element: &Self::Element) -> Self::ElementBytes;
+
+    /// Decodes an element, checking that it lies in the prime-order subgroup.
+    fn element_from_bytes(bytes: Self::ElementBytes) -> Result<Self::Element>;
+
+    /// Whether `element` lies in the prime-order subgroup.
+    ///
+    /// Elements received from other parties MUST be checked with this
+    /// before being multiplied by a secret scalar.
+    fn is_torsion_free(element: &Self::Element) -> bool;
+
+    fn signing_share(
+        scalar: &Self::Scalar,
+    ) -> Result<SigningShare<Self::Frost>>;
+
+    fn signing_share_scalar(share: &SigningShare<Self::Frost>) -> Self::Scalar;
+
+    fn verifying_share(
+        element: &Self::Element,
+    ) -> Result<VerifyingShare<Self::Frost>>;
+
+    fn verifying_share_element(
+        share: &VerifyingShare<Self::Frost>,
+    ) -> Result<Self::Element>;
+
+    fn signing_key(scalar: &Self::Scalar) -> Result<SigningKey<Self::Frost>>;
+
+    fn verifying_key_to_bytes(
+        key: &VerifyingKey<Self::Frost>,
+    ) -> Self::ElementBytes;
+
+    fn verifying_key_from_bytes(
+        bytes: Self::ElementBytes,
+    ) -> Result<VerifyingKey<Self::Frost>>;
+
+    fn verifying_key(
+        element: &Self::Element,
+    ) -> Result<VerifyingKey<Self::Frost>> {
+        Self::verifying_key_from_bytes(Self::element_to_bytes(element))
+    }
+
+    fn verifying_key_element(
+        key: &VerifyingKey<Self::Frost>,
+    ) -> Result<Self::Element> {
+        Self::element_from_bytes(Self::verifying_key_to_bytes(key))
+    }
+}
+
+/// FROST(ristretto255, SHA-512), with ElGamal over ristretto255.
+///
+/// This is the default ciphersuite.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ristretto255;
+
+/// FROST(Ed25519, SHA-512), with ElGamal over the prime-order subgroup of
+/// Curve25519.
+///
+/// Useful when signatures on tokens need to be checked by existing Ed25519
+/// verifiers. Received points are checked for small-order components, which
+/// ristretto255 makes unnecessary.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ed25519;
+
+impl Ciphersuite for Ristretto255 {
+    type Frost = frost_ristretto255::Ristretto255Sha512;
+    type Scalar = Scalar;
+    type Element = RistrettoPoint;
+    type ScalarBytes = [u8; 32];
+    type ElementBytes = [u8; 32];
+
+    fn scalar_from_u64(x: u64) -> Scalar {
+        Scalar::from(x)
+    }
+
+    fn random_scalar<R: CryptoRng + RngCore>(rng: &mut R) -> Scalar {
+        Scalar::random(rng)
+    }
+
+    fn invert(scalar: &Scalar) -> Scalar {
+        scalar.invert()
+    }
+
+    fn hash_to_scalar(inputs: &[&[u8]]) -> Scalar {
+        hash_to_curve25519_scalar(inputs)
+    }
+
+    fn scalar_to_bytes(scalar: &Scalar) -> [u8; 32] {
+        scalar.to_bytes()
+    }
+
+    fn scalar_from_bytes_mod_order(bytes: [u8; 32]) -> Scalar {
+        Scalar::from_bytes_mod_order(bytes)
+    }
+
+    fn identity() -> RistrettoPoint {
+        RistrettoPoint::identity()
+    }
+
+    fn base_mul(scalar: &Scalar) -> RistrettoPoint {
+        scalar * &RISTRETTO_BASEPOINT_TABLE
+    }
+
+    fn hash_to_element(input: &[u8]) -> RistrettoPoint {
+        RistrettoPoint::hash_from_bytes::<Sha512>(input)
+    }
+
+    fn vartime_multiscalar_mul(
+        scalars: &[Scalar],
+        elements: &[RistrettoPoint],
+    ) -> RistrettoPoint {
+        RistrettoPoint::vartime_multiscalar_mul(scalars, elements)
+    }
+
+    fn element_to_bytes(element: &RistrettoPoint) -> [u8; 32] {
+        element.compress().to_bytes()
+    }
+
+    fn element_from_bytes(bytes: [u8; 32]) -> Result<RistrettoPoint> {
+        CompressedRistretto(bytes)
+            .decompress()
+            .ok_or_else(|| "Invalid ristretto255 point.".into())
+    }
+
+    fn is_torsion_free(_element: &RistrettoPoint) -> bool {
+        // ristretto255 is a prime-order group
+        true
+    }
+
+    fn signing_share(scalar: &Scalar) -> Result<SigningShare<Self::Frost>> {
+        Ok(frost_ristretto255::keys::SigningShare::from_bytes(
+            scalar.to_bytes(),
+        )?)
+    }
+
+    fn signing_share_scalar(share: &SigningShare<Self::Frost>) -> Scalar {
+        Scalar::from_bytes_mod_order(share.to_bytes())
+    }
+
+    fn verifying_share(
+        element: &RistrettoPoint,
+    ) -> Result<VerifyingShare<Self::Frost>> {
+        Ok(frost_ristretto255::keys::VerifyingShare::from_bytes(
+            element.compress().to_bytes(),
+        )?)
+    }
+
+    fn verifying_share_element(
+        share: &VerifyingShare<Self::Frost>,
+    ) -> Result<RistrettoPoint> {
+        Self::element_from_bytes(share.to_bytes())
+    }
+
+    fn signing_key(scalar: &Scalar) -> Result<SigningKey<Self::Frost>> {
+        Ok(frost_ristretto255::SigningKey::from_bytes(
+            scalar.to_bytes(),
+        )?)
+    }
+
+    fn verifying_key_to_bytes(key: &VerifyingKey<Self::Frost>) -> [u8; 32] {
+        key.to_bytes()
+    }
+
+    fn verifying_key_from_bytes(
+        bytes: [u8; 32],
+    ) -> Result<VerifyingKey<Self::Frost>> {
+        Ok(frost_ristretto255::VerifyingKey::from_bytes(bytes)?)
+    }
+}
+
+impl Ciphersuite for Ed25519 {
+    type Frost = frost_ed25519::Ed25519Sha512;
+    type Scalar = Scalar;
+    type Element = EdwardsPoint;
+    type ScalarBytes = [u8; 32];
+    type ElementBytes = [u8; 32];
+
+    fn scalar_from_u64(x: u64) -> Scalar {
+        Scalar::from(x)
+    }
+
+    fn random_scalar<R: CryptoRng + RngCore>(rng: &mut R) -> Scalar {
+        Scalar::random(rng)
+    }
+
+    fn invert(scalar: &Scalar) -> Scalar {
+        scalar.invert()
+    }
+
+    fn hash_to_scalar(inputs: &[&[u8]]) -> Scalar {
+        hash_to_curve25519_scalar(inputs)
+    }
+
+    fn scalar_to_bytes(scalar: &Scalar) -> [u8; 32] {
+        scalar.to_bytes()
+    }
+
+    fn scalar_from_bytes_mod_order(bytes: [u8; 32]) -> Scalar {
+        Scalar::from_bytes_mod_order(bytes)
+    }
+
+    fn identity() -> EdwardsPoint {
+        EdwardsPoint::identity()
+    }
+
+    fn base_mul(scalar: &Scalar) -> EdwardsPoint {
+        scalar * &ED25519_BASEPOINT_TABLE
+    }
+
+    fn hash_to_element(input: &[u8]) -> EdwardsPoint {
+        // the result is multiplied by the cofactor, so is torsion-free
+        EdwardsPoint::hash_from_bytes::<Sha512>(input)
+    }
+
+    fn vartime_multiscalar_mul(
+        scalars: &[Scalar],
+        elements: &[EdwardsPoint],
+    ) -> EdwardsPoint {
+        EdwardsPoint::vartime_multiscalar_mul(scalars, elements)
+    }
+
+    fn element_to_bytes(element: &EdwardsPoint) -> [u8; 32] {
+        element.compress().to_bytes()
+    }
+
+    fn element_from_bytes(bytes: [u8; 32]) -> Result<EdwardsPoint> {
+        CompressedEdwardsY(bytes)
+            .decompress()
+            .filter(Self::is_torsion_free)
+            .ok_or_else(|| "Invalid Ed25519 point.".into())
+    }
+
+    fn is_torsion_free(element: &EdwardsPoint) -> bool {
+        element.is_torsion_free()
+    }
+
+    fn signing_share(scalar: &Scalar) -> Result<SigningShare<Self::Frost>> {
+        Ok(frost_ed25519::keys::SigningShare::from_bytes(
+            scalar.to_bytes(),
+        )?)
+    }
+
+    fn signing_share_scalar(share: &SigningShare<Self::Frost>) -> Scalar {
+        Scalar::from_bytes_mod_order(share.to_bytes())
+    }
+
+    fn verifying_share(
+        element: &EdwardsPoint,
+    ) -> Result<VerifyingShare<Self::Frost>> {
+        Ok(frost_ed25519::keys::VerifyingShare::from_bytes(
+            element.compress().to_bytes(),
+        )?)
+    }
+
+    fn verifying_share_element(
+        share: &VerifyingShare<Self::Frost>,
+    ) -> Result<EdwardsPoint> {
+        Self::element_from_bytes(share.to_bytes())
+    }
+
+    fn signing_key(scalar: &Scalar) -> Result<SigningKey<Self::Frost>> {
+        Ok(frost_ed25519::SigningKey::from_bytes(scalar.to_bytes())?)
+    }
+
+    fn verifying_key_to_bytes(key: &VerifyingKey<Self::Frost>) -> [u8; 32] {
+        key.to_bytes()
+    }
+
+    fn verifying_key_from_bytes(
+        bytes: [u8; 32],
+    ) -> Result<VerifyingKey<Self::Frost>> {
+        Ok(frost_ed25519::VerifyingKey::from_bytes(bytes)?)
+    }
+}
+
+/// Hashes the concatenation of `inputs` with SHA-512 and reduces the
+/// result modulo the order of Curve25519's prime-order subgroup.
+fn hash_to_curve25519_scalar(inputs: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha512::new();
+    for input in inputs {
+        hasher.update(input);
+    }
+
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ciphersuite, Ed25519, Encoding, Ristretto255};
+    use curve25519_dalek::constants::EIGHT_TORSION;
+
+    fn test_encodings<C: Ciphersuite>() {
+        let mut rng = rand::thread_rng();
+        let scalar = C::random_scalar(&mut rng);
+        let element = C::base_mul(&scalar);
+
+        assert_eq!(
+            C::element_from_bytes(C::element_to_bytes(&element)).ok(),
+            Some(element)
+        );
+        assert_eq!(
+            C::scalar_from_bytes_mod_order(C::scalar_to_bytes(&scalar)),
+            scalar
+        );
+        assert_eq!(
+            C::verifying_key_element(&C::verifying_key(&element).unwrap()).ok(),
+            Some(element)
+        );
+        assert!(C::is_torsion_free(&C::hash_to_element(b"test")));
+
+        let other = C::random_scalar(&mut rng);
+        assert_eq!(
+            C::vartime_multiscalar_mul(
+                &[scalar, other],
+                &[element, C::hash_to_element(b"test")]
+            ),
+            scalar * element + other * C::hash_to_element(b"test")
+        );
+    }
+
+    #[test]
+    fn test_encoding_from_slice() {
+        assert_eq!(<[u8; 32]>::from_slice(&[7; 32]).ok(), Some([7; 32]));
+        assert!(<[u8; 32]>::from_slice(&[7; 31]).is_err());
+        assert!(<[u8; 32]>::from_slice(&[7; 33]).is_err());
+    }
+
+    #[test]
+    fn test_ristretto255_encodings() {
+        test_encodings::<Ristretto255>()
+    }
+
+    #[test]
+    fn test_ed25519_encodings() {
+        test_encodings::<Ed25519>()
+    }
+
+    #[test]
+    fn test_ed25519_rejects_torsion() {
+        let point =
+            Ed25519::base_mul(&Ed25519::scalar_from_u64(5)) + EIGHT_TORSION[1];
+        assert!(!Ed25519::is_torsion_free(&point));
+        assert!(
+            Ed25519::element_from_bytes(point.compress().to_bytes()).is_err()
+        );
+    }
+}
@@ -1,72 +1,389 @@
 /// Setup round of communication
 pub mod setup {
 
-    use crate::{elgamal, Batch};
-    use frost_ristretto255 as frost;
+    use crate::{
+        ciphersuite::Ciphersuite,
+        elgamal::{self, IdEncryption},
+        identity::Signed,
+        shamir::Commitments,
+        token::TimestampPolicy,
+        Batch,
+    };
+    use frost_core::frost;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Request<C: Ciphersuite> {
+        /// Only sent to moderators on the signing committee.
+        pub frost_secret_share: Option<frost::keys::SecretShare<C::Frost>>,
+
+        /// Only sent to moderators on the decryption committee.
+        pub elgamal_secret_share: Option<elgamal::KeyShare<C>>,
+
+        /// The key that tokens are signed with, which every moderator needs
+        /// to check tokens even if it holds no share of it.
+        pub(crate) group_public_key: C::ElementBytes,
+
+        /// Feldman commitments to the polynomial used to
+        /// deal the ElGamal key shares.
+        pub(crate) elgamal_commitments: Commitments<C>,
+        pub(crate) batch_size: usize,
+        pub(crate) timestamp_policy: TimestampPolicy,
+        pub(crate) id_encryption: IdEncryption,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Response<C: Ciphersuite> {
+        /// Empty if the moderator isn't on the signing committee.
+        pub nonce_commitments:
+            Batch<frost::round1::SigningCommitments<C::Frost>>,
+
+        /// The moderator's signature on a digest of the public values it was
+        /// sent, to be checked by the rest of its committee.
+        pub(crate) confirmation: Signed<C, [u8; 32]>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct ConfirmRequest<C: Ciphersuite> {
+        /// Every committee member's confirmation, including the recipient's own.
+        pub(crate) confirmations: Vec<Signed<C, [u8; 32]>>,
+    }
+}
+
+/// Distributed key generation, as an alternative to the setup round.
+///
+/// The coordinator relays every message between the moderators.
+pub mod keygen {
+    use crate::{
+        ciphersuite::Ciphersuite,
+        dkg::{EncryptedShares, Round1Package, Thresholds},
+        elgamal::{self, IdEncryption},
+        identity::Signed,
+        token::TimestampPolicy,
+        Batch,
+    };
+    use frost_core::frost;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    pub(crate) struct Round1Request {
+        /// The (1-indexed) identifier of the receiving moderator.
+        pub(crate) identifier: u16,
+        pub(crate) n_moderators: usize,
+
+        /// The keys that the moderator's committee holds shares of.
+        pub(crate) thresholds: Thresholds,
+        pub(crate) batch_size: usize,
+        pub(crate) timestamp_policy: TimestampPolicy,
+        pub(crate) id_encryption: IdEncryption,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round1Response<C: Ciphersuite> {
+        /// The package, signed with the moderator's identity key.
+        pub(crate) package: Signed<C, Round1Package<C>>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round2Request<C: Ciphersuite> {
+        /// Every moderator's signed round 1 package, including the
+        /// recipient's own.
+        pub(crate) packages: Vec<Signed<C, Round1Package<C>>>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(crate) struct Round2Response {
+        pub(crate) encrypted_shares: Vec<EncryptedShares>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round3Request<C: Ciphersuite> {
+        /// The shares addressed to the receiving moderator.
+        pub(crate) encrypted_shares: Vec<EncryptedShares>,
+
+        // the group keys as computed by the coordinator from the round 1
+        // packages. The moderator checks the keys its committee holds, and
+        // takes the coordinator's word for the other committee's key.
+        pub(crate) group_public_key: C::Element,
+        pub(crate) group_public_elgamal_key: elgamal::PublicKey<C>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round3Response<C: Ciphersuite> {
+        /// Empty if the moderator isn't on the signing committee.
+        pub(crate) nonce_commitments:
+            Batch<frost::round1::SigningCommitments<C::Frost>>,
+    }
+}
+
+/// Proactive refresh of the moderators' ElGamal key shares.
+///
+/// As with key generation, the coordinator relays every message.
+pub mod refresh {
+    use crate::{
+        ciphersuite::Ciphersuite,
+        elgamal,
+        identity::Signed,
+        refresh::{EncryptedRefreshShare, RefreshPackage},
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    pub(crate) struct Round1Request {
+        pub(crate) n_moderators: usize,
+        pub(crate) decryption_threshold: usize,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round1Response<C: Ciphersuite> {
+        /// The package, signed with the moderator's identity key.
+        pub(crate) package: Signed<C, RefreshPackage<C>>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round2Request<C: Ciphersuite> {
+        pub(crate) packages: Vec<Signed<C, RefreshPackage<C>>>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(crate) struct Round2Response {
+        pub(crate) encrypted_shares: Vec<EncryptedRefreshShare>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub(crate) struct Round3Request {
+        /// The shares addressed to the receiving moderator.
+        pub(crate) encrypted_shares: Vec<EncryptedRefreshShare>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round3Response<C: Ciphersuite> {
+        /// The verification key of the moderator's refreshed share.
+        pub(crate) verification_key: elgamal::VerificationKey<C>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct CommitRequest<C: Ciphersuite> {
+        /// The verification key that the moderator's refreshed share must
+        /// match, as computed by the coordinator from the commitments.
+        pub(crate) verification_key: elgamal::VerificationKey<C>,
+    }
+}
+
+/// Resharing the moderators' keys to a new committee.
+///
+/// Rounds 1 and 3 are sent to the new moderators, and round 2 to the old ones.
+pub mod reshare {
+    use crate::{
+        ciphersuite::Ciphersuite,
+        elgamal::IdEncryption,
+        identity::Signed,
+        reshare::{Dealing, ReshareContext},
+        token::TimestampPolicy,
+        Batch,
+    };
+    use frost_core::frost;
     use serde::{Deserialize, Serialize};
 
     #[derive(Deserialize, Serialize)]
-    pub(crate) struct Request {
-        pub frost_secret_share: frost::keys::SecretShare,
-        pub elgamal_secret_share: elgamal::KeyShare,
+    pub(crate) struct Round1Request {
+        /// The (1-indexed) identifier of the receiving moderator in the new committee.
+        pub(crate) identifier: u16,
         pub(crate) batch_size: usize,
+        pub(crate) timestamp_policy: TimestampPolicy,
+        pub(crate) id_encryption: IdEncryption,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round1Response<C: Ciphersuite> {
+        /// Diffie-Hellman key that the moderator's shares are encrypted to,
+        /// signed with the moderator's identity key.
+        pub(crate) encryption_key: Signed<C, C::Element>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round2Request<C: Ciphersuite> {
+        /// The new moderators' signed encryption keys.
+        pub(crate) recipients: Vec<Signed<C, C::Element>>,
+
+        /// The old moderators taking part, the keys being reshared, and the
+        /// group keys.
+        pub(crate) context: ReshareContext<C>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round2Response<C: Ciphersuite> {
+        pub(crate) dealing: Signed<C, Dealing<C>>,
+
+        /// The moderator's signature on the context it was sent, to be
+        /// checked by the new moderators.
+        pub(crate) confirmation: Signed<C, ReshareContext<C>>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Round3Request<C: Ciphersuite> {
+        pub(crate) dealings: Vec<Signed<C, Dealing<C>>>,
+
+        /// Every dealer's confirmation of `context`.
+        pub(crate) confirmations: Vec<Signed<C, ReshareContext<C>>>,
+        pub(crate) context: ReshareContext<C>,
     }
 
     #[derive(Deserialize, Serialize)]
-    pub(crate) struct Response {
-        pub nonce_commitments: Batch<frost::round1::SigningCommitments>,
+    #[serde(bound = "")]
+    pub(crate) struct Round3Response<C: Ciphersuite> {
+        /// Empty if the moderator isn't on the signing committee.
+        pub(crate) nonce_commitments:
+            Batch<frost::round1::SigningCommitments<C::Frost>>,
+    }
+}
+
+/// Fresh nonce commitments from a moderator that the coordinator left out of
+/// signing, e.g., because it failed to respond to a signing request.
+pub mod commitments {
+    use crate::{ciphersuite::Ciphersuite, Batch};
+    use frost_core::frost;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub(crate) struct Response<C: Ciphersuite> {
+        pub(crate) nonce_commitments:
+            Batch<frost::round1::SigningCommitments<C::Frost>>,
     }
 }
 
 // Signing round of communication
 pub mod signing {
 
-    use crate::{Batch, UserId};
-    use curve25519_dalek::scalar::Scalar;
-    use frost_ristretto255::{
+    use crate::{
+        ciphersuite::Ciphersuite,
+        elgamal::{CommittedIdProof, IdCommitment},
+        payload::Payload,
+        Batch, UserId,
+    };
+    use frost_core::frost::{
         round1::SigningCommitments, round2::SignatureShare, SigningPackage,
     };
     use serde::{Deserialize, Serialize};
+    use zeroize::Zeroize;
 
     #[derive(Deserialize, Serialize, Clone)]
-    pub struct Request {
-        pub(crate) signing_requests: Batch<SigningRequest>,
+    #[serde(bound = "")]
+    pub struct Request<C: Ciphersuite> {
+        pub(crate) signing_requests: Batch<SigningRequest<C>>,
     }
 
     #[derive(Deserialize, Serialize)]
-    pub struct Response {
-        pub(crate) signature_shares: Batch<SignatureShare>,
+    #[serde(bound = "")]
+    pub struct Response<C: Ciphersuite> {
+        pub(crate) signature_shares: Batch<SignatureShare<C::Frost>>,
 
-        pub(crate) new_nonce_commitments: Batch<SigningCommitments>,
+        pub(crate) new_nonce_commitments: Batch<SigningCommitments<C::Frost>>,
     }
 
     #[derive(Deserialize, Serialize, Clone)]
-    pub struct SigningRequest {
-        pub(crate) signing_package: SigningPackage,
+    #[serde(bound = "")]
+    pub struct SigningRequest<C: Ciphersuite> {
+        pub(crate) signing_package: SigningPackage<C::Frost>,
 
         /// Used to verify the well-formedness of the signing package.
-        pub(crate) elgamal_randomness: Scalar,
+        pub(crate) id_evidence: IdEvidence<C>,
+
+        /// The payload to be encrypted, if any. Its `account_id` is the
+        /// user's id, so payloads are only sent along with
+        /// [`IdEvidence::Opening`].
+        pub(crate) payload: Option<Payload>,
 
-        /// The user ID to be encrypted.
-        pub(crate) user_id: UserId,
+        /// Used to verify the encryption of `payload`.
+        pub(crate) payload_randomness: C::Scalar,
+    }
+
+    /// Evidence that the token's `x_1` is a well-formed encryption of the
+    /// id of the user that the coordinator authenticated.
+    #[derive(Deserialize, Serialize, Clone)]
+    #[serde(bound = "")]
+    pub(crate) enum IdEvidence<C: Ciphersuite> {
+        /// The id and encryption randomness, which the moderator uses to
+        /// re-encrypt the id and compare. Reveals the id to every moderator.
+        Opening {
+            user_id: UserId,
+            elgamal_randomness: C::Scalar,
+        },
+
+        /// A commitment to the id that the coordinator authenticated, and a
+        /// proof that `x_1` encrypts the committed id. The moderator never
+        /// sees the id.
+        Proof {
+            id_commitment: IdCommitment<C>,
+            proof: CommittedIdProof<C>,
+        },
+    }
+
+    impl<C: Ciphersuite> Drop for SigningRequest<C> {
+        fn drop(&mut self) {
+            if let IdEvidence::Opening {
+                elgamal_randomness, ..
+            } = &mut self.id_evidence
+            {
+                elgamal_randomness.zeroize();
+            }
+            self.payload_randomness.zeroize();
+        }
+    }
+}
+
+/// Registration of the platform's stamping key with the moderators
+pub mod platform {
+    use crate::{ciphersuite::Ciphersuite, PlatformPublicKey};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(bound = "")]
+    pub struct Request<C: Ciphersuite> {
+        pub(crate) platform_public_key: PlatformPublicKey<C>,
     }
 }
 
 pub mod decryption {
     use serde::{Deserialize, Serialize};
 
-    use crate::{elgamal::DecryptionShare, token::SignedToken};
+    use crate::{
+        ciphersuite::Ciphersuite, elgamal::DecryptionShare, message::Envelope,
+    };
 
     #[derive(Deserialize, Serialize)]
-    pub struct Request {
+    #[serde(bound = "")]
+    pub struct Request<C: Ciphersuite> {
+        /// The plaintext of the reported message.
         pub message: Vec<u8>,
-        pub token: SignedToken,
+
+        /// The metadata that was sent with the message, binding it to the sender's token.
+        pub envelope: Envelope<C>,
     }
 
     #[derive(Deserialize, Serialize)]
-    pub struct Response {
-        pub(crate) decryption_share: DecryptionShare,
-        // TODO is there more that we need here?
+    #[serde(bound = "")]
+    pub struct Response<C: Ciphersuite> {
+        pub(crate) decryption_share: DecryptionShare<C>,
+
+        /// A decryption share of the token's payload, if it has one.
+        pub(crate) payload_share: Option<DecryptionShare<C>>,
     }
 }
 
@@ -75,13 +392,16 @@ mod tests {
 
     use super::{
         setup,
-        signing::{self, SigningRequest},
+        signing::{self, IdEvidence, SigningRequest},
+    };
+    use crate::{
+        ciphersuite::{Ciphersuite, Ristretto255},
+        elgamal::{generate_private_key_shares, IdEncryption},
+        token::TimestampPolicy,
+        Result, UserId,
     };
-    use crate::{elgamal::generate_private_key_shares, Result, UserId};
-    use curve25519_dalek::scalar::Scalar;
     use frost::{Identifier, SigningPackage};
-    use frost_core::frost::keys::SigningShare;
-    use frost_ristretto255 as frost;
+    use frost_core::frost;
     use rand::Rng;
 
     #[test]
@@ -92,34 +412,43 @@ mod tests {
         let signing_threshold = 6;
         let decryption_threshold = 4;
 
-        let frost_secret_share = {
-            let (shares, _) = frost::keys::keygen_with_dealer(
-                n_mods,
-                signing_threshold,
-                &mut rng,
-            )?;
-
-            shares[0].to_owned()
+        let (frost_secret_share, group_public_key) = {
+            let (shares, public_key_package) =
+                frost::keys::keygen_with_dealer::<
+                    <Ristretto255 as Ciphersuite>::Frost,
+                    _,
+                >(n_mods, signing_threshold, &mut rng)?;
+
+            (
+                shares[0].to_owned(),
+                Ristretto255::verifying_key_to_bytes(
+                    &public_key_package.group_public,
+                ),
+            )
         };
 
-        let elgamal_secret_share = {
-            let (_, shares) = generate_private_key_shares(
+        let (_, elgamal_shares, elgamal_commitments) =
+            generate_private_key_shares::<Ristretto255, _>(
                 &mut rng,
                 n_mods as usize,
                 decryption_threshold,
             );
-            shares[0].to_owned()
-        };
+        let elgamal_secret_share = elgamal_shares[0].to_owned();
 
         let request = setup::Request {
             batch_size: 10,
-            frost_secret_share,
-            elgamal_secret_share,
+            frost_secret_share: Some(frost_secret_share),
+            elgamal_secret_share: Some(elgamal_secret_share),
+            group_public_key,
+            elgamal_commitments,
+            timestamp_policy: TimestampPolicy::default(),
+            id_encryption: IdEncryption::default(),
         };
 
         let bytes = bincode::serialize(&request)?;
 
-        let should_be_request: setup::Request = bincode::deserialize(&bytes)?;
+        let should_be_request: setup::Request<Ristretto255> =
+            bincode::deserialize(&bytes)?;
 
         assert_eq!(
             request.elgamal_secret_share,
@@ -128,11 +457,23 @@ mod tests {
         );
 
         assert_eq!(
-            request.frost_secret_share.value,
-            should_be_request.frost_secret_share.value,
+            request.elgamal_commitments, should_be_request.elgamal_commitments,
+            "Elgamal commitments are not equal to the original."
+        );
+
+        assert_eq!(
+            request.frost_secret_share.map(|share| share.value),
+            should_be_request
+                .frost_secret_share
+                .map(|share| share.value),
             "Frost secret is not equal to the original."
         );
 
+        assert_eq!(
+            request.group_public_key, should_be_request.group_public_key,
+            "Group public key is not equal to the original."
+        );
+
         Ok(())
     }
 
@@ -149,8 +490,8 @@ mod tests {
                 .map(|i| {
                     let participant_identifier =
                         Identifier::try_from((i + 1) as u16).unwrap();
-                    let secret = SigningShare::from_bytes(
-                        Scalar::random(&mut rng).to_bytes(),
+                    let secret = Ristretto255::signing_share(
+                        &Ristretto255::random_scalar(&mut rng),
                     )
                     .unwrap();
                     let (_nonces, commitment) = frost::round1::commit(
@@ -164,27 +505,42 @@ mod tests {
 
             let message = (0..128).map(|_| 0).collect(); // random message
 
-            signing_requests.push(SigningRequest {
-                elgamal_randomness: Scalar::random(&mut rng),
+            signing_requests.push(SigningRequest::<Ristretto255> {
                 signing_package: SigningPackage::new(
                     signing_commitments,
                     message,
                 ),
-                user_id: UserId(rng.gen()),
+                id_evidence: IdEvidence::Opening {
+                    user_id: UserId(rng.gen()),
+                    elgamal_randomness: Ristretto255::random_scalar(&mut rng),
+                },
+                payload: None,
+                payload_randomness: Ristretto255::random_scalar(&mut rng),
             })
         }
 
         let request = signing::Request { signing_requests };
 
-        let should_be_request: signing::Request = {
+        let should_be_request: signing::Request<Ristretto255> = {
             let bytes = bincode::serialize(&request)?;
             bincode::deserialize(&bytes)?
         };
 
-        assert_eq!(
-            request.signing_requests[0].elgamal_randomness,
-            should_be_request.signing_requests[0].elgamal_randomness
-        );
+        match (
+            &request.signing_requests[0].id_evidence,
+            &should_be_request.signing_requests[0].id_evidence,
+        ) {
+            (
+                IdEvidence::Opening {
+                    elgamal_randomness, ..
+                },
+                IdEvidence::Opening {
+                    elgamal_randomness: should_be_randomness,
+                    ..
+                },
+            ) => assert_eq!(elgamal_randomness, should_be_randomness),
+            _ => panic!("Id evidence changed during serialization."),
+        }
 
         Ok(())
     }
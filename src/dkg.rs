@@ -0,0 +1,1104 @@
+//! Distributed key generation for the moderators' signing and decryption keys.
+//!
+//! Every moderator deals a random polynomial for each key, publishes Feldman
+//! commitments to its coefficients (with a proof of knowledge of the constant
+//! term), and sends each other moderator a share of it. Each moderator's key
+//! share is the sum of the shares it receives, and the group key is the sum of
+//! the committed constant terms, so no single party ever learns either secret.
+//!
+//! The coordinator relays every message, so shares are encrypted to their
+//! recipients with a Diffie-Hellman key and the coordinator only ever sees
+//! public values. Round 1 packages are signed with the moderators' identity
+//! keys (see [`crate::identity`]), so the coordinator can't substitute its own
+//! encryption keys or commitments for a moderator's.
+
+use frost_core::frost;
+use hkdf::Hkdf;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use zeroize::Zeroize;
+
+use crate::{
+    ciphersuite::{Ciphersuite, Encoding},
+    elgamal,
+    identity::{ModeratorIdentity, Signed},
+    shamir::{Commitments, Polynomial},
+    ModeratorId, Result,
+};
+
+/// Label for signatures on round 1 packages.
+const ROUND1_LABEL: &[u8] = b"keygen/round1";
+
+/// A Schnorr proof of knowledge of the discrete log of a public key.
+///
+/// Prevents a dealer from choosing its commitments as a function of everyone
+/// else's (a rogue-key attack). Also used as a signature by moderator
+/// identity keys, see [`crate::identity`].
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub(crate) struct ProofOfKnowledge<C: Ciphersuite> {
+    commitment: C::Element,
+    response: C::Scalar,
+}
+
+impl<C: Ciphersuite> ProofOfKnowledge<C> {
+    pub(crate) fn new<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        secret: &C::Scalar,
+        context: &[u8],
+    ) -> Self {
+        let public = C::base_mul(secret);
+        let nonce = C::random_scalar(rng);
+        let commitment = C::base_mul(&nonce);
+
+        let challenge = Self::challenge(&public, &commitment, context);
+
+        Self {
+            commitment,
+            response: nonce + challenge * *secret,
+        }
+    }
+
+    pub(crate) fn verify(&self, public: &C::Element, context: &[u8]) -> bool {
+        let challenge = Self::challenge(public, &self.commitment, context);
+
+        C::base_mul(&self.response) == self.commitment + challenge * *public
+    }
+
+    fn challenge(
+        public: &C::Element,
+        commitment: &C::Element,
+        context: &[u8],
+    ) -> C::Scalar {
+        C::hash_to_scalar(&[
+            b"cerberus-dkg-proof-of-knowledge",
+            context,
+            C::element_to_bytes(public).as_ref(),
+            C::element_to_bytes(commitment).as_ref(),
+        ])
+    }
+}
+
+/// The thresholds of the group keys that a committee holds shares of.
+///
+/// A committee that only signs tokens or only decrypts them holds no share
+/// of the other key, and so has no threshold for it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Thresholds {
+    pub(crate) signing: Option<usize>,
+    pub(crate) decryption: Option<usize>,
+}
+
+impl Thresholds {
+    /// The thresholds of a committee holding shares of both keys.
+    pub(crate) fn both(signing: usize, decryption: usize) -> Self {
+        Self {
+            signing: Some(signing),
+            decryption: Some(decryption),
+        }
+    }
+}
+
+/// Feldman commitments to a dealt polynomial, with a proof of knowledge of
+/// its constant term.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub(crate) struct CommittedPolynomial<C: Ciphersuite> {
+    pub(crate) commitments: Commitments<C>,
+    proof: ProofOfKnowledge<C>,
+}
+
+impl<C: Ciphersuite> CommittedPolynomial<C> {
+    fn new<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        polynomial: &Polynomial<C>,
+        label: &[u8],
+        identifier: u16,
+    ) -> Self {
+        Self {
+            commitments: polynomial.commitments(),
+            proof: ProofOfKnowledge::new(
+                rng,
+                &polynomial.0[0],
+                &proof_context(label, identifier),
+            ),
+        }
+    }
+
+    fn verify(&self, label: &[u8], identifier: u16) -> bool {
+        self.proof.verify(
+            &self.commitments.public_key(),
+            &proof_context(label, identifier),
+        )
+    }
+}
+
+/// The public values broadcast by a moderator in the first round of key generation.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub(crate) struct Round1Package<C: Ciphersuite> {
+    pub(crate) identifier: u16,
+
+    /// Only present if the committee holds a share of the signing key.
+    pub(crate) signing: Option<CommittedPolynomial<C>>,
+
+    /// Only present if the committee holds a share of the decryption key.
+    pub(crate) decryption: Option<CommittedPolynomial<C>>,
+
+    /// Diffie-Hellman key that shares are encrypted to.
+    encryption_key: C::Element,
+}
+
+impl<C: Ciphersuite> Round1Package<C> {
+    fn verify(&self, thresholds: Thresholds) -> Result<()> {
+        if self.thresholds() != thresholds {
+            return Err(format!(
+                "Moderator {} committed to a polynomial of the wrong degree.",
+                self.identifier
+            )
+            .into());
+        }
+
+        if !C::is_torsion_free(&self.encryption_key) {
+            return Err(format!(
+                "Moderator {} sent an invalid encryption key.",
+                self.identifier
+            )
+            .into());
+        }
+
+        let signing_valid = self.signing.as_ref().map_or(true, |signing| {
+            signing.verify(b"signing", self.identifier)
+        });
+        let decryption_valid =
+            self.decryption.as_ref().map_or(true, |decryption| {
+                decryption.verify(b"decryption", self.identifier)
+            });
+
+        match signing_valid && decryption_valid {
+            true => Ok(()),
+            false => Err(format!(
+                "Moderator {} sent an invalid proof of knowledge.",
+                self.identifier
+            )
+            .into()),
+        }
+    }
+
+    /// The thresholds of the polynomials committed to.
+    fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            signing: self
+                .signing
+                .as_ref()
+                .map(|signing| signing.commitments.threshold()),
+            decryption: self
+                .decryption
+                .as_ref()
+                .map(|decryption| decryption.commitments.threshold()),
+        }
+    }
+
+    fn commitments(
+        &self,
+    ) -> (Option<&Commitments<C>>, Option<&Commitments<C>>) {
+        (
+            self.signing.as_ref().map(|signing| &signing.commitments),
+            self.decryption
+                .as_ref()
+                .map(|decryption| &decryption.commitments),
+        )
+    }
+}
+
+/// A moderator's shares of the committee's secrets, encrypted to their recipient.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct EncryptedShares {
+    pub(crate) sender: u16,
+    pub(crate) recipient: u16,
+    signing_share: Option<Vec<u8>>,
+    decryption_share: Option<Vec<u8>>,
+}
+
+impl EncryptedShares {
+    /// Encrypts `sender`'s shares for `recipient` using their Diffie-Hellman keys.
+    pub(crate) fn encrypt<C: Ciphersuite>(
+        encryption_secret: &C::Scalar,
+        sender: u16,
+        recipient: u16,
+        recipient_encryption_key: &C::Element,
+        signing_share: Option<C::Scalar>,
+        decryption_share: Option<C::Scalar>,
+    ) -> Self {
+        let pad = |label: &[u8]| {
+            share_pad::<C>(
+                encryption_secret,
+                sender,
+                recipient,
+                recipient_encryption_key,
+                label,
+            )
+        };
+
+        Self {
+            sender,
+            recipient,
+            signing_share: signing_share
+                .map(|share| encrypt_share::<C>(pad(b"signing"), &share)),
+            decryption_share: decryption_share
+                .map(|share| encrypt_share::<C>(pad(b"decryption"), &share)),
+        }
+    }
+
+    /// Decrypts the signing and decryption shares using the recipient's secret.
+    pub(crate) fn decrypt<C: Ciphersuite>(
+        &self,
+        encryption_secret: &C::Scalar,
+        sender_encryption_key: &C::Element,
+    ) -> Result<(Option<C::Scalar>, Option<C::Scalar>)> {
+        let pad = |label: &[u8]| {
+            share_pad::<C>(
+                encryption_secret,
+                self.sender,
+                self.recipient,
+                sender_encryption_key,
+                label,
+            )
+        };
+
+        Ok((
+            self.signing_share
+                .as_ref()
+                .map(|share| decrypt_share::<C>(pad(b"signing"), share))
+                .transpose()?,
+            self.decryption_share
+                .as_ref()
+                .map(|share| decrypt_share::<C>(pad(b"decryption"), share))
+                .transpose()?,
+        ))
+    }
+}
+
+/// A single moderator's state during key generation.
+pub(crate) struct Participant<C: Ciphersuite> {
+    identifier: u16,
+    n_participants: usize,
+    signing_polynomial: Option<Polynomial<C>>,
+    decryption_polynomial: Option<Polynomial<C>>,
+    encryption_secret: C::Scalar,
+    round1_package: Round1Package<C>,
+    identity: ModeratorIdentity<C>,
+
+    /// Everyone's round 1 packages, indexed by identifier. Filled in round 2.
+    round1_packages: HashMap<u16, Round1Package<C>>,
+}
+
+/// A moderator's share of one of the group keys, along with commitments to
+/// the group's polynomial.
+type Share<C> = (<C as Ciphersuite>::Scalar, Commitments<C>);
+
+/// A moderator's key material at the end of key generation, for the keys
+/// that its committee holds.
+pub(crate) struct KeyShares<C: Ciphersuite> {
+    identifier: u16,
+    signing: Option<Share<C>>,
+    decryption: Option<Share<C>>,
+}
+
+impl<C: Ciphersuite> Participant<C> {
+    /// Starts key generation for the moderator with the given identity,
+    /// dealing a polynomial for every key in `thresholds`.
+    ///
+    /// Returns the participant's state and the signed package to broadcast to
+    /// everyone else.
+    pub(crate) fn new<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        identity: &ModeratorIdentity<C>,
+        n_participants: usize,
+        thresholds: Thresholds,
+    ) -> Result<(Self, Signed<C, Round1Package<C>>)> {
+        let identifier = u16::from(identity.identifier());
+
+        let mut random_polynomial = |threshold| {
+            let secret = C::random_scalar(rng);
+            Polynomial::random(rng, secret, threshold)
+        };
+        let signing_polynomial = thresholds.signing.map(&mut random_polynomial);
+        let decryption_polynomial =
+            thresholds.decryption.map(&mut random_polynomial);
+        let encryption_secret = C::random_scalar(rng);
+
+        let round1_package = Round1Package {
+            identifier,
+            signing: signing_polynomial.as_ref().map(|polynomial| {
+                CommittedPolynomial::new(
+                    rng, polynomial, b"signing", identifier,
+                )
+            }),
+            decryption: decryption_polynomial.as_ref().map(|polynomial| {
+                CommittedPolynomial::new(
+                    rng,
+                    polynomial,
+                    b"decryption",
+                    identifier,
+                )
+            }),
+            encryption_key: C::base_mul(&encryption_secret),
+        };
+        let signed_package =
+            identity.sign(rng, ROUND1_LABEL, round1_package.clone())?;
+
+        let participant = Self {
+            identifier,
+            n_participants,
+            signing_polynomial,
+            decryption_polynomial,
+            encryption_secret,
+            round1_package,
+            identity: identity.clone(),
+            round1_packages: HashMap::new(),
+        };
+
+        Ok((participant, signed_package))
+    }
+
+    /// Checks everyone's round 1 packages and returns this moderator's
+    /// encrypted shares for every other moderator.
+    pub(crate) fn round2(
+        &mut self,
+        round1_packages: Vec<Signed<C, Round1Package<C>>>,
+    ) -> Result<Vec<EncryptedShares>> {
+        if round1_packages.len() != self.n_participants {
+            return Err("Received the wrong number of round 1 packages.".into());
+        }
+
+        for signed_package in round1_packages {
+            // only the moderator itself can vouch for its encryption key
+            // and commitments
+            let package =
+                self.identity.verify(ROUND1_LABEL, &signed_package)?.clone();
+            if package.identifier != u16::from(signed_package.signer) {
+                return Err(format!(
+                    "Moderator {} signed the round 1 package of moderator {}.",
+                    signed_package.signer, package.identifier
+                )
+                .into());
+            }
+
+            if package.identifier == 0
+                || package.identifier as usize > self.n_participants
+            {
+                return Err(format!(
+                    "Invalid moderator identifier: {}",
+                    package.identifier
+                )
+                .into());
+            }
+
+            package.verify(self.round1_package.thresholds())?;
+
+            if self
+                .round1_packages
+                .insert(package.identifier, package)
+                .is_some()
+            {
+                return Err("Received duplicate round 1 packages.".into());
+            }
+        }
+
+        // make sure our own package was relayed unchanged
+        let own_package = &self.round1_packages[&self.identifier];
+        if own_package.encryption_key != self.round1_package.encryption_key
+            || own_package.commitments() != self.round1_package.commitments()
+        {
+            return Err("Our round 1 package was altered in transit.".into());
+        }
+
+        Ok(self
+            .round1_packages
+            .values()
+            .filter(|package| package.identifier != self.identifier)
+            .map(|package| {
+                let x = C::scalar_from_u64(package.identifier as u64);
+
+                EncryptedShares::encrypt::<C>(
+                    &self.encryption_secret,
+                    self.identifier,
+                    package.identifier,
+                    &package.encryption_key,
+                    self.signing_polynomial
+                        .as_ref()
+                        .map(|polynomial| polynomial.evaluate(&x)),
+                    self.decryption_polynomial
+                        .as_ref()
+                        .map(|polynomial| polynomial.evaluate(&x)),
+                )
+            })
+            .collect())
+    }
+
+    /// Decrypts and verifies the shares sent by every other moderator and
+    /// combines them into this moderator's key shares.
+    pub(crate) fn finish(
+        self,
+        encrypted_shares: Vec<EncryptedShares>,
+    ) -> Result<KeyShares<C>> {
+        if self.round1_packages.len() != self.n_participants {
+            return Err("Key generation round 2 has not been completed.".into());
+        }
+        if encrypted_shares.len() != self.n_participants - 1 {
+            return Err("Received the wrong number of shares.".into());
+        }
+
+        let x = C::scalar_from_u64(self.identifier as u64);
+        let mut signing_share = self
+            .signing_polynomial
+            .as_ref()
+            .map(|polynomial| polynomial.evaluate(&x));
+        let mut decryption_share = self
+            .decryption_polynomial
+            .as_ref()
+            .map(|polynomial| polynomial.evaluate(&x));
+
+        let mut senders = Vec::with_capacity(encrypted_shares.len());
+        for shares in encrypted_shares {
+            if shares.recipient != self.identifier
+                || shares.sender == self.identifier
+                || senders.contains(&shares.sender)
+            {
+                return Err(
+                    "Received a misaddressed or duplicate share.".into()
+                );
+            }
+            senders.push(shares.sender);
+
+            let sender = self
+                .round1_packages
+                .get(&shares.sender)
+                .ok_or("Received a share from an unknown moderator.")?;
+
+            let (sender_signing_share, sender_decryption_share) = shares
+                .decrypt::<C>(
+                    &self.encryption_secret,
+                    &sender.encryption_key,
+                )?;
+
+            let (signing_commitments, decryption_commitments) =
+                sender.commitments();
+            if !add_verified_share(
+                &mut signing_share,
+                sender_signing_share,
+                signing_commitments,
+                &x,
+            ) || !add_verified_share(
+                &mut decryption_share,
+                sender_decryption_share,
+                decryption_commitments,
+                &x,
+            ) {
+                return Err(format!(
+                    "Moderator {} sent an invalid share.",
+                    shares.sender
+                )
+                .into());
+            }
+        }
+
+        let (signing_commitments, decryption_commitments) =
+            group_commitments(self.round1_packages.values())?;
+
+        Ok(KeyShares {
+            identifier: self.identifier,
+            signing: signing_share.zip(signing_commitments),
+            decryption: decryption_share.zip(decryption_commitments),
+        })
+    }
+}
+
+/// Adds a share dealt to this moderator to `share` if it matches the dealer's
+/// `commitments`. A share and commitments MUST be received exactly for the
+/// keys that this moderator holds a share of.
+pub(crate) fn add_verified_share<C: Ciphersuite>(
+    share: &mut Option<C::Scalar>,
+    dealt_share: Option<C::Scalar>,
+    commitments: Option<&Commitments<C>>,
+    x: &C::Scalar,
+) -> bool {
+    match (share, dealt_share, commitments) {
+        (Some(share), Some(dealt_share), Some(commitments))
+            if commitments.verify_share(x, &dealt_share) =>
+        {
+            *share += dealt_share;
+            true
+        }
+        (None, None, None) => true,
+        _ => false,
+    }
+}
+
+impl<C: Ciphersuite> Drop for Participant<C> {
+    fn drop(&mut self) {
+        self.encryption_secret.zeroize();
+    }
+}
+
+impl<C: Ciphersuite> Drop for KeyShares<C> {
+    fn drop(&mut self) {
+        if let Some((share, _)) = &mut self.signing {
+            share.zeroize();
+        }
+        if let Some((share, _)) = &mut self.decryption {
+            share.zeroize();
+        }
+    }
+}
+
+impl<C: Ciphersuite> KeyShares<C> {
+    pub(crate) fn new(
+        identifier: u16,
+        signing: Option<Share<C>>,
+        decryption: Option<Share<C>>,
+    ) -> Self {
+        Self {
+            identifier,
+            signing,
+            decryption,
+        }
+    }
+
+    /// This moderator's FROST key package, if it holds a share of the
+    /// signing key.
+    pub(crate) fn frost_key_package(
+        &self,
+    ) -> Result<Option<frost::keys::KeyPackage<C::Frost>>> {
+        let (signing_share, signing_commitments) = match &self.signing {
+            Some(signing) => signing,
+            None => return Ok(None),
+        };
+        let x = C::scalar_from_u64(self.identifier as u64);
+
+        Ok(Some(frost::keys::KeyPackage {
+            identifier: frost::Identifier::try_from(self.identifier)?,
+            secret_share: C::signing_share(signing_share)?,
+            public: C::verifying_share(&signing_commitments.evaluate(&x))?,
+            group_public: C::verifying_key(&signing_commitments.public_key())?,
+        }))
+    }
+
+    /// This moderator's share of the ElGamal decryption key, if it has one.
+    pub(crate) fn elgamal_key_share(&self) -> Option<elgamal::KeyShare<C>> {
+        let (decryption_share, decryption_commitments) =
+            self.decryption.as_ref()?;
+
+        Some(elgamal::KeyShare::new(
+            self.identifier,
+            *decryption_share,
+            elgamal::PublicKey(decryption_commitments.public_key()),
+        ))
+    }
+
+    pub(crate) fn signing_public_key(&self) -> Option<C::Element> {
+        self.signing
+            .as_ref()
+            .map(|(_, commitments)| commitments.public_key())
+    }
+
+    pub(crate) fn elgamal_public_key(&self) -> Option<elgamal::PublicKey<C>> {
+        self.decryption.as_ref().map(|(_, commitments)| {
+            elgamal::PublicKey(commitments.public_key())
+        })
+    }
+}
+
+/// Combines everyone's round 1 commitments into commitments to the group's
+/// polynomials for the keys that the committee holds.
+#[allow(clippy::type_complexity)]
+pub(crate) fn group_commitments<'a, C: Ciphersuite, I>(
+    round1_packages: I,
+) -> Result<(Option<Commitments<C>>, Option<Commitments<C>>)>
+where
+    I: IntoIterator<Item = &'a Round1Package<C>> + Clone,
+{
+    let thresholds = round1_packages
+        .clone()
+        .into_iter()
+        .next()
+        .ok_or("No round 1 packages.")?
+        .thresholds();
+    if round1_packages
+        .clone()
+        .into_iter()
+        .any(|package| package.thresholds() != thresholds)
+    {
+        return Err("Moderators committed to different polynomials.".into());
+    }
+
+    Ok((
+        thresholds.signing.map(|threshold| {
+            Commitments::sum(
+                round1_packages
+                    .clone()
+                    .into_iter()
+                    .filter_map(|package| package.commitments().0),
+                threshold,
+            )
+        }),
+        thresholds.decryption.map(|threshold| {
+            Commitments::sum(
+                round1_packages
+                    .into_iter()
+                    .filter_map(|package| package.commitments().1),
+                threshold,
+            )
+        }),
+    ))
+}
+
+/// The ElGamal verification keys of moderators `1..=n_participants` derived
+/// from commitments to the group decryption polynomial.
+pub(crate) fn elgamal_verification_keys<C: Ciphersuite>(
+    decryption_commitments: &Commitments<C>,
+    n_participants: usize,
+) -> HashMap<u16, elgamal::VerificationKey<C>> {
+    (1..=n_participants as u16)
+        .map(|i| {
+            let key =
+                decryption_commitments.evaluate(&C::scalar_from_u64(i as u64));
+            (i, elgamal::VerificationKey(key))
+        })
+        .collect()
+}
+
+/// The FROST public key package for moderators `1..=n_participants` derived
+/// from commitments to the group signing polynomial.
+pub(crate) fn frost_public_key_package<C: Ciphersuite>(
+    signing_commitments: &Commitments<C>,
+    n_participants: usize,
+) -> Result<frost::keys::PublicKeyPackage<C::Frost>> {
+    let mut signer_pubkeys = HashMap::with_capacity(n_participants);
+    for moderator in ModeratorId::all(n_participants) {
+        let verifying_share = signing_commitments
+            .evaluate(&C::scalar_from_u64(u16::from(moderator) as u64));
+
+        signer_pubkeys.insert(
+            frost::Identifier::from(moderator),
+            C::verifying_share(&verifying_share)?,
+        );
+    }
+
+    Ok(frost::keys::PublicKeyPackage {
+        signer_pubkeys,
+        group_public: C::verifying_key(&signing_commitments.public_key())?,
+    })
+}
+
+/// A one-time pad for a share sent from `sender` to `recipient`, derived from
+/// their Diffie-Hellman shared secret. Both ends pass the same direction,
+/// with `other_encryption_key` being the key of the other end.
+///
+/// The shares sent in each direction are encrypted under the same shared
+/// secret, so the pad MUST depend on the direction: otherwise the relaying
+/// coordinator could XOR the two ciphertexts to learn `f_i(j) ⊕ f_j(i)`.
+pub(crate) fn share_pad<C: Ciphersuite>(
+    encryption_secret: &C::Scalar,
+    sender: u16,
+    recipient: u16,
+    other_encryption_key: &C::Element,
+    label: &[u8],
+) -> C::ScalarBytes {
+    let shared_secret = *encryption_secret * *other_encryption_key;
+
+    let mut pad = C::ScalarBytes::default();
+    Hkdf::<Sha256>::new(None, C::element_to_bytes(&shared_secret).as_ref())
+        .expand_multi_info(
+            &[
+                b"cerberus-dkg-share-encryption",
+                label,
+                &sender.to_be_bytes(),
+                &recipient.to_be_bytes(),
+            ],
+            pad.as_mut(),
+        )
+        .expect("A scalar encoding is a valid HKDF-SHA256 output length.");
+
+    pad
+}
+
+/// Encrypts a share with a pad from [`share_pad`].
+pub(crate) fn encrypt_share<C: Ciphersuite>(
+    mut pad: C::ScalarBytes,
+    share: &C::Scalar,
+) -> Vec<u8> {
+    xor_into(pad.as_mut(), C::scalar_to_bytes(share).as_ref());
+
+    pad.as_ref().to_vec()
+}
+
+/// Decrypts a share encrypted with [`encrypt_share`].
+pub(crate) fn decrypt_share<C: Ciphersuite>(
+    mut pad: C::ScalarBytes,
+    ciphertext: &[u8],
+) -> Result<C::Scalar> {
+    xor_into(
+        pad.as_mut(),
+        C::ScalarBytes::from_slice(ciphertext)?.as_ref(),
+    );
+
+    Ok(C::scalar_from_bytes_mod_order(pad))
+}
+
+fn xor_into(a: &mut [u8], b: &[u8]) {
+    for (a, b) in a.iter_mut().zip(b) {
+        *a ^= b;
+    }
+}
+
+fn proof_context(label: &[u8], identifier: u16) -> Vec<u8> {
+    let mut context = label.to_vec();
+    context.extend(identifier.to_be_bytes());
+
+    context
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        decrypt_share, elgamal_verification_keys, encrypt_share,
+        frost_public_key_package, group_commitments, share_pad, Participant,
+        Thresholds,
+    };
+    use crate::{
+        ciphersuite::{Ciphersuite, Ed25519, Ristretto255},
+        identity::ModeratorIdentity,
+        Result, UserId,
+    };
+    use curve25519_dalek::scalar::Scalar;
+    use frost_core::frost;
+
+    /// Runs key generation in memory, relaying messages like the coordinator.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn run_dkg<C: Ciphersuite>(
+        n: usize,
+        thresholds: Thresholds,
+    ) -> Result<(Vec<super::KeyShares<C>>, Vec<super::Round1Package<C>>)> {
+        let mut rng = rand::thread_rng();
+
+        let identities = ModeratorIdentity::generate_committee(&mut rng, n);
+        let (mut participants, round1_packages): (Vec<_>, Vec<_>) = identities
+            .iter()
+            .map(|identity| Participant::new(&mut rng, identity, n, thresholds))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        let mut encrypted_shares = Vec::new();
+        for participant in participants.iter_mut() {
+            encrypted_shares
+                .extend(participant.round2(round1_packages.clone())?);
+        }
+
+        let key_shares = participants
+            .into_iter()
+            .map(|participant| {
+                let shares = encrypted_shares
+                    .iter()
+                    .filter(|shares| shares.recipient == participant.identifier)
+                    .cloned()
+                    .collect();
+                participant.finish(shares)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let round1_packages = round1_packages
+            .into_iter()
+            .map(|package| package.message)
+            .collect();
+
+        Ok((key_shares, round1_packages))
+    }
+
+    #[test]
+    fn test_dkg_decryption() -> Result<()> {
+        let (n, decryption_threshold) = (5, 3);
+        let (key_shares, round1_packages) = run_dkg::<Ristretto255>(
+            n,
+            Thresholds::both(4, decryption_threshold),
+        )?;
+
+        let (_, decryption_commitments) = group_commitments(&round1_packages)?;
+        let decryption_commitments = decryption_commitments.unwrap();
+        let pk = key_shares[0].elgamal_public_key().unwrap();
+        assert!(key_shares
+            .iter()
+            .all(|k| k.elgamal_public_key() == Some(pk)));
+        assert_eq!(pk.0, decryption_commitments.public_key());
+
+        let mut rng = rand::thread_rng();
+        let id = UserId::random(&mut rng);
+        let x_1 = pk.encrypt(&id, &Scalar::random(&mut rng));
+
+        // any subset of `decryption_threshold` moderators can decrypt
+        let decryption_shares: Vec<_> = key_shares[n - decryption_threshold..]
+            .iter()
+            .map(|k| {
+                k.elgamal_key_share()
+                    .unwrap()
+                    .decryption_share(&x_1, &mut rng)
+            })
+            .collect();
+
+        // the verification keys derived from the public commitments
+        // must match the moderators' key shares
+        let verification_keys =
+            elgamal_verification_keys(&decryption_commitments, n);
+
+        assert_eq!(
+            id,
+            x_1.decrypt_with_shares(
+                &decryption_shares,
+                &verification_keys,
+                decryption_threshold
+            )?
+        );
+
+        Ok(())
+    }
+
+    fn dkg_signing<C: Ciphersuite>() -> Result<()> {
+        let (n, signing_threshold) = (5, 3);
+        let (key_shares, round1_packages) =
+            run_dkg::<C>(n, Thresholds::both(signing_threshold, 3))?;
+
+        let (signing_commitments, _) = group_commitments(&round1_packages)?;
+        let public_key_package =
+            frost_public_key_package(&signing_commitments.unwrap(), n)?;
+
+        let key_packages = key_shares
+            .iter()
+            .map(|k| Ok(k.frost_key_package()?.unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut rng = rand::thread_rng();
+        let (nonces, commitments): (Vec<_>, Vec<_>) = key_packages
+            .iter()
+            .map(|key_package| {
+                frost::round1::commit(
+                    key_package.identifier,
+                    &key_package.secret_share,
+                    &mut rng,
+                )
+            })
+            .unzip();
+
+        let message = b"hello, world".to_vec();
+        let signing_package =
+            frost::SigningPackage::new(commitments, message.clone());
+
+        let signature_shares = key_packages
+            .iter()
+            .zip(&nonces)
+            .map(|(key_package, nonces)| {
+                frost::round2::sign(&signing_package, nonces, key_package)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let signature = frost::aggregate(
+            &signing_package,
+            &signature_shares,
+            &public_key_package,
+        )?;
+
+        assert!(public_key_package
+            .group_public
+            .verify(&message, &signature)
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dkg_signing() -> Result<()> {
+        dkg_signing::<Ristretto255>()
+    }
+
+    #[test]
+    fn test_ed25519_dkg_signing() -> Result<()> {
+        dkg_signing::<Ed25519>()
+    }
+
+    #[test]
+    fn test_share_pad_direction() {
+        let mut rng = rand::thread_rng();
+        let (secret_1, secret_2) = (
+            Ristretto255::random_scalar(&mut rng),
+            Ristretto255::random_scalar(&mut rng),
+        );
+        let (key_1, key_2) = (
+            Ristretto255::base_mul(&secret_1),
+            Ristretto255::base_mul(&secret_2),
+        );
+
+        // both ends derive the same pad for a share sent from 1 to 2
+        let pad_1_to_2 =
+            share_pad::<Ristretto255>(&secret_1, 1, 2, &key_2, b"test");
+        assert_eq!(
+            pad_1_to_2,
+            share_pad::<Ristretto255>(&secret_2, 1, 2, &key_1, b"test")
+        );
+
+        // but not for a share sent from 2 to 1
+        let pad_2_to_1 =
+            share_pad::<Ristretto255>(&secret_2, 2, 1, &key_1, b"test");
+        assert_ne!(pad_1_to_2, pad_2_to_1, "Share pads are reused.");
+    }
+
+    #[test]
+    fn test_share_encryption() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let share = Ristretto255::random_scalar(&mut rng);
+        let pad = share_pad::<Ristretto255>(
+            &Ristretto255::random_scalar(&mut rng),
+            1,
+            2,
+            &Ristretto255::base_mul(&Ristretto255::random_scalar(&mut rng)),
+            b"test",
+        );
+
+        let ciphertext = encrypt_share::<Ristretto255>(pad, &share);
+        assert_eq!(decrypt_share::<Ristretto255>(pad, &ciphertext)?, share);
+
+        // a ciphertext of the wrong length is rejected
+        assert!(decrypt_share::<Ristretto255>(pad, &ciphertext[1..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dkg_single_key() -> Result<()> {
+        let n = 4;
+        let thresholds = Thresholds {
+            signing: None,
+            decryption: Some(3),
+        };
+        let (key_shares, round1_packages) =
+            run_dkg::<Ristretto255>(n, thresholds)?;
+
+        // a decryption committee only ends up with decryption key shares
+        let (signing_commitments, decryption_commitments) =
+            group_commitments(&round1_packages)?;
+        assert!(signing_commitments.is_none());
+        assert!(key_shares.iter().all(|k| {
+            k.frost_key_package()
+                .map_or(false, |package| package.is_none())
+                && k.elgamal_public_key().map(|pk| pk.0)
+                    == decryption_commitments
+                        .as_ref()
+                        .map(|commitments| commitments.public_key())
+        }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dkg_rejects_mismatched_keys() -> Result<()> {
+        let n = 3;
+        let mut rng = rand::thread_rng();
+
+        let identities = ModeratorIdentity::generate_committee(&mut rng, n);
+        let (mut participants, round1_packages): (Vec<_>, Vec<_>) = identities
+            .iter()
+            .map(|identity| {
+                // moderator 3 doesn't deal a signing polynomial
+                let thresholds = match u16::from(identity.identifier()) {
+                    3 => Thresholds {
+                        signing: None,
+                        decryption: Some(2),
+                    },
+                    _ => Thresholds::both(2, 2),
+                };
+                Participant::<Ristretto255>::new(
+                    &mut rng, identity, n, thresholds,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        for participant in participants.iter_mut() {
+            assert!(participant.round2(round1_packages.clone()).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dkg_rejects_bad_share() -> Result<()> {
+        let n = 3;
+        let mut rng = rand::thread_rng();
+
+        let identities = ModeratorIdentity::generate_committee(&mut rng, n);
+        let (mut participants, round1_packages): (Vec<_>, Vec<_>) = identities
+            .iter()
+            .map(|identity| {
+                Participant::<Ristretto255>::new(
+                    &mut rng,
+                    identity,
+                    n,
+                    Thresholds::both(2, 2),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        let mut encrypted_shares = Vec::new();
+        for participant in participants.iter_mut() {
+            encrypted_shares
+                .extend(participant.round2(round1_packages.clone())?);
+        }
+
+        // corrupt a share sent to moderator 1
+        let mut shares: Vec<_> = encrypted_shares
+            .into_iter()
+            .filter(|shares| shares.recipient == 1)
+            .collect();
+        shares[0].decryption_share.as_mut().unwrap()[0] ^= 1;
+
+        assert!(participants.remove(0).finish(shares).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dkg_rejects_substituted_package() -> Result<()> {
+        let n = 3;
+        let mut rng = rand::thread_rng();
+
+        let identities = ModeratorIdentity::generate_committee(&mut rng, n);
+        let (participants, mut round1_packages): (Vec<_>, Vec<_>) = identities
+            .iter()
+            .map(|identity| {
+                Participant::<Ristretto255>::new(
+                    &mut rng,
+                    identity,
+                    n,
+                    Thresholds::both(2, 2),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        // the coordinator swaps in its own encryption key for moderator 2
+        round1_packages[1].message.encryption_key =
+            Ristretto255::base_mul(&Ristretto255::random_scalar(&mut rng));
+
+        for mut participant in participants {
+            assert!(participant.round2(round1_packages.clone()).is_err());
+        }
+
+        Ok(())
+    }
+}
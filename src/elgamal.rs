@@ -1,154 +1,829 @@
 use crate::{
     // parameters::{DECRYPTION_THRESHOLD, N_MODERATORS},
+    ciphersuite::{Ciphersuite, Ristretto255},
+    shamir::{Commitments, LagrangeCache, Polynomial},
     Result,
     UserId,
 };
 
-use curve25519_dalek::{
-    constants::RISTRETTO_BASEPOINT_TABLE, ristretto::RistrettoPoint,
-    scalar::Scalar,
-};
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::{collections::HashMap, error::Error, fmt};
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::Zeroize;
 
-#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
-pub(crate) struct KeyShare {
-    identifier: Scalar, // x
-    sk: Scalar,         // f(x)
-    pk: PublicKey,
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub(crate) struct KeyShare<C: Ciphersuite> {
+    identifier: u16, // x
+    sk: C::Scalar,   // f(x)
+    pk: PublicKey<C>,
 }
 
-/// Wrapper around a single Ristretto point.
+/// Wrapper around a single group element.
 /// Equivalent to `x * G` where `y` is the private key and `G`
-/// is the Ristretto base point.
+/// is the group's generator.
 #[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
-pub struct PublicKey(RistrettoPoint);
+#[serde(bound = "")]
+pub struct PublicKey<C: Ciphersuite = Ristretto255>(pub(crate) C::Element);
+
+/// How the sender id is encrypted into a token's `x_1`.
+///
+/// Chosen at setup, and enforced by the moderators when signing and
+/// decrypting tokens.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+pub enum IdEncryption {
+    /// Hashed ElGamal. The ciphertext is malleable, and is only
+    /// protected from tampering by the token's signature.
+    #[default]
+    Hashed,
+
+    /// Hashed ElGamal with a TDH2 validity proof labeled with the token's
+    /// ephemeral key. The moderators refuse to sign or decrypt unlabeled or
+    /// invalid ciphertexts.
+    Labeled,
+}
 
 /// An ElGamal encryption of a
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct EncryptedUserId {
-    c_1: RistrettoPoint,
+///
+/// Compared in constant time.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct EncryptedUserId<C: Ciphersuite = Ristretto255> {
+    c_1: C::Element,
     c_2: [u8; 32],
+
+    /// `M + r * PK`, where `M` is the id mapped to a group element. Present
+    /// if the ciphertext was created by [`PublicKey::encrypt_provable`].
+    c_3: Option<C::Element>,
+
+    /// Present if the ciphertext was created by [`Self::labeled`].
+    validity_proof: Option<ValidityProof<C>>,
+}
+
+/// A Pedersen commitment `M + s * H` to a user's id, where `M` is the id
+/// mapped to a group element and `s` is a random blinding.
+///
+/// Hides the id from the moderators, while binding the coordinator (which
+/// authenticated the user) to it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(bound = "")]
+pub(crate) struct IdCommitment<C: Ciphersuite>(C::Element);
+
+/// A proof that `c_1 = r * G` and `c_3 - commitment = r * PK - s * H` for
+/// some `r` and `s`, i.e., that `c_3` encrypts the id in the commitment.
+///
+/// Reveals nothing about the id itself.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub(crate) struct CommittedIdProof<C: Ciphersuite> {
+    challenge: C::Scalar,
+    randomness_response: C::Scalar,
+    blinding_response: C::Scalar,
+}
+
+/// The TDH2 proof that `c_1 = r * G` and `u_bar = r * G_bar` for the same `r`,
+/// bound to the rest of the ciphertext and a label.
+///
+/// Makes the ciphertext non-malleable: changing any part of it, or
+/// decrypting it under a different label, invalidates the proof.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+struct ValidityProof<C: Ciphersuite> {
+    u_bar: C::Element,
+    challenge: C::Scalar,
+    response: C::Scalar,
 }
 
+/// A moderator's public verification key `f(x) * G`, used to check
+/// that its decryption shares were computed with its key share.
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct VerificationKey<C: Ciphersuite = Ristretto255>(
+    pub(crate) C::Element,
+);
+
 /// Wrapper for a decryption shares `(x, d)` where `d = f(x) * c_1` is
 /// the product of the Shamir secret share and the the first entry
 /// of the ElGamal ciphertext tuple, `c_1`.
-#[derive(Deserialize, Serialize)]
-pub struct DecryptionShare(Scalar, RistrettoPoint);
+///
+/// Carries a proof that `d` was computed with the same secret as
+/// the moderator's [`VerificationKey`].
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(bound = "")]
+pub struct DecryptionShare<C: Ciphersuite = Ristretto255> {
+    pub(crate) identifier: u16,
+    pub(crate) share: C::Element,
+    proof: DleqProof<C>,
+}
+
+/// A Chaum-Pedersen proof that `log_G(A) = log_B(C)` for public points `A, B, C`.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(bound = "")]
+struct DleqProof<C: Ciphersuite> {
+    challenge: C::Scalar,
+    response: C::Scalar,
+}
+
+/// Errors that can occur while combining decryption shares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecryptionError {
+    /// Fewer than `threshold` moderators sent a valid share.
+    ///
+    /// `invalid_moderators` lists the moderators whose shares failed verification.
+    NotEnoughShares {
+        valid: usize,
+        threshold: usize,
+        invalid_moderators: Vec<u16>,
+    },
+}
+
+impl fmt::Display for DecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughShares {
+                valid,
+                threshold,
+                invalid_moderators,
+            } => write!(
+                f,
+                "Only {valid} of the {threshold} required decryption shares \
+                were valid. Invalid shares were sent by moderators \
+                {invalid_moderators:?}."
+            ),
+        }
+    }
+}
+
+impl Error for DecryptionError {}
+
+impl<C: Ciphersuite> fmt::Debug for KeyShare<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyShare")
+            .field("identifier", &self.identifier)
+            .field("sk", &"<redacted>")
+            .field("pk", &self.pk)
+            .finish()
+    }
+}
+
+impl<C: Ciphersuite> PartialEq for KeyShare<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.identifier == other.identifier
+            && self.pk == other.pk
+            && bool::from(self.sk.ct_eq(&other.sk))
+    }
+}
+
+impl<C: Ciphersuite> Drop for KeyShare<C> {
+    fn drop(&mut self) {
+        self.sk.zeroize();
+    }
+}
+
+impl<C: Ciphersuite> ConstantTimeEq for EncryptedUserId<C> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        let proofs_equal = match (&self.validity_proof, &other.validity_proof) {
+            (Some(a), Some(b)) => {
+                a.u_bar.ct_eq(&b.u_bar)
+                    & a.challenge.ct_eq(&b.challenge)
+                    & a.response.ct_eq(&b.response)
+            }
+            (None, None) => Choice::from(1),
+            _ => Choice::from(0),
+        };
+        let c_3s_equal = match (&self.c_3, &other.c_3) {
+            (Some(a), Some(b)) => a.ct_eq(b),
+            (None, None) => Choice::from(1),
+            _ => Choice::from(0),
+        };
+
+        self.c_1.ct_eq(&other.c_1)
+            & self.c_2[..].ct_eq(&other.c_2[..])
+            & c_3s_equal
+            & proofs_equal
+    }
+}
 
-impl PublicKey {
+impl<C: Ciphersuite> PartialEq for EncryptedUserId<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl<C: Ciphersuite> Eq for EncryptedUserId<C> {}
+
+impl<C: Ciphersuite> PublicKey<C> {
     pub(crate) fn encrypt(
         &self,
         user_id: &UserId,
-        randomness: &Scalar,
-    ) -> EncryptedUserId {
+        randomness: &C::Scalar,
+    ) -> EncryptedUserId<C> {
         // compute c_1 = r * G
-        let c_1 = randomness * &RISTRETTO_BASEPOINT_TABLE;
+        let c_1 = C::base_mul(randomness);
 
         // compute c_2 = ID + H(r * PK)
         let mut hasher = Sha256::new();
-        hasher.update((randomness * self.0).compress().as_bytes());
+        hasher.update(C::element_to_bytes(&(*randomness * self.0)));
         let hashed_point = hasher.finalize()[..]
             .try_into()
-            .expect("Unable to hash Ristretto point during ID encryption.");
+            .expect("Unable to hash group element during ID encryption.");
 
         let c_2 = xor_bytes(hashed_point, &user_id.0);
 
-        EncryptedUserId { c_1, c_2 }
+        EncryptedUserId {
+            c_1,
+            c_2,
+            c_3: None,
+            validity_proof: None,
+        }
+    }
+
+    /// Encrypts the id as in [`Self::encrypt`], and also encrypts the id's
+    /// group element `M` with standard ElGamal as `c_3 = M + r * PK`, using
+    /// the same randomness.
+    ///
+    /// Unlike `c_2`, `c_3` admits proofs about the encrypted id, see
+    /// [`EncryptedUserId::prove_committed_id`]. Decryption checks that the
+    /// two agree.
+    pub(crate) fn encrypt_provable(
+        &self,
+        user_id: &UserId,
+        randomness: &C::Scalar,
+    ) -> EncryptedUserId<C> {
+        let mut x_1 = self.encrypt(user_id, randomness);
+        x_1.c_3 = Some(id_element::<C>(user_id) + *randomness * self.0);
+
+        x_1
+    }
+}
+
+impl<C: Ciphersuite> IdCommitment<C> {
+    /// Commits to `user_id` with the given blinding.
+    pub(crate) fn new(user_id: &UserId, blinding: &C::Scalar) -> Self {
+        Self(id_element::<C>(user_id) + *blinding * commitment_generator::<C>())
     }
 }
 
-impl KeyShare {
-    pub(crate) fn decryption_share(
+impl<C: Ciphersuite> KeyShare<C> {
+    pub(crate) fn new(
+        identifier: u16,
+        sk: C::Scalar,
+        pk: PublicKey<C>,
+    ) -> Self {
+        Self { identifier, sk, pk }
+    }
+
+    pub(crate) fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Checks that the share lies on the committed polynomial
+    /// and that its public key is the polynomial's constant term.
+    pub(crate) fn verify(&self, commitments: &Commitments<C>) -> Result<()> {
+        let x = C::scalar_from_u64(self.identifier as u64);
+
+        if self.identifier == 0 || !commitments.verify_share(&x, &self.sk) {
+            return Err(
+                "ElGamal key share doesn't match the commitments.".into()
+            );
+        }
+        if commitments.public_key() != self.pk.0 {
+            return Err(
+                "ElGamal public key doesn't match the commitments.".into()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The secret share `f(x)`, for resharing it to a new committee.
+    pub(crate) fn secret(&self) -> &C::Scalar {
+        &self.sk
+    }
+
+    /// This share with `delta` added to it, as computed in a refresh.
+    pub(crate) fn refreshed(&self, delta: &C::Scalar) -> Self {
+        Self {
+            identifier: self.identifier,
+            sk: self.sk + *delta,
+            pk: self.pk,
+        }
+    }
+
+    /// The key that this moderator's decryption shares are verified against.
+    pub(crate) fn verification_key(&self) -> VerificationKey<C> {
+        VerificationKey(C::base_mul(&self.sk))
+    }
+
+    pub(crate) fn decryption_share<T: Ciphertext<C>, R: RngCore + CryptoRng>(
         &self,
-        x_1: &EncryptedUserId,
-    ) -> DecryptionShare {
-        DecryptionShare(self.identifier, self.sk * x_1.c_1)
+        x_1: &T,
+        rng: &mut R,
+    ) -> DecryptionShare<C> {
+        let c_1 = x_1.c_1();
+        let share = self.sk * *c_1;
+        let proof = DleqProof::new(
+            rng,
+            &self.sk,
+            &self.verification_key().0,
+            c_1,
+            &share,
+        );
+
+        DecryptionShare {
+            identifier: self.identifier,
+            share,
+            proof,
+        }
     }
 
-    pub(crate) fn encrypt(
+    pub(crate) fn public_key(&self) -> &PublicKey<C> {
+        &self.pk
+    }
+}
+
+impl<C: Ciphersuite> EncryptedUserId<C> {
+    /// Attaches a TDH2 validity proof binding the ciphertext, which must be
+    /// the encryption of `user_id` with `randomness`, to `label`, making it
+    /// CCA-secure.
+    ///
+    /// The proof's nonce is derived from the inputs, so this is deterministic
+    /// and can be checked by re-encrypting, as with [`PublicKey::encrypt`].
+    pub(crate) fn labeled(
+        mut self,
+        pk: &PublicKey<C>,
+        user_id: &UserId,
+        randomness: &C::Scalar,
+        label: &[u8],
+    ) -> Self {
+        let g_bar = second_generator::<C>();
+        let u_bar = *randomness * g_bar;
+
+        let nonce = C::hash_to_scalar(&[
+            b"cerberus-tdh2-nonce",
+            C::scalar_to_bytes(randomness).as_ref(),
+            C::element_to_bytes(&pk.0).as_ref(),
+            &user_id.0,
+            label,
+        ]);
+        let w = C::base_mul(&nonce);
+        let w_bar = nonce * g_bar;
+
+        let challenge = self.validity_challenge(pk, &u_bar, &w, &w_bar, label);
+        self.validity_proof = Some(ValidityProof {
+            u_bar,
+            challenge,
+            response: nonce + challenge * *randomness,
+        });
+
+        self
+    }
+
+    /// Whether the ciphertext carries a validity proof, see [`Self::labeled`].
+    pub(crate) fn is_labeled(&self) -> bool {
+        self.validity_proof.is_some()
+    }
+
+    /// Checks the ciphertext's validity proof for the public key `pk` under
+    /// `label`.
+    pub(crate) fn verify_label(
+        &self,
+        pk: &PublicKey<C>,
+        label: &[u8],
+    ) -> Result<()> {
+        let proof = self
+            .validity_proof
+            .as_ref()
+            .ok_or("ID encryption has no validity proof.")?;
+
+        let w = C::base_mul(&proof.response) - proof.challenge * self.c_1;
+        let w_bar = proof.response * second_generator::<C>()
+            - proof.challenge * proof.u_bar;
+
+        if self.validity_challenge(pk, &proof.u_bar, &w, &w_bar, label)
+            != proof.challenge
+        {
+            return Err("ID encryption is invalid.".into());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the ciphertext is allowed by `policy`, verifying its
+    /// validity proof under `label` if it has one.
+    pub(crate) fn check_policy(
+        &self,
+        policy: IdEncryption,
+        pk: &PublicKey<C>,
+        label: &[u8],
+    ) -> Result<()> {
+        match (policy, self.is_labeled()) {
+            (_, true) => self.verify_label(pk, label),
+            (IdEncryption::Labeled, false) => {
+                Err("ID encryption must be labeled.".into())
+            }
+            (IdEncryption::Hashed, false) => Ok(()),
+        }
+    }
+
+    /// Checks that this is the encryption of `user_id` with `randomness`,
+    /// labeled with `label` if the ciphertext is labeled.
+    pub(crate) fn is_encryption_of(
         &self,
+        pk: &PublicKey<C>,
         user_id: &UserId,
-        randomness: &Scalar,
-    ) -> EncryptedUserId {
-        self.pk.encrypt(user_id, randomness)
+        randomness: &C::Scalar,
+        label: &[u8],
+    ) -> bool {
+        let expected = match self.c_3 {
+            Some(_) => pk.encrypt_provable(user_id, randomness),
+            None => pk.encrypt(user_id, randomness),
+        };
+        let expected = match self.validity_proof {
+            Some(_) => expected.labeled(pk, user_id, randomness, label),
+            None => expected,
+        };
+
+        *self == expected
     }
-}
 
-impl EncryptedUserId {
-    /// The length of `shares` MUST be exactly the decryption threshold and every share MUST be unique.
+    /// Proves that `c_3` encrypts the id in `commitment`, given the
+    /// encryption randomness and the commitment's blinding. Bound to
+    /// `context`, e.g., the token that the ciphertext is part of.
+    ///
+    /// Only possible for ciphertexts created by [`PublicKey::encrypt_provable`].
+    pub(crate) fn prove_committed_id<R: CryptoRng + RngCore>(
+        &self,
+        pk: &PublicKey<C>,
+        commitment: &IdCommitment<C>,
+        randomness: &C::Scalar,
+        blinding: &C::Scalar,
+        context: &[u8],
+        rng: &mut R,
+    ) -> Result<CommittedIdProof<C>> {
+        self.c_3.ok_or("ID encryption has no provable component.")?;
+
+        let randomness_nonce = C::random_scalar(rng);
+        let blinding_nonce = C::random_scalar(rng);
+        let challenge = self.committed_id_challenge(
+            pk,
+            commitment,
+            &C::base_mul(&randomness_nonce),
+            &(randomness_nonce * pk.0
+                - blinding_nonce * commitment_generator::<C>()),
+            context,
+        );
+
+        Ok(CommittedIdProof {
+            challenge,
+            randomness_response: randomness_nonce + challenge * *randomness,
+            blinding_response: blinding_nonce + challenge * *blinding,
+        })
+    }
+
+    /// Checks a proof created by [`Self::prove_committed_id`].
+    pub(crate) fn verify_committed_id(
+        &self,
+        pk: &PublicKey<C>,
+        commitment: &IdCommitment<C>,
+        proof: &CommittedIdProof<C>,
+        context: &[u8],
+    ) -> Result<()> {
+        let c_3 = self.c_3.ok_or("ID encryption has no provable component.")?;
+
+        // recompute the prover's commitments from the challenge and responses
+        let commitment_g = C::base_mul(&proof.randomness_response)
+            - proof.challenge * self.c_1;
+        let commitment_pk = proof.randomness_response * pk.0
+            - proof.blinding_response * commitment_generator::<C>()
+            - proof.challenge * (c_3 - commitment.0);
+
+        if self.committed_id_challenge(
+            pk,
+            commitment,
+            &commitment_g,
+            &commitment_pk,
+            context,
+        ) != proof.challenge
+        {
+            return Err("ID encryption doesn't match the id commitment.".into());
+        }
+
+        Ok(())
+    }
+
+    fn committed_id_challenge(
+        &self,
+        pk: &PublicKey<C>,
+        commitment: &IdCommitment<C>,
+        commitment_g: &C::Element,
+        commitment_pk: &C::Element,
+        context: &[u8],
+    ) -> C::Scalar {
+        C::hash_to_scalar(&[
+            b"cerberus-committed-id",
+            C::element_to_bytes(&pk.0).as_ref(),
+            &self.to_bytes(),
+            C::element_to_bytes(&commitment.0).as_ref(),
+            C::element_to_bytes(commitment_g).as_ref(),
+            C::element_to_bytes(commitment_pk).as_ref(),
+            context,
+        ])
+    }
+
+    fn validity_challenge(
+        &self,
+        pk: &PublicKey<C>,
+        u_bar: &C::Element,
+        w: &C::Element,
+        w_bar: &C::Element,
+        label: &[u8],
+    ) -> C::Scalar {
+        C::hash_to_scalar(&[
+            b"cerberus-tdh2-challenge",
+            C::element_to_bytes(&pk.0).as_ref(),
+            &self.to_bytes(),
+            C::element_to_bytes(u_bar).as_ref(),
+            C::element_to_bytes(w).as_ref(),
+            C::element_to_bytes(w_bar).as_ref(),
+            label,
+        ])
+    }
+
+    /// The bytes of the ciphertext `(c_1, c_2)`, followed by `c_3` if
+    /// present, without its validity proof.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = C::element_to_bytes(&self.c_1).as_ref().to_vec();
+        bytes.extend(self.c_2);
+        if let Some(c_3) = &self.c_3 {
+            bytes.extend(C::element_to_bytes(c_3).as_ref());
+        }
+
+        bytes
+    }
+
+    /// Decrypts the id using the first `threshold` valid shares from distinct moderators.
+    ///
+    /// Every share is checked against its sender's key in `verification_keys`.
+    /// Invalid and duplicate shares are skipped, and a [`DecryptionError`] is
+    /// returned if fewer than `threshold` valid shares remain.
     pub fn decrypt_with_shares(
         &self,
-        shares: &[DecryptionShare],
+        shares: &[DecryptionShare<C>],
+        verification_keys: &HashMap<u16, VerificationKey<C>>,
+        threshold: usize,
     ) -> Result<UserId> {
-        // TODO: extract this into a separate function
-        let identifiers: Vec<_> = shares.iter().map(|share| share.0).collect();
+        self.decrypt_with_cached_coefficients(
+            shares,
+            verification_keys,
+            threshold,
+            &LagrangeCache::default(),
+        )
+    }
 
-        let sum_of_decryption_shares: RistrettoPoint = shares
-            .iter()
-            .map(|share| lagrange_coefficient(&share.0, &identifiers) * share.1)
-            .sum();
+    /// Like [`Self::decrypt_with_shares`], but reuses the Lagrange
+    /// coefficients in `lagrange_cache` when the same moderators answer again.
+    pub(crate) fn decrypt_with_cached_coefficients(
+        &self,
+        shares: &[DecryptionShare<C>],
+        verification_keys: &HashMap<u16, VerificationKey<C>>,
+        threshold: usize,
+        lagrange_cache: &LagrangeCache<C>,
+    ) -> Result<UserId> {
+        let sum_of_decryption_shares = combine_shares(
+            self,
+            shares,
+            verification_keys,
+            threshold,
+            lagrange_cache,
+        )?;
 
         let mut hasher = Sha256::new();
-        hasher.update(sum_of_decryption_shares.compress().as_bytes());
+        hasher.update(C::element_to_bytes(&sum_of_decryption_shares));
         let decryption_share_bytes = hasher.finalize()[..]
             .try_into()
             .expect("Failed to compute hash during ID decryption");
 
         let decrypted_id = UserId(xor_bytes(decryption_share_bytes, &self.c_2));
 
+        // c_2 can't be proven to hold the same id as c_3, so the coordinator
+        // could have swapped it out
+        if let Some(c_3) = &self.c_3 {
+            if *c_3 - sum_of_decryption_shares != id_element::<C>(&decrypted_id)
+            {
+                return Err("ID encryption is inconsistent.".into());
+            }
+        }
+
         Ok(decrypted_id)
     }
 }
 
+impl<C: Ciphersuite> Ciphertext<C> for EncryptedUserId<C> {
+    fn c_1(&self) -> &C::Element {
+        &self.c_1
+    }
+}
+
+/// An ElGamal-style ciphertext whose first component is `c_1 = r * G`,
+/// so that it can be decrypted with the moderators' decryption shares.
+pub(crate) trait Ciphertext<C: Ciphersuite> {
+    fn c_1(&self) -> &C::Element;
+}
+
+/// Combines the first `threshold` valid shares from distinct moderators
+/// into `r * PK`, where `x_1.c_1() = r * G`. Shares are selected as
+/// described in [`EncryptedUserId::decrypt_with_shares`].
+pub(crate) fn combine_shares<C: Ciphersuite, T: Ciphertext<C>>(
+    x_1: &T,
+    shares: &[DecryptionShare<C>],
+    verification_keys: &HashMap<u16, VerificationKey<C>>,
+    threshold: usize,
+    lagrange_cache: &LagrangeCache<C>,
+) -> Result<C::Element> {
+    let mut valid_shares: Vec<&DecryptionShare<C>> =
+        Vec::with_capacity(threshold);
+    let mut invalid_moderators = Vec::new();
+
+    for share in shares {
+        if valid_shares.len() == threshold {
+            break;
+        }
+
+        let is_duplicate = valid_shares
+            .iter()
+            .any(|valid| valid.identifier == share.identifier);
+        if is_duplicate {
+            continue;
+        }
+
+        match verification_keys.get(&share.identifier) {
+            Some(key) if share.verify(x_1, key) => valid_shares.push(share),
+            _ if !invalid_moderators.contains(&share.identifier) => {
+                invalid_moderators.push(share.identifier)
+            }
+            _ => {}
+        }
+    }
+
+    if valid_shares.is_empty() || valid_shares.len() < threshold {
+        return Err(DecryptionError::NotEnoughShares {
+            valid: valid_shares.len(),
+            threshold,
+            invalid_moderators,
+        }
+        .into());
+    }
+
+    // the same moderators should hit the same cache entry whatever order
+    // their shares arrived in
+    valid_shares.sort_by_key(|share| share.identifier);
+    let identifiers: Vec<_> =
+        valid_shares.iter().map(|share| share.identifier).collect();
+    let shares: Vec<_> = valid_shares.iter().map(|share| share.share).collect();
+
+    Ok(interpolate::<C>(&identifiers, &shares, lagrange_cache))
+}
+
+/// Computes `Σ λ_i * shares[i]`, where `λ_i` is the Lagrange coefficient of
+/// `identifiers[i]`, with a single multiscalar multiplication.
+///
+/// This runs in variable time, which is fine since the coefficients only
+/// depend on the public identifiers.
+pub(crate) fn interpolate<C: Ciphersuite>(
+    identifiers: &[u16],
+    shares: &[C::Element],
+    lagrange_cache: &LagrangeCache<C>,
+) -> C::Element {
+    C::vartime_multiscalar_mul(
+        &lagrange_cache.coefficients(identifiers),
+        shares,
+    )
+}
+
+impl<C: Ciphersuite> DecryptionShare<C> {
+    /// Checks the share's proof against its sender's verification key.
+    pub(crate) fn verify<T: Ciphertext<C>>(
+        &self,
+        x_1: &T,
+        verification_key: &VerificationKey<C>,
+    ) -> bool {
+        self.proof
+            .verify(&verification_key.0, x_1.c_1(), &self.share)
+    }
+}
+
+impl<C: Ciphersuite> DleqProof<C> {
+    /// Proves that `a = secret * G` and `c = secret * b`.
+    fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        secret: &C::Scalar,
+        a: &C::Element,
+        b: &C::Element,
+        c: &C::Element,
+    ) -> Self {
+        let nonce = C::random_scalar(rng);
+        let challenge =
+            Self::challenge(a, b, c, &C::base_mul(&nonce), &(nonce * *b));
+
+        Self {
+            challenge,
+            response: nonce + challenge * *secret,
+        }
+    }
+
+    fn verify(&self, a: &C::Element, b: &C::Element, c: &C::Element) -> bool {
+        // recompute the prover's commitments from the challenge and response
+        let commitment_g = C::base_mul(&self.response) - self.challenge * *a;
+        let commitment_b = self.response * *b - self.challenge * *c;
+
+        self.challenge == Self::challenge(a, b, c, &commitment_g, &commitment_b)
+    }
+
+    fn challenge(
+        a: &C::Element,
+        b: &C::Element,
+        c: &C::Element,
+        commitment_g: &C::Element,
+        commitment_b: &C::Element,
+    ) -> C::Scalar {
+        C::hash_to_scalar(&[
+            b"cerberus-decryption-share-dleq",
+            C::element_to_bytes(a).as_ref(),
+            C::element_to_bytes(b).as_ref(),
+            C::element_to_bytes(c).as_ref(),
+            C::element_to_bytes(commitment_g).as_ref(),
+            C::element_to_bytes(commitment_b).as_ref(),
+        ])
+    }
+}
+
 /// In practice, this would be done in a distributed fashion without a
-/// trusted central party.
-pub(crate) fn generate_private_key_shares<R: RngCore + CryptoRng>(
+/// trusted central party (see [`crate::dkg`]).
+///
+/// Also returns Feldman commitments to the polynomial, which allow each
+/// moderator to check its share with [`KeyShare::verify`].
+pub(crate) fn generate_private_key_shares<
+    C: Ciphersuite,
+    R: RngCore + CryptoRng,
+>(
     rng: &mut R,
     n_shares: usize,
     decryption_threshold: usize,
-) -> (PublicKey, Vec<KeyShare>) {
+) -> (PublicKey<C>, Vec<KeyShare<C>>, Commitments<C>) {
+    assert!(
+        n_shares <= u16::MAX as usize,
+        "Too many shares for 16-bit identifiers."
+    );
+
     // secret to be split up
-    let sk = Scalar::random(rng);
+    let mut sk = C::random_scalar(rng);
 
-    // generate random polynomial coefficients
-    // to be used in Shamir secret sharing
-    let mut polynomial_coefficients = vec![sk];
-    for _ in 1..decryption_threshold {
-        polynomial_coefficients.push(Scalar::random(rng))
-    }
+    // the polynomial that will be interpolated to recover the secret
+    let polynomial = Polynomial::<C>::random(rng, sk, decryption_threshold);
 
-    // define the polynomial that will be interpolated
-    // to recover the secret
-    // f(x) = a_0 + a_1 x + a_2 x^2 + ... + a_{k-1} x^{k-1}
-    let f = |x: u64| {
-        polynomial_coefficients
-            .iter()
-            .zip(0..)
-            .map(move |(a, k)|             a * Scalar::from(
-                x.checked_pow(k)
-                .expect("Exponentiation overflow during computation of Shamir polynomial."))
-            )
-            .sum()
-    };
-
-    let pk = PublicKey(&sk * &RISTRETTO_BASEPOINT_TABLE);
+    let pk = PublicKey(C::base_mul(&sk));
+    let commitments = polynomial.commitments();
 
-    // generate shares `(x, f(x))` for `x = 1..N_MODERATORS`
+    // generate shares `(x, f(x))` for `x = 1..=n_shares`
     let sk_shares = (1..=n_shares)
         .map(|i| KeyShare {
-            identifier: Scalar::from(i as u64),
-            sk: f(i as u64),
+            identifier: i as u16,
+            sk: polynomial.evaluate(&C::scalar_from_u64(i as u64)),
             pk,
         })
         .collect();
 
-    (pk, sk_shares)
+    sk.zeroize();
+
+    (pk, sk_shares, commitments)
+}
+
+/// The second generator `G_bar` used by TDH2, whose discrete log
+/// with respect to `G` is unknown.
+fn second_generator<C: Ciphersuite>() -> C::Element {
+    C::hash_to_element(b"cerberus-tdh2-generator")
+}
+
+/// The generator `H` of the blinding in an [`IdCommitment`], whose discrete
+/// log with respect to `G` is unknown.
+fn commitment_generator<C: Ciphersuite>() -> C::Element {
+    C::hash_to_element(b"cerberus-id-commitment-generator")
+}
+
+/// Maps an id to the group element `M` encrypted in `c_3`.
+fn id_element<C: Ciphersuite>(user_id: &UserId) -> C::Element {
+    let mut input = b"cerberus-id-element".to_vec();
+    input.extend(user_id.0);
+
+    C::hash_to_element(&input)
 }
 
-fn xor_bytes(mut a: [u8; 32], b: &[u8; 32]) -> [u8; 32] {
+pub(crate) fn xor_bytes(mut a: [u8; 32], b: &[u8; 32]) -> [u8; 32] {
     for i in 0..32 {
         a[i] ^= b[i]
     }
@@ -156,36 +831,39 @@ fn xor_bytes(mut a: [u8; 32], b: &[u8; 32]) -> [u8; 32] {
     a
 }
 
-/// Calculate the lagrange coefficients for decryption shares.
-/// The length of `other_identifiers` MUST be equal to the decryption threshold.
-pub(crate) fn lagrange_coefficient(
-    identifier: &Scalar,
-    other_identifiers: &[Scalar],
-) -> Scalar {
-    other_identifiers
-        .iter()
-        .filter(|other| *other != identifier) // take the product over all i != j
-        .map(|other| other * (other - identifier).invert())
-        .product()
-}
-
 #[cfg(test)]
 mod tests {
     use curve25519_dalek::scalar::Scalar;
     use rand::Rng;
+    use std::collections::HashMap;
 
-    use crate::UserId;
+    use crate::{
+        ciphersuite::{Ciphersuite, Ed25519, Ristretto255},
+        shamir::LagrangeCache,
+        Result, UserId,
+    };
 
-    use super::generate_private_key_shares;
+    use super::{
+        generate_private_key_shares, DecryptionError, IdCommitment,
+        IdEncryption, KeyShare, PublicKey, VerificationKey,
+    };
 
-    #[test]
-    fn test_decryption() {
+    fn verification_keys<C: Ciphersuite>(
+        shares: &[KeyShare<C>],
+    ) -> HashMap<u16, VerificationKey<C>> {
+        shares
+            .iter()
+            .map(|share| (share.identifier, share.verification_key()))
+            .collect()
+    }
+
+    fn decryption<C: Ciphersuite>() {
         let n_shares = 7;
         let decryption_threshold = 4;
 
         let mut rng = rand::thread_rng();
 
-        let (pk, shares) = generate_private_key_shares(
+        let (pk, shares, _) = generate_private_key_shares::<C, _>(
             &mut rng,
             n_shares,
             decryption_threshold,
@@ -193,17 +871,392 @@ mod tests {
 
         let id = UserId(rng.gen());
 
-        let x_1 = pk.encrypt(&id, &Scalar::random(&mut rng));
+        let x_1 = pk.encrypt(&id, &C::random_scalar(&mut rng));
 
         let decryption_shares: Vec<_> = shares[..decryption_threshold]
             .iter()
-            .map(|share| share.decryption_share(&x_1))
+            .map(|share| share.decryption_share(&x_1, &mut rng))
             .collect();
 
-        let id_decrypted = x_1.decrypt_with_shares(&decryption_shares);
+        let id_decrypted = x_1.decrypt_with_shares(
+            &decryption_shares,
+            &verification_keys(&shares),
+            decryption_threshold,
+        );
 
         assert!(id_decrypted.is_ok(), "Unable to decrypt id");
 
         assert_eq!(id, id_decrypted.unwrap(), "Decrypted id is incorrect");
     }
+
+    #[test]
+    fn test_decryption() {
+        decryption::<Ristretto255>()
+    }
+
+    #[test]
+    fn test_ed25519_decryption() {
+        decryption::<Ed25519>()
+    }
+
+    #[test]
+    fn test_large_committee_decryption() -> Result<()> {
+        let (n_shares, decryption_threshold) = (300, 200);
+        let mut rng = rand::thread_rng();
+
+        let (pk, shares, _) = generate_private_key_shares::<Ristretto255, _>(
+            &mut rng,
+            n_shares,
+            decryption_threshold,
+        );
+
+        let id = UserId(rng.gen());
+        let x_1 = pk.encrypt(&id, &Scalar::random(&mut rng));
+
+        let decryption_shares: Vec<_> = shares
+            [n_shares - decryption_threshold..]
+            .iter()
+            .map(|share| share.decryption_share(&x_1, &mut rng))
+            .collect();
+
+        assert_eq!(
+            id,
+            x_1.decrypt_with_shares(
+                &decryption_shares,
+                &verification_keys(&shares),
+                decryption_threshold
+            )?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_decryption() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let (pk, shares, _) =
+            generate_private_key_shares::<Ristretto255, _>(&mut rng, 7, 4);
+        let verification_keys = verification_keys(&shares);
+        let cache = LagrangeCache::default();
+
+        // the same moderators answering in a different order reuse the
+        // cached coefficients
+        for decryption_order in [[1, 4, 5, 6], [6, 5, 4, 1], [4, 1, 6, 5]] {
+            let id = UserId(rng.gen());
+            let x_1 = pk.encrypt(&id, &Scalar::random(&mut rng));
+
+            let decryption_shares: Vec<_> = decryption_order
+                .iter()
+                .map(|i| shares[*i].decryption_share(&x_1, &mut rng))
+                .collect();
+
+            assert_eq!(
+                id,
+                x_1.decrypt_with_cached_coefficients(
+                    &decryption_shares,
+                    &verification_keys,
+                    4,
+                    &cache,
+                )?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_decryption_share() {
+        let mut rng = rand::thread_rng();
+        let (pk, shares, _) =
+            generate_private_key_shares::<Ristretto255, _>(&mut rng, 5, 3);
+
+        let x_1 = pk.encrypt(&UserId(rng.gen()), &Scalar::random(&mut rng));
+
+        let mut decryption_shares: Vec<_> = shares[..3]
+            .iter()
+            .map(|share| share.decryption_share(&x_1, &mut rng))
+            .collect();
+
+        // moderator 2 sends a share computed with the wrong secret
+        let other_share = decryption_shares[0].share;
+        decryption_shares[1].share += other_share;
+
+        let err = x_1
+            .decrypt_with_shares(
+                &decryption_shares,
+                &verification_keys(&shares),
+                3,
+            )
+            .expect_err("Decryption succeeded with an invalid share");
+
+        assert_eq!(
+            err.downcast_ref::<DecryptionError>(),
+            Some(&DecryptionError::NotEnoughShares {
+                valid: 2,
+                threshold: 3,
+                invalid_moderators: vec![2],
+            })
+        );
+    }
+
+    #[test]
+    fn test_robust_decryption() -> crate::Result<()> {
+        let mut rng = rand::thread_rng();
+        let (pk, shares, _) =
+            generate_private_key_shares::<Ristretto255, _>(&mut rng, 7, 4);
+
+        let id = UserId(rng.gen());
+        let x_1 = pk.encrypt(&id, &Scalar::random(&mut rng));
+
+        let mut decryption_shares: Vec<_> = shares
+            .iter()
+            .map(|share| share.decryption_share(&x_1, &mut rng))
+            .collect();
+
+        // moderator 1 sends a bad share, and moderator 3's share is repeated
+        let other_share = decryption_shares[1].share;
+        decryption_shares[0].share += other_share;
+        decryption_shares.insert(2, decryption_shares[2].clone());
+
+        let id_decrypted = x_1.decrypt_with_shares(
+            &decryption_shares,
+            &verification_keys(&shares),
+            4,
+        )?;
+
+        assert_eq!(id, id_decrypted, "Decrypted id is incorrect");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_share_verification() {
+        let mut rng = rand::thread_rng();
+        let (_, shares, commitments) =
+            generate_private_key_shares::<Ristretto255, _>(&mut rng, 5, 3);
+
+        assert!(shares
+            .iter()
+            .all(|share| share.verify(&commitments).is_ok()));
+
+        let mut bad_share = shares[0].clone();
+        bad_share.sk += Scalar::from(1u64);
+        assert!(bad_share.verify(&commitments).is_err());
+
+        let mut bad_share = shares[0].clone();
+        bad_share.pk = PublicKey(shares[0].verification_key().0);
+        assert!(bad_share.verify(&commitments).is_err());
+    }
+
+    #[test]
+    fn test_key_share_debug_is_redacted() {
+        let mut rng = rand::thread_rng();
+        let (_, shares, _) =
+            generate_private_key_shares::<Ristretto255, _>(&mut rng, 3, 2);
+
+        let debug = format!("{:?}", shares[0]);
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains(&format!("{:?}", shares[0].sk)));
+    }
+
+    fn labeled_encryption<C: Ciphersuite>() -> Result<()> {
+        let (n_shares, decryption_threshold) = (5, 3);
+        let mut rng = rand::thread_rng();
+
+        let (pk, shares, _) = generate_private_key_shares::<C, _>(
+            &mut rng,
+            n_shares,
+            decryption_threshold,
+        );
+
+        let id = UserId(rng.gen());
+        let randomness = C::random_scalar(&mut rng);
+        let x_1 = pk.encrypt(&id, &randomness).labeled(
+            &pk,
+            &id,
+            &randomness,
+            b"label",
+        );
+
+        x_1.verify_label(&pk, b"label")?;
+        assert!(x_1.is_encryption_of(&pk, &id, &randomness, b"label"));
+
+        let decryption_shares: Vec<_> = shares[..decryption_threshold]
+            .iter()
+            .map(|share| share.decryption_share(&x_1, &mut rng))
+            .collect();
+        assert_eq!(
+            id,
+            x_1.decrypt_with_shares(
+                &decryption_shares,
+                &verification_keys(&shares),
+                decryption_threshold
+            )?
+        );
+
+        // the ciphertext is bound to its label
+        assert!(x_1.verify_label(&pk, b"other label").is_err());
+
+        // flipping bits of the id invalidates the proof
+        let mut mauled = x_1.clone();
+        mauled.c_2[0] ^= 1;
+        assert!(mauled.verify_label(&pk, b"label").is_err());
+
+        // as does swapping in another ciphertext's c_1
+        let mut mauled = x_1.clone();
+        mauled.c_1 = pk.encrypt(&id, &C::random_scalar(&mut rng)).c_1;
+        assert!(mauled.verify_label(&pk, b"label").is_err());
+
+        // the proof is bound to the public key too
+        let (other_pk, _, _) = generate_private_key_shares::<C, _>(
+            &mut rng,
+            n_shares,
+            decryption_threshold,
+        );
+        assert!(x_1.verify_label(&other_pk, b"label").is_err());
+
+        // and plain ciphertexts have no proof at all
+        let plain = pk.encrypt(&id, &randomness);
+        assert!(plain.verify_label(&pk, b"label").is_err());
+
+        // which is only allowed when labels aren't required
+        x_1.check_policy(IdEncryption::Labeled, &pk, b"label")?;
+        x_1.check_policy(IdEncryption::Hashed, &pk, b"label")?;
+        assert!(x_1
+            .check_policy(IdEncryption::Hashed, &pk, b"other label")
+            .is_err());
+        plain.check_policy(IdEncryption::Hashed, &pk, b"label")?;
+        assert!(plain
+            .check_policy(IdEncryption::Labeled, &pk, b"label")
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_labeled_encryption() -> Result<()> {
+        labeled_encryption::<Ristretto255>()
+    }
+
+    #[test]
+    fn test_ed25519_labeled_encryption() -> Result<()> {
+        labeled_encryption::<Ed25519>()
+    }
+
+    fn committed_id<C: Ciphersuite>() -> Result<()> {
+        let (n_shares, decryption_threshold) = (5, 3);
+        let mut rng = rand::thread_rng();
+
+        let (pk, shares, _) = generate_private_key_shares::<C, _>(
+            &mut rng,
+            n_shares,
+            decryption_threshold,
+        );
+
+        let id = UserId(rng.gen());
+        let randomness = C::random_scalar(&mut rng);
+        let blinding = C::random_scalar(&mut rng);
+        let commitment = IdCommitment::new(&id, &blinding);
+
+        let x_1 = pk.encrypt_provable(&id, &randomness).labeled(
+            &pk,
+            &id,
+            &randomness,
+            b"label",
+        );
+        assert!(x_1.is_encryption_of(&pk, &id, &randomness, b"label"));
+        x_1.verify_label(&pk, b"label")?;
+
+        let proof = x_1.prove_committed_id(
+            &pk,
+            &commitment,
+            &randomness,
+            &blinding,
+            b"token",
+            &mut rng,
+        )?;
+        x_1.verify_committed_id(&pk, &commitment, &proof, b"token")?;
+
+        // the proof is bound to its context
+        assert!(x_1
+            .verify_committed_id(&pk, &commitment, &proof, b"other token")
+            .is_err());
+
+        // and to the committed id
+        let other_commitment = IdCommitment::new(&UserId(rng.gen()), &blinding);
+        assert!(x_1
+            .verify_committed_id(&pk, &other_commitment, &proof, b"token")
+            .is_err());
+
+        // nor can a ciphertext of another id be proven to match
+        let other_x_1 = pk.encrypt_provable(&UserId(rng.gen()), &randomness);
+        let forged_proof = other_x_1.prove_committed_id(
+            &pk,
+            &commitment,
+            &randomness,
+            &blinding,
+            b"token",
+            &mut rng,
+        )?;
+        assert!(other_x_1
+            .verify_committed_id(&pk, &commitment, &forged_proof, b"token")
+            .is_err());
+
+        // plain ciphertexts can't be proven to match at all
+        let plain = pk.encrypt(&id, &randomness);
+        assert!(plain
+            .prove_committed_id(
+                &pk,
+                &commitment,
+                &randomness,
+                &blinding,
+                b"token",
+                &mut rng
+            )
+            .is_err());
+        assert!(plain
+            .verify_committed_id(&pk, &commitment, &proof, b"token")
+            .is_err());
+
+        // the id decrypts as usual
+        let decryption_shares: Vec<_> = shares[..decryption_threshold]
+            .iter()
+            .map(|share| share.decryption_share(&x_1, &mut rng))
+            .collect();
+        assert_eq!(
+            id,
+            x_1.decrypt_with_shares(
+                &decryption_shares,
+                &verification_keys(&shares),
+                decryption_threshold
+            )?
+        );
+
+        // but not if c_2 holds a different id than c_3
+        let mut swapped = pk.encrypt_provable(&id, &randomness);
+        swapped.c_2 = pk.encrypt(&UserId(rng.gen()), &randomness).c_2;
+        let decryption_shares: Vec<_> = shares[..decryption_threshold]
+            .iter()
+            .map(|share| share.decryption_share(&swapped, &mut rng))
+            .collect();
+        assert!(swapped
+            .decrypt_with_shares(
+                &decryption_shares,
+                &verification_keys(&shares),
+                decryption_threshold
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_committed_id() -> Result<()> {
+        committed_id::<Ristretto255>()
+    }
+
+    #[test]
+    fn test_ed25519_committed_id() -> Result<()> {
+        committed_id::<Ed25519>()
+    }
 }
@@ -0,0 +1,293 @@
+//! Long-term moderator identity keys.
+//!
+//! Every message between moderators is relayed by the coordinator, so
+//! moderators sign the public values they broadcast (encryption keys and
+//! commitments) with their identity keys. Otherwise the coordinator could
+//! substitute its own encryption key for a moderator's, learn every share
+//! sent to it, and re-encrypt them so that nobody notices.
+//!
+//! The roster of identity keys MUST be distributed to the moderators
+//! out-of-band, e.g., when they are deployed, since the coordinator isn't
+//! trusted to relay it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    dkg::ProofOfKnowledge,
+    ModeratorId, Result,
+};
+
+/// A moderator's identity key along with the identity keys of the
+/// other moderators in its committee.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct ModeratorIdentity<C: Ciphersuite = Ristretto255> {
+    identifier: ModeratorId,
+    secret: C::Scalar,
+
+    /// Every moderator's public identity key, including this one's.
+    roster: HashMap<ModeratorId, C::Element>,
+}
+
+impl<C: Ciphersuite> ModeratorIdentity<C> {
+    /// Generates identities for a committee of moderators `1..=n_moderators`
+    /// that all share the same roster.
+    pub fn generate_committee<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        n_moderators: usize,
+    ) -> Vec<Self> {
+        let secrets: Vec<_> = ModeratorId::all(n_moderators)
+            .map(|moderator| (moderator, C::random_scalar(rng)))
+            .collect();
+
+        let roster: HashMap<_, _> = secrets
+            .iter()
+            .map(|(moderator, secret)| (*moderator, C::base_mul(secret)))
+            .collect();
+
+        secrets
+            .into_iter()
+            .map(|(identifier, secret)| Self {
+                identifier,
+                secret,
+                roster: roster.clone(),
+            })
+            .collect()
+    }
+
+    /// This moderator's id within its committee.
+    pub fn identifier(&self) -> ModeratorId {
+        self.identifier
+    }
+
+    /// Writes the identity to disk.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, bincode::serialize(self)?)?;
+
+        Ok(())
+    }
+
+    /// Reads an identity previously written with [`ModeratorIdentity::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(bincode::deserialize(&fs::read(path)?)?)
+    }
+
+    /// Signs `message` on behalf of this moderator.
+    pub(crate) fn sign<T: Serialize, R: CryptoRng + RngCore>(
+        &self,
+        rng: &mut R,
+        label: &[u8],
+        message: T,
+    ) -> Result<Signed<C, T>> {
+        let context = signed_bytes(label, self.identifier, &message)?;
+
+        Ok(Signed {
+            signer: self.identifier,
+            signature: ProofOfKnowledge::new(rng, &self.secret, &context),
+            message,
+        })
+    }
+
+    /// Checks that `signed` was signed by the moderator that it claims to be
+    /// from, according to this moderator's roster.
+    pub(crate) fn verify<'a, T: Serialize>(
+        &self,
+        label: &[u8],
+        signed: &'a Signed<C, T>,
+    ) -> Result<&'a T> {
+        let public = self.roster.get(&signed.signer).ok_or_else(|| {
+            format!("Moderator {} is unknown.", signed.signer)
+        })?;
+
+        let context = signed_bytes(label, signed.signer, &signed.message)?;
+        match signed.signature.verify(public, &context) {
+            true => Ok(&signed.message),
+            false => Err(format!(
+                "Moderator {} sent an invalid signature.",
+                signed.signer
+            )
+            .into()),
+        }
+    }
+
+    /// Checks that every moderator in the roster signed the same `expected`
+    /// message, e.g., to make sure that everyone was sent the same public
+    /// values.
+    pub(crate) fn verify_unanimous<T: Serialize + PartialEq>(
+        &self,
+        label: &[u8],
+        signed: &[Signed<C, T>],
+        expected: &T,
+    ) -> Result<()> {
+        let roster: Vec<_> = self.roster.keys().copied().collect();
+
+        self.verify_unanimous_among(label, signed, expected, &roster)
+    }
+
+    /// Like [`Self::verify_unanimous`], but for when only `moderators`, e.g.,
+    /// the old committee in a reshare, have to sign off.
+    pub(crate) fn verify_unanimous_among<T: Serialize + PartialEq>(
+        &self,
+        label: &[u8],
+        signed: &[Signed<C, T>],
+        expected: &T,
+        moderators: &[ModeratorId],
+    ) -> Result<()> {
+        let mut signers = HashSet::with_capacity(signed.len());
+        for signed in signed {
+            if self.verify(label, signed)? != expected {
+                return Err(format!(
+                    "Moderator {} disagrees with this moderator.",
+                    signed.signer
+                )
+                .into());
+            }
+
+            if !moderators.contains(&signed.signer) {
+                return Err(format!(
+                    "Moderator {} wasn't asked to sign off.",
+                    signed.signer
+                )
+                .into());
+            }
+
+            signers.insert(signed.signer);
+        }
+
+        match moderators
+            .iter()
+            .all(|moderator| signers.contains(moderator))
+        {
+            true => Ok(()),
+            false => Err("Not every moderator signed off.".into()),
+        }
+    }
+}
+
+impl<C: Ciphersuite> Drop for ModeratorIdentity<C> {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+/// A message signed with a moderator's identity key.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "T: Serialize + DeserializeOwned")]
+pub(crate) struct Signed<C: Ciphersuite, T> {
+    pub(crate) signer: ModeratorId,
+    pub(crate) message: T,
+    signature: ProofOfKnowledge<C>,
+}
+
+/// The bytes signed for `message`, bound to what it is and who sent it.
+fn signed_bytes<T: Serialize>(
+    label: &[u8],
+    signer: ModeratorId,
+    message: &T,
+) -> Result<Vec<u8>> {
+    let mut bytes = b"cerberus-moderator-identity".to_vec();
+    bytes.extend(label);
+    bytes.extend(u16::from(signer).to_be_bytes());
+    bytes.extend(bincode::serialize(message)?);
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModeratorIdentity;
+    use crate::{ciphersuite::Ristretto255, Result};
+
+    #[test]
+    fn test_signatures() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let identities =
+            ModeratorIdentity::<Ristretto255>::generate_committee(&mut rng, 3);
+
+        let signed = identities[0].sign(&mut rng, b"test", 42u64)?;
+        assert_eq!(*identities[1].verify(b"test", &signed)?, 42);
+
+        // signatures are bound to their label, message, and signer
+        assert!(identities[1].verify(b"other", &signed).is_err());
+
+        let mut altered = signed.clone();
+        altered.message = 43;
+        assert!(identities[1].verify(b"test", &altered).is_err());
+
+        let mut misattributed = signed;
+        misattributed.signer = identities[2].identifier();
+        assert!(identities[1].verify(b"test", &misattributed).is_err());
+
+        // moderators outside the committee aren't trusted
+        let outsider =
+            &ModeratorIdentity::<Ristretto255>::generate_committee(&mut rng, 1)
+                [0];
+        let signed = outsider.sign(&mut rng, b"test", 42u64)?;
+        assert!(identities[0].verify(b"test", &signed).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unanimous_signatures() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let identities =
+            ModeratorIdentity::<Ristretto255>::generate_committee(&mut rng, 3);
+
+        let signed = identities
+            .iter()
+            .map(|identity| identity.sign(&mut rng, b"test", 42u64))
+            .collect::<Result<Vec<_>>>()?;
+        identities[0].verify_unanimous(b"test", &signed, &42)?;
+
+        // everyone has to agree
+        assert!(identities[0]
+            .verify_unanimous(b"test", &signed, &43)
+            .is_err());
+
+        let mut disagreeing = signed.clone();
+        disagreeing[2] = identities[2].sign(&mut rng, b"test", 43u64)?;
+        assert!(identities[0]
+            .verify_unanimous(b"test", &disagreeing, &42)
+            .is_err());
+
+        // and nobody can be left out or counted twice
+        assert!(identities[0]
+            .verify_unanimous(b"test", &signed[..2], &42)
+            .is_err());
+
+        let mut repeated = signed.clone();
+        repeated[2] = repeated[1].clone();
+        assert!(identities[0]
+            .verify_unanimous(b"test", &repeated, &42)
+            .is_err());
+
+        // only the moderators asked to sign off count
+        let moderators =
+            [identities[0].identifier(), identities[2].identifier()];
+        let among = [signed[0].clone(), signed[2].clone()];
+        identities[1].verify_unanimous_among(
+            b"test",
+            &among,
+            &42,
+            &moderators,
+        )?;
+        assert!(identities[1]
+            .verify_unanimous_among(b"test", &signed, &42, &moderators)
+            .is_err());
+        assert!(identities[1]
+            .verify_unanimous_among(b"test", &among[..1], &42, &moderators)
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -1,12 +1,44 @@
+use frost_core::frost::Identifier;
 use rand::{CryptoRng, Rng, RngCore};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
+#[doc(hidden)]
+pub mod bench;
+mod ciphersuite;
 mod communication;
+mod dkg;
 mod elgamal;
+mod identity;
+mod message;
+mod payload;
+mod refresh;
+mod reshare;
 mod roles;
+mod shamir;
 mod token;
+mod wallet;
 
-pub use roles::{coordinator::Coordinator, moderator::Moderator};
+pub use ciphersuite::{Ciphersuite, Ed25519, Encoding, Ristretto255};
+pub use elgamal::{
+    DecryptionError, IdEncryption, PublicKey as ElGamalPublicKey,
+};
+pub use identity::ModeratorIdentity;
+pub use message::{Envelope, PlatformStamp};
+pub use payload::{Payload, PAYLOAD_VERSION};
+pub use roles::{
+    client::Client,
+    coordinator::{
+        Committee, Coordinator, InvalidSharePolicy, KeyGeneration,
+        SigningError, SigningMode,
+    },
+    moderator::Moderator,
+    platform::Platform,
+};
+pub use token::{
+    EphemeralSecretKey, SignedToken, TimestampPolicy, TokenRequest,
+};
+pub use wallet::TokenWallet;
 
 /// Wrapper type for an
 #[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
@@ -18,7 +50,72 @@ impl UserId {
     }
 }
 
-pub type UserPublicKey = [u8; 32];
+/// The (serialized) ephemeral key `pk_e` embedded in a token.
+pub type UserPublicKey<C = Ristretto255> = <C as Ciphersuite>::ElementBytes;
+
+/// Identifies a moderator within its committee, from `1` to the number of
+/// moderators.
+///
+/// Moderator `i` holds the signing and decryption key shares at `x = i`, so
+/// the same id is both its FROST [`Identifier`] and its ElGamal share
+/// identifier.
+#[derive(
+    Deserialize,
+    Serialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+)]
+#[serde(try_from = "u16", into = "u16")]
+pub struct ModeratorId(u16);
+
+impl ModeratorId {
+    /// The ids of a committee of `n_moderators` moderators, in order.
+    pub fn all(n_moderators: usize) -> impl Iterator<Item = Self> {
+        assert!(n_moderators <= u16::MAX as usize);
+
+        (1..=n_moderators as u16).map(Self)
+    }
+}
+
+impl TryFrom<u16> for ModeratorId {
+    type Error = Box<dyn std::error::Error>;
+
+    /// Fails if `id` is zero, since shares are never dealt at `x = 0`.
+    fn try_from(id: u16) -> Result<Self> {
+        if id == 0 {
+            return Err("Moderator ids must be non-zero.".into());
+        }
+
+        Ok(Self(id))
+    }
+}
+
+impl From<ModeratorId> for u16 {
+    fn from(id: ModeratorId) -> Self {
+        id.0
+    }
+}
+
+impl<C: frost_core::Ciphersuite> From<ModeratorId> for Identifier<C> {
+    fn from(id: ModeratorId) -> Self {
+        Identifier::try_from(id.0).expect("Moderator ids are non-zero.")
+    }
+}
+
+impl fmt::Display for ModeratorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The (serialized) key used by the platform to stamp delivered messages.
+pub type PlatformPublicKey<C = Ristretto255> = <C as Ciphersuite>::ElementBytes;
 
 /// Wrapper a single batch of something in the protocol, e.g.,
 /// a batch of signature shares sent by a moderator to the coordinator.
@@ -27,3 +124,30 @@ type Batch<T> = Vec<T>;
 /// Convenience type to avoid typing this out
 /// for every function signature
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[cfg(test)]
+mod tests {
+    use super::{ModeratorId, Result};
+    use frost_core::frost::Identifier;
+
+    type Frost = <crate::Ristretto255 as crate::Ciphersuite>::Frost;
+
+    #[test]
+    fn test_moderator_id_conversions() -> Result<()> {
+        assert!(ModeratorId::try_from(0).is_err());
+
+        for (i, id) in (1..).zip(ModeratorId::all(5)) {
+            assert_eq!(ModeratorId::try_from(i)?, id);
+            assert_eq!(u16::from(id), i);
+            assert!(Identifier::<Frost>::from(id) == Identifier::try_from(i)?);
+        }
+
+        // zero is rejected when deserializing too
+        assert!(bincode::deserialize::<ModeratorId>(&[0, 0]).is_err());
+        let id: ModeratorId =
+            bincode::deserialize(&bincode::serialize(&3u16)?)?;
+        assert_eq!(u16::from(id), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    token::{EphemeralSecretKey, SignedToken},
+    PlatformPublicKey, Result,
+};
+use frost_core::{Signature, VerifyingKey};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+/// The reporting metadata that travels alongside a message.
+///
+/// Binds the sender's token to the message via
+/// `x_2 = x_1 ⊕ H(m)` and the sender signature `σ_src = Sign_{sk_e}(x_2)`,
+/// as in Hecate. The plaintext itself is not included and must be supplied
+/// when verifying.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct Envelope<C: Ciphersuite = Ristretto255> {
+    pub(crate) token: SignedToken<C>,
+    pub(crate) x_2: Vec<u8>,
+    pub(crate) sender_signature: Signature<C::Frost>,
+
+    /// Added by the platform when it delivers the message.
+    pub(crate) stamp: Option<PlatformStamp<C>>,
+}
+
+/// The platform's attestation that an envelope was delivered at `timestamp`.
+///
+/// `signature` is made with the platform's key over `x_2 || timestamp`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct PlatformStamp<C: Ciphersuite = Ristretto255> {
+    pub(crate) timestamp: i64,
+    pub(crate) signature: Signature<C::Frost>,
+}
+
+impl<C: Ciphersuite> PlatformStamp<C> {
+    /// The bytes signed by the platform when stamping an envelope.
+    pub(crate) fn signed_bytes(x_2: &[u8], timestamp: i64) -> Vec<u8> {
+        let mut bytes = x_2.to_vec();
+        bytes.extend(timestamp.to_be_bytes());
+
+        bytes
+    }
+}
+
+impl<C: Ciphersuite> Envelope<C> {
+    /// Consumes `token` to create the metadata for sending `message`.
+    ///
+    /// `sk_e` MUST be the ephemeral secret key that the token was requested with.
+    pub fn seal(
+        token: SignedToken<C>,
+        sk_e: &EphemeralSecretKey<C>,
+        message: &[u8],
+    ) -> Result<Self> {
+        if sk_e.public_key() != token.token.pk_e {
+            return Err(
+                "Ephemeral secret key does not match the token's public key."
+                    .into(),
+            );
+        }
+
+        let x_2 = compute_x_2(&token, message);
+        let sender_signature = sk_e.sign(&x_2);
+
+        Ok(Self {
+            token,
+            x_2,
+            sender_signature,
+            stamp: None,
+        })
+    }
+
+    /// Checks that the token is signed by the moderators and that the envelope
+    /// is bound to `message` by the token's ephemeral key.
+    pub fn verify(
+        &self,
+        message: &[u8],
+        group_public_key: &VerifyingKey<C::Frost>,
+    ) -> Result<()> {
+        self.token.verify(group_public_key)?;
+        self.verify_binding(message)
+    }
+
+    /// Checks that the envelope is bound to `message`, i.e., that `x_2` is
+    /// computed from the token and the hash of `message` and is signed with
+    /// the token's ephemeral key.
+    ///
+    /// This does not check the moderators' signature on the token.
+    pub fn verify_binding(&self, message: &[u8]) -> Result<()> {
+        let x_2 = compute_x_2(&self.token, message);
+        if !bool::from(x_2[..].ct_eq(&self.x_2[..])) {
+            return Err("Envelope is not bound to the given message.".into());
+        }
+
+        self.verify_sender_signature()
+    }
+
+    /// Checks that `x_2` is signed with the token's ephemeral key.
+    ///
+    /// Unlike [`Envelope::verify_binding`], this doesn't require the plaintext,
+    /// so it can be checked by the platform.
+    pub(crate) fn verify_sender_signature(&self) -> Result<()> {
+        let pk_e = C::verifying_key_from_bytes(self.token.token.pk_e)
+            .map_err(|_| "Token contains an invalid ephemeral public key.")?;
+
+        pk_e.verify(&self.x_2, &self.sender_signature)
+            .map_err(|_| "Sender signature is invalid.".into())
+    }
+
+    /// Checks that the envelope was stamped by the platform with the given key.
+    pub fn verify_stamp(
+        &self,
+        platform_key: &PlatformPublicKey<C>,
+    ) -> Result<()> {
+        let stamp = self
+            .stamp
+            .as_ref()
+            .ok_or("Envelope has not been stamped by the platform.")?;
+
+        let platform_key = C::verifying_key_from_bytes(*platform_key)
+            .map_err(|_| "Invalid platform public key.")?;
+
+        platform_key
+            .verify(
+                &PlatformStamp::<C>::signed_bytes(&self.x_2, stamp.timestamp),
+                &stamp.signature,
+            )
+            .map_err(|_| "Platform stamp is invalid.".into())
+    }
+
+    /// Re-wraps the envelope to forward `message` to a new recipient.
+    ///
+    /// The original token, `x_2`, and sender signature are carried over
+    /// unchanged, so reporting any forwarded copy still reveals the original
+    /// author. Fails if the envelope isn't bound to `message`, e.g., if the
+    /// content was altered before forwarding.
+    ///
+    /// The platform stamp is dropped since the forwarded copy is a new
+    /// delivery and must be stamped again.
+    pub fn forward(&self, message: &[u8]) -> Result<Self> {
+        self.verify_binding(message)?;
+
+        Ok(Self {
+            stamp: None,
+            ..self.clone()
+        })
+    }
+
+    /// The platform's stamp, if the envelope has been delivered.
+    pub fn stamp(&self) -> Option<&PlatformStamp<C>> {
+        self.stamp.as_ref()
+    }
+
+    /// The token that the message was sent with.
+    pub fn token(&self) -> &SignedToken<C> {
+        &self.token
+    }
+}
+
+/// Computes `x_2 = x_1 ⊕ H(m)`, where `x_1` is the encrypted sender id
+/// `(c_1, c_2)` and `H` is expanded with HKDF-SHA256 to the length of `x_1`.
+fn compute_x_2<C: Ciphersuite>(
+    token: &SignedToken<C>,
+    message: &[u8],
+) -> Vec<u8> {
+    let mut x_2 = token.token.x_1.to_bytes();
+
+    let mut message_hash = vec![0; x_2.len()];
+    Hkdf::<Sha256>::new(None, message)
+        .expand(b"cerberus-message-hash", &mut message_hash)
+        .expect("An encrypted id is a valid HKDF-SHA256 output length.");
+
+    for (x, h) in x_2.iter_mut().zip(&message_hash) {
+        *x ^= h;
+    }
+
+    x_2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Envelope;
+    use crate::{
+        ciphersuite::Ristretto255, token::tests::issue_token, Platform, Result,
+        UserId,
+    };
+
+    #[test]
+    fn test_envelope_verification() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let (token, sk_e, group_public_key) =
+            issue_token::<Ristretto255>(UserId::random(&mut rng))?;
+
+        let message = b"hello, world";
+        let envelope = Envelope::seal(token, &sk_e, message)?;
+
+        assert!(
+            envelope.verify(message, &group_public_key).is_ok(),
+            "Valid envelope failed to verify."
+        );
+
+        assert!(
+            envelope
+                .verify(b"some other message", &group_public_key)
+                .is_err(),
+            "Envelope verified against the wrong message."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_x_2_masks_x_1() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let message = b"hello, world";
+
+        // x_2 ⊕ x_1 = H(m) doesn't depend on the token
+        let masks = (0..2)
+            .map(|_| {
+                let (token, sk_e, _) =
+                    issue_token::<Ristretto255>(UserId::random(&mut rng))?;
+                let envelope = Envelope::seal(token, &sk_e, message)?;
+                let x_1 = envelope.token.token.x_1.to_bytes();
+                assert_eq!(x_1.len(), envelope.x_2.len());
+
+                Ok(x_1
+                    .iter()
+                    .zip(&envelope.x_2)
+                    .map(|(x_1, x_2)| x_1 ^ x_2)
+                    .collect::<Vec<_>>())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(masks[0], masks[1], "x_2 is not x_1 ⊕ H(m).");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_forwarding_chain() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let (token, sk_e, group_public_key) =
+            issue_token::<Ristretto255>(UserId::random(&mut rng))?;
+
+        let message = b"hello, world";
+        let original = Envelope::seal(token, &sk_e, message)?;
+
+        // forward the message along a chain of recipients
+        let mut forwarded = original.clone();
+        for _ in 0..3 {
+            forwarded = forwarded.forward(message)?;
+        }
+
+        assert!(
+            forwarded.verify(message, &group_public_key).is_ok(),
+            "Forwarded envelope failed to verify."
+        );
+        assert_eq!(
+            original.x_2, forwarded.x_2,
+            "Forwarding changed the message binding."
+        );
+        assert_eq!(
+            original.token.token.x_1.to_bytes(),
+            forwarded.token.token.x_1.to_bytes(),
+            "Forwarding changed the encrypted sender id."
+        );
+
+        assert!(
+            forwarded.forward(b"an edited message").is_err(),
+            "Forwarded an envelope with altered content."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_platform_stamp() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let (token, sk_e, group_public_key) =
+            issue_token::<Ristretto255>(UserId::random(&mut rng))?;
+
+        let platform =
+            Platform::<Ristretto255>::new(group_public_key, &mut rng);
+        let other_platform =
+            Platform::<Ristretto255>::new(group_public_key, &mut rng);
+
+        let message = b"hello, world";
+        let envelope = Envelope::seal(token, &sk_e, message)?;
+
+        assert!(
+            envelope.verify_stamp(&platform.public_key()).is_err(),
+            "Unstamped envelope passed stamp verification."
+        );
+
+        let stamped = platform.stamp(&envelope)?;
+
+        assert!(
+            stamped.verify_stamp(&platform.public_key()).is_ok(),
+            "Valid stamp failed to verify."
+        );
+        assert!(
+            stamped.verify_stamp(&other_platform.public_key()).is_err(),
+            "Stamp verified under the wrong platform key."
+        );
+
+        // forwarded copies need to be stamped again
+        let forwarded = stamped.forward(message)?;
+        assert!(forwarded.stamp().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seal_with_wrong_key() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let (token, _, _) =
+            issue_token::<Ristretto255>(UserId::random(&mut rng))?;
+        let (_, other_sk_e, _) =
+            issue_token::<Ristretto255>(UserId::random(&mut rng))?;
+
+        assert!(
+            Envelope::seal(token, &other_sk_e, b"hello, world").is_err(),
+            "Sealed a message with the wrong ephemeral key."
+        );
+
+        Ok(())
+    }
+}
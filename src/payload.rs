@@ -0,0 +1,320 @@
+//! Encryption of variable-length payloads under the moderators' ElGamal key.
+//!
+//! A payload is encrypted as `(c_1, c)` where `c_1 = r * G`, and `c` is the
+//! ChaCha20-Poly1305 encryption of the payload under a key derived from
+//! `r * PK` with HKDF. Since `c_1` has the same form as in an id encryption,
+//! the moderators' decryption shares (and their proofs) work unchanged.
+//!
+//! Tokens may carry an encrypted payload alongside `x_1`, which is revealed
+//! when the token is reported, see [`crate::TokenRequest::with_payload`].
+
+use chacha20poly1305::{
+    aead::{self, Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    elgamal::{
+        combine_shares, Ciphertext, DecryptionShare, PublicKey, VerificationKey,
+    },
+    shamir::LagrangeCache,
+    Result, UserId,
+};
+
+/// The current version of the [`Payload`] format.
+pub const PAYLOAD_VERSION: u8 = 1;
+
+/// The information about a sender that is revealed when they're reported.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Payload {
+    pub account_id: UserId,
+    pub device_id: Option<Vec<u8>>,
+
+    /// Free-form context about how the token was issued.
+    pub issuance_context: Vec<u8>,
+}
+
+/// A [`Payload`] encrypted to the moderators.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(bound = "")]
+pub(crate) struct EncryptedPayload<C: Ciphersuite = Ristretto255> {
+    c_1: C::Element,
+
+    /// The AEAD ciphertext, including its authentication tag.
+    ciphertext: Vec<u8>,
+}
+
+/// The AEAD key derived from the shared point `r * PK`.
+#[derive(Zeroize, ZeroizeOnDrop)]
+struct PayloadKey([u8; 32]);
+
+impl Payload {
+    /// Serializes the payload, prefixed with its format version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![PAYLOAD_VERSION];
+        bytes.extend(bincode::serialize(self)?);
+
+        Ok(bytes)
+    }
+
+    /// Deserializes a payload created by [`Self::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&PAYLOAD_VERSION, payload)) => {
+                Ok(bincode::deserialize(payload)?)
+            }
+            Some((version, _)) => {
+                Err(format!("Unsupported payload version: {version}").into())
+            }
+            None => Err("Payload is empty.".into()),
+        }
+    }
+}
+
+impl<C: Ciphersuite> EncryptedPayload<C> {
+    /// Encrypts `payload` under the moderators' group key with `randomness`,
+    /// binding the ciphertext to `label`.
+    ///
+    /// This is deterministic, so that whoever knows the randomness can check
+    /// the ciphertext by re-encrypting. Every key is only ever used with
+    /// one payload as long as `randomness` isn't reused, so the AEAD nonce is
+    /// fixed.
+    pub(crate) fn encrypt(
+        pk: &PublicKey<C>,
+        payload: &Payload,
+        randomness: &C::Scalar,
+        label: &[u8],
+    ) -> Result<Self> {
+        let c_1 = C::base_mul(randomness);
+        let key = PayloadKey::derive::<C>(&c_1, &(*randomness * pk.0));
+
+        let ciphertext = key
+            .cipher()
+            .encrypt(
+                &Nonce::default(),
+                aead::Payload {
+                    msg: &payload.to_bytes()?,
+                    aad: &associated_data::<C>(&c_1, label),
+                },
+            )
+            .map_err(|_| "Failed to encrypt payload.")?;
+
+        Ok(Self { c_1, ciphertext })
+    }
+
+    /// Checks that this is the encryption of `payload` with `randomness`
+    /// under `label`.
+    pub(crate) fn is_encryption_of(
+        &self,
+        pk: &PublicKey<C>,
+        payload: &Payload,
+        randomness: &C::Scalar,
+        label: &[u8],
+    ) -> bool {
+        Self::encrypt(pk, payload, randomness, label)
+            .map_or(false, |expected| *self == expected)
+    }
+
+    /// Decrypts the payload using the first `threshold` valid shares from
+    /// distinct moderators, as in [`crate::elgamal::EncryptedUserId::decrypt_with_shares`].
+    ///
+    /// Fails if the ciphertext has been tampered with or isn't bound to
+    /// `label`.
+    pub(crate) fn decrypt_with_cached_coefficients(
+        &self,
+        shares: &[DecryptionShare<C>],
+        verification_keys: &HashMap<u16, VerificationKey<C>>,
+        threshold: usize,
+        label: &[u8],
+        lagrange_cache: &LagrangeCache<C>,
+    ) -> Result<Payload> {
+        let shared_point = combine_shares(
+            self,
+            shares,
+            verification_keys,
+            threshold,
+            lagrange_cache,
+        )?;
+        let key = PayloadKey::derive::<C>(&self.c_1, &shared_point);
+
+        let bytes = key
+            .cipher()
+            .decrypt(
+                &Nonce::default(),
+                aead::Payload {
+                    msg: &self.ciphertext,
+                    aad: &associated_data::<C>(&self.c_1, label),
+                },
+            )
+            .map_err(|_| "Payload has been tampered with.")?;
+
+        Payload::from_bytes(&bytes)
+    }
+}
+
+impl<C: Ciphersuite> Ciphertext<C> for EncryptedPayload<C> {
+    fn c_1(&self) -> &C::Element {
+        &self.c_1
+    }
+}
+
+impl PayloadKey {
+    fn derive<C: Ciphersuite>(
+        c_1: &C::Element,
+        shared_point: &C::Element,
+    ) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(
+            Some(C::element_to_bytes(c_1).as_ref()),
+            C::element_to_bytes(shared_point).as_ref(),
+        );
+
+        let mut key = Self([0; 32]);
+        hkdf.expand(b"cerberus-payload-key", &mut key.0)
+            .expect("32 bytes is a valid HKDF-SHA256 output length.");
+
+        key
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+/// The data authenticated alongside a payload: its `c_1` and label.
+fn associated_data<C: Ciphersuite>(c_1: &C::Element, label: &[u8]) -> Vec<u8> {
+    let mut aad = C::element_to_bytes(c_1).as_ref().to_vec();
+    aad.extend(label);
+
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncryptedPayload, Payload, PAYLOAD_VERSION};
+    use crate::{
+        ciphersuite::{Ciphersuite, Ristretto255},
+        elgamal::{generate_private_key_shares, DecryptionError},
+        shamir::LagrangeCache,
+        Result, UserId,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_payload_encryption() -> Result<()> {
+        let (n_shares, decryption_threshold) = (5, 3);
+        let mut rng = rand::thread_rng();
+
+        let (pk, shares, _) = generate_private_key_shares::<Ristretto255, _>(
+            &mut rng,
+            n_shares,
+            decryption_threshold,
+        );
+        let verification_keys: HashMap<_, _> = shares
+            .iter()
+            .map(|share| (share.identifier(), share.verification_key()))
+            .collect();
+
+        let payload = Payload {
+            account_id: UserId::random(&mut rng),
+            device_id: Some(b"phone".to_vec()),
+            issuance_context: vec![7; 100],
+        };
+        let randomness = Ristretto255::random_scalar(&mut rng);
+        let encrypted =
+            EncryptedPayload::encrypt(&pk, &payload, &randomness, b"label")?;
+        assert!(encrypted.is_encryption_of(
+            &pk,
+            &payload,
+            &randomness,
+            b"label"
+        ));
+        assert!(!encrypted.is_encryption_of(
+            &pk,
+            &Payload {
+                device_id: None,
+                ..payload.clone()
+            },
+            &randomness,
+            b"label"
+        ));
+
+        let decryption_shares: Vec<_> = shares[..decryption_threshold]
+            .iter()
+            .map(|share| share.decryption_share(&encrypted, &mut rng))
+            .collect();
+
+        assert_eq!(
+            payload,
+            encrypted.decrypt_with_cached_coefficients(
+                &decryption_shares,
+                &verification_keys,
+                decryption_threshold,
+                b"label",
+                &LagrangeCache::default(),
+            )?
+        );
+
+        // flipping any bit of the ciphertext is detected
+        let mut tampered = encrypted.clone();
+        tampered.ciphertext[40] ^= 1;
+        assert!(tampered
+            .decrypt_with_cached_coefficients(
+                &decryption_shares,
+                &verification_keys,
+                decryption_threshold,
+                b"label",
+                &LagrangeCache::default(),
+            )
+            .is_err());
+
+        // as is decrypting under a different label
+        assert!(encrypted
+            .decrypt_with_cached_coefficients(
+                &decryption_shares,
+                &verification_keys,
+                decryption_threshold,
+                b"other label",
+                &LagrangeCache::default(),
+            )
+            .is_err());
+
+        // and not enough shares is reported as usual
+        let err = encrypted
+            .decrypt_with_cached_coefficients(
+                &decryption_shares[1..],
+                &verification_keys,
+                decryption_threshold,
+                b"label",
+                &LagrangeCache::default(),
+            )
+            .unwrap_err();
+        assert!(err.downcast_ref::<DecryptionError>().is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_versioning() -> Result<()> {
+        let payload = Payload {
+            account_id: UserId([1; 32]),
+            device_id: None,
+            issuance_context: Vec::new(),
+        };
+
+        let mut bytes = payload.to_bytes()?;
+        assert_eq!(bytes[0], PAYLOAD_VERSION);
+        assert_eq!(payload, Payload::from_bytes(&bytes)?);
+
+        bytes[0] = PAYLOAD_VERSION + 1;
+        assert!(Payload::from_bytes(&bytes).is_err());
+        assert!(Payload::from_bytes(&[]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,471 @@
+//! Proactive refresh of the moderators' ElGamal decryption key shares.
+//!
+//! Every moderator deals a random polynomial with constant term zero and
+//! sends each other moderator a share of it, encrypted as in [`crate::dkg`].
+//! Adding the received shares to its existing one re-randomizes a moderator's
+//! share without changing the group key, so old ciphertexts still decrypt,
+//! but shares from before the refresh can no longer be combined with shares
+//! from after it.
+//!
+//! As in key generation, packages are signed with the moderators' identity
+//! keys so that the coordinator can't tamper with them.
+
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use zeroize::Zeroize;
+
+use crate::{
+    ciphersuite::Ciphersuite,
+    dkg::{decrypt_share, encrypt_share, share_pad},
+    elgamal,
+    identity::{ModeratorIdentity, Signed},
+    shamir::{Commitments, Polynomial},
+    Result,
+};
+
+/// Label for signatures on refresh packages.
+const PACKAGE_LABEL: &[u8] = b"refresh/round1";
+
+/// The public values broadcast by a moderator in the first round of a refresh.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub(crate) struct RefreshPackage<C: Ciphersuite> {
+    pub(crate) identifier: u16,
+    pub(crate) commitments: Commitments<C>,
+
+    /// Diffie-Hellman key that shares are encrypted to.
+    encryption_key: C::Element,
+}
+
+/// A share of a moderator's zero polynomial, encrypted to its recipient.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct EncryptedRefreshShare {
+    pub(crate) sender: u16,
+    pub(crate) recipient: u16,
+    share: Vec<u8>,
+}
+
+/// A single moderator's state during a refresh.
+pub(crate) struct Refresh<C: Ciphersuite> {
+    identifier: u16,
+    n_participants: usize,
+    polynomial: Polynomial<C>,
+    encryption_secret: C::Scalar,
+    package: RefreshPackage<C>,
+    identity: ModeratorIdentity<C>,
+
+    /// Everyone's packages, indexed by identifier. Filled in round 2.
+    packages: HashMap<u16, RefreshPackage<C>>,
+}
+
+impl<C: Ciphersuite> Refresh<C> {
+    /// Starts a refresh for the moderator with the given identity.
+    ///
+    /// Returns the moderator's state and the signed package to broadcast to
+    /// everyone else.
+    pub(crate) fn new<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        identity: &ModeratorIdentity<C>,
+        n_participants: usize,
+        decryption_threshold: usize,
+    ) -> Result<(Self, Signed<C, RefreshPackage<C>>)> {
+        let identifier = u16::from(identity.identifier());
+        let polynomial = Polynomial::random(
+            rng,
+            C::scalar_from_u64(0),
+            decryption_threshold,
+        );
+        let encryption_secret = C::random_scalar(rng);
+
+        let package = RefreshPackage {
+            identifier,
+            commitments: polynomial.commitments(),
+            encryption_key: C::base_mul(&encryption_secret),
+        };
+        let signed_package =
+            identity.sign(rng, PACKAGE_LABEL, package.clone())?;
+
+        let refresh = Self {
+            identifier,
+            n_participants,
+            polynomial,
+            encryption_secret,
+            package,
+            identity: identity.clone(),
+            packages: HashMap::new(),
+        };
+
+        Ok((refresh, signed_package))
+    }
+
+    /// Checks everyone's packages and returns this moderator's
+    /// encrypted shares for every other moderator.
+    pub(crate) fn round2(
+        &mut self,
+        packages: Vec<Signed<C, RefreshPackage<C>>>,
+    ) -> Result<Vec<EncryptedRefreshShare>> {
+        if packages.len() != self.n_participants {
+            return Err("Received the wrong number of refresh packages.".into());
+        }
+
+        let threshold = self.package.commitments.threshold();
+        for signed_package in packages {
+            let package = self
+                .identity
+                .verify(PACKAGE_LABEL, &signed_package)?
+                .clone();
+            if package.identifier != u16::from(signed_package.signer) {
+                return Err(format!(
+                    "Moderator {} signed the refresh package of moderator {}.",
+                    signed_package.signer, package.identifier
+                )
+                .into());
+            }
+
+            if package.identifier == 0
+                || package.identifier as usize > self.n_participants
+            {
+                return Err(format!(
+                    "Invalid moderator identifier: {}",
+                    package.identifier
+                )
+                .into());
+            }
+
+            // a non-zero constant term would change the group key
+            if package.commitments.threshold() != threshold
+                || package.commitments.public_key() != C::identity()
+                || !C::is_torsion_free(&package.encryption_key)
+            {
+                return Err(format!(
+                    "Moderator {} committed to an invalid refresh polynomial.",
+                    package.identifier
+                )
+                .into());
+            }
+
+            if self.packages.insert(package.identifier, package).is_some() {
+                return Err("Received duplicate refresh packages.".into());
+            }
+        }
+
+        // make sure our own package was relayed unchanged
+        let own_package = &self.packages[&self.identifier];
+        if own_package.encryption_key != self.package.encryption_key
+            || own_package.commitments != self.package.commitments
+        {
+            return Err("Our refresh package was altered in transit.".into());
+        }
+
+        Ok(self
+            .packages
+            .values()
+            .filter(|package| package.identifier != self.identifier)
+            .map(|package| {
+                let x = C::scalar_from_u64(package.identifier as u64);
+                let pad =
+                    self.pad(self.identifier, package.identifier, package);
+
+                EncryptedRefreshShare {
+                    sender: self.identifier,
+                    recipient: package.identifier,
+                    share: encrypt_share::<C>(
+                        pad,
+                        &self.polynomial.evaluate(&x),
+                    ),
+                }
+            })
+            .collect())
+    }
+
+    /// Decrypts and verifies the shares sent by every other moderator
+    /// and adds them to `key_share`, returning the refreshed key share.
+    pub(crate) fn finish(
+        self,
+        encrypted_shares: Vec<EncryptedRefreshShare>,
+        key_share: &elgamal::KeyShare<C>,
+    ) -> Result<elgamal::KeyShare<C>> {
+        if self.packages.len() != self.n_participants {
+            return Err("Refresh round 2 has not been completed.".into());
+        }
+        if key_share.identifier() != self.identifier {
+            return Err("Key share belongs to a different moderator.".into());
+        }
+        if encrypted_shares.len() != self.n_participants - 1 {
+            return Err("Received the wrong number of refresh shares.".into());
+        }
+
+        let x = C::scalar_from_u64(self.identifier as u64);
+        let mut delta = self.polynomial.evaluate(&x);
+
+        let mut senders = Vec::with_capacity(encrypted_shares.len());
+        for encrypted_share in encrypted_shares {
+            if encrypted_share.recipient != self.identifier
+                || encrypted_share.sender == self.identifier
+                || senders.contains(&encrypted_share.sender)
+            {
+                return Err(
+                    "Received a misaddressed or duplicate refresh share."
+                        .into(),
+                );
+            }
+            senders.push(encrypted_share.sender);
+
+            let sender = self
+                .packages
+                .get(&encrypted_share.sender)
+                .ok_or("Received a refresh share from an unknown moderator.")?;
+
+            let share = decrypt_share::<C>(
+                self.pad(sender.identifier, self.identifier, sender),
+                &encrypted_share.share,
+            )?;
+
+            if !sender.commitments.verify_share(&x, &share) {
+                return Err(format!(
+                    "Moderator {} sent an invalid refresh share.",
+                    encrypted_share.sender
+                )
+                .into());
+            }
+
+            delta += share;
+        }
+
+        Ok(key_share.refreshed(&delta))
+    }
+
+    /// The one-time pad for the share sent from `sender` to `recipient`,
+    /// one of which is this moderator and the other is `other`.
+    fn pad(
+        &self,
+        sender: u16,
+        recipient: u16,
+        other: &RefreshPackage<C>,
+    ) -> C::ScalarBytes {
+        share_pad::<C>(
+            &self.encryption_secret,
+            sender,
+            recipient,
+            &other.encryption_key,
+            b"refresh",
+        )
+    }
+}
+
+impl<C: Ciphersuite> Drop for Refresh<C> {
+    fn drop(&mut self) {
+        self.encryption_secret.zeroize();
+    }
+}
+
+/// Updates the moderators' verification keys to match their refreshed key shares.
+pub(crate) fn refreshed_verification_keys<C: Ciphersuite>(
+    verification_keys: &HashMap<u16, elgamal::VerificationKey<C>>,
+    packages: &[RefreshPackage<C>],
+) -> Result<HashMap<u16, elgamal::VerificationKey<C>>> {
+    let threshold = packages
+        .first()
+        .ok_or("No refresh packages.")?
+        .commitments
+        .threshold();
+    let commitments = Commitments::sum(
+        packages.iter().map(|package| &package.commitments),
+        threshold,
+    );
+
+    Ok(verification_keys
+        .iter()
+        .map(|(&i, key)| {
+            let delta = commitments.evaluate(&C::scalar_from_u64(i as u64));
+            (i, elgamal::VerificationKey(key.0 + delta))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{refreshed_verification_keys, Refresh};
+    use crate::{
+        ciphersuite::{Ciphersuite, Ristretto255},
+        elgamal::{generate_private_key_shares, KeyShare},
+        identity::ModeratorIdentity,
+        Result, UserId,
+    };
+    use curve25519_dalek::scalar::Scalar;
+    use std::collections::HashMap;
+
+    /// Runs a refresh in memory, relaying messages like the coordinator.
+    #[allow(clippy::type_complexity)]
+    fn refresh(
+        key_shares: &[KeyShare<Ristretto255>],
+        decryption_threshold: usize,
+    ) -> Result<(
+        Vec<KeyShare<Ristretto255>>,
+        Vec<super::RefreshPackage<Ristretto255>>,
+    )> {
+        let mut rng = rand::thread_rng();
+        let n = key_shares.len();
+
+        // key shares are dealt to moderators `1..=n`, in order
+        let identities = ModeratorIdentity::generate_committee(&mut rng, n);
+        let (mut participants, packages): (Vec<_>, Vec<_>) = identities
+            .iter()
+            .map(|identity| {
+                Refresh::<Ristretto255>::new(
+                    &mut rng,
+                    identity,
+                    n,
+                    decryption_threshold,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        let mut encrypted_shares = Vec::new();
+        for participant in participants.iter_mut() {
+            encrypted_shares.extend(participant.round2(packages.clone())?);
+        }
+
+        let new_key_shares = participants
+            .into_iter()
+            .zip(key_shares)
+            .map(|(participant, key_share)| {
+                let shares = encrypted_shares
+                    .iter()
+                    .filter(|share| share.recipient == key_share.identifier())
+                    .cloned()
+                    .collect();
+                participant.finish(shares, key_share)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let packages = packages
+            .into_iter()
+            .map(|package| package.message)
+            .collect();
+
+        Ok((new_key_shares, packages))
+    }
+
+    #[test]
+    fn test_refresh_pad_direction() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let identities = ModeratorIdentity::generate_committee(&mut rng, 2);
+        let (refresh_1, package_1) =
+            Refresh::<Ristretto255>::new(&mut rng, &identities[0], 2, 2)?;
+        let (refresh_2, package_2) =
+            Refresh::<Ristretto255>::new(&mut rng, &identities[1], 2, 2)?;
+        let (package_1, package_2) = (package_1.message, package_2.message);
+
+        // both ends derive the same pad for a share sent from 1 to 2
+        let pad_1_to_2 = refresh_1.pad(1, 2, &package_2);
+        assert_eq!(pad_1_to_2, refresh_2.pad(1, 2, &package_1));
+
+        // but the share sent back from 2 to 1 uses a different one
+        assert_ne!(
+            pad_1_to_2,
+            refresh_2.pad(2, 1, &package_1),
+            "Refresh pads are reused."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_rejects_substituted_package() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let identities = ModeratorIdentity::generate_committee(&mut rng, 3);
+        let (participants, mut packages): (Vec<_>, Vec<_>) = identities
+            .iter()
+            .map(|identity| {
+                Refresh::<Ristretto255>::new(&mut rng, identity, 3, 2)
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        // the coordinator swaps in its own encryption key for moderator 2
+        packages[1].message.encryption_key =
+            Ristretto255::base_mul(&Ristretto255::random_scalar(&mut rng));
+
+        for mut participant in participants {
+            assert!(participant.round2(packages.clone()).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh() -> Result<()> {
+        let (n, decryption_threshold) = (5, 3);
+        let mut rng = rand::thread_rng();
+
+        let (pk, old_shares, _) = generate_private_key_shares::<Ristretto255, _>(
+            &mut rng,
+            n,
+            decryption_threshold,
+        );
+        let old_verification_keys: HashMap<_, _> = old_shares
+            .iter()
+            .map(|share| (share.identifier(), share.verification_key()))
+            .collect();
+
+        let (new_shares, packages) =
+            refresh(&old_shares, decryption_threshold)?;
+        let new_verification_keys =
+            refreshed_verification_keys(&old_verification_keys, &packages)?;
+
+        assert!(new_shares.iter().all(|share| {
+            new_verification_keys[&share.identifier()]
+                == share.verification_key()
+        }));
+
+        // ciphertexts from before the refresh still decrypt
+        let id = UserId::random(&mut rng);
+        let x_1 = pk.encrypt(&id, &Scalar::random(&mut rng));
+
+        let decryption_shares: Vec<_> = new_shares[..decryption_threshold]
+            .iter()
+            .map(|share| share.decryption_share(&x_1, &mut rng))
+            .collect();
+
+        assert_eq!(
+            id,
+            x_1.decrypt_with_shares(
+                &decryption_shares,
+                &new_verification_keys,
+                decryption_threshold
+            )?
+        );
+
+        // but old shares can't be combined with new ones
+        let mixed_shares = vec![
+            old_shares[0].decryption_share(&x_1, &mut rng),
+            new_shares[1].decryption_share(&x_1, &mut rng),
+            new_shares[2].decryption_share(&x_1, &mut rng),
+        ];
+
+        assert!(x_1
+            .decrypt_with_shares(
+                &mixed_shares,
+                &new_verification_keys,
+                decryption_threshold
+            )
+            .is_err());
+
+        // even when the old share is verified against the old key
+        let mut old_verification_keys = new_verification_keys.clone();
+        old_verification_keys.insert(1, old_shares[0].verification_key());
+        let mixed_id = x_1.decrypt_with_shares(
+            &mixed_shares,
+            &old_verification_keys,
+            decryption_threshold,
+        )?;
+        assert_ne!(id, mixed_id);
+
+        Ok(())
+    }
+}
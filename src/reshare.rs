@@ -0,0 +1,820 @@
+//! Resharing the moderators' keys to a new committee.
+//!
+//! Every moderator `i` of the old committee deals a polynomial whose constant
+//! term is `λ_i * s_i`, where `s_i` is its share and `λ_i` its Lagrange
+//! coefficient among the dealers. These constant terms sum to the group secret,
+//! so the shares of the new committee (which may have a different size and
+//! threshold) correspond to the same group keys, and existing tokens remain
+//! reportable.
+//!
+//! Shares are encrypted to the new moderators as in [`crate::dkg`].
+//!
+//! As in key generation, the new moderators sign their encryption keys and
+//! the old moderators sign their dealings with their identity keys, so that
+//! the coordinator can't substitute its own encryption keys and learn the
+//! dealt shares. The old moderators also vouch for their own public keys and
+//! the group keys, which the new moderators check dealings against.
+
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use zeroize::Zeroize;
+
+use crate::{
+    ciphersuite::Ciphersuite,
+    dkg::{add_verified_share, EncryptedShares, KeyShares, Thresholds},
+    elgamal,
+    identity::{ModeratorIdentity, Signed},
+    shamir::{
+        lagrange_coefficient, lagrange_coefficients, Commitments, Polynomial,
+    },
+    ModeratorId, Result,
+};
+
+/// Label for signatures on the new moderators' encryption keys.
+const RECIPIENT_LABEL: &[u8] = b"reshare/round1";
+
+/// Label for signatures on dealings.
+const DEALING_LABEL: &[u8] = b"reshare/round2";
+
+/// Label for the dealers' signatures on the public values of a reshare.
+const CONTEXT_LABEL: &[u8] = b"reshare/context";
+
+/// The public keys of an old moderator's shares, against which its dealing
+/// is checked. Only the keys being reshared are present.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(bound = "")]
+pub(crate) struct DealerKeys<C: Ciphersuite> {
+    pub(crate) dealer: u16,
+    pub(crate) signing_verifying_share: Option<C::Element>,
+    pub(crate) decryption_verification_key: Option<C::Element>,
+}
+
+/// The public values of a reshare, which every dealer signs off on after
+/// checking its own keys and the group keys.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(bound = "")]
+pub(crate) struct ReshareContext<C: Ciphersuite> {
+    /// The old moderators taking part, along with their public keys.
+    pub(crate) dealer_keys: Vec<DealerKeys<C>>,
+
+    /// The keys being reshared.
+    pub(crate) thresholds: Thresholds,
+
+    // the group keys, which must not change
+    pub(crate) group_public_key: C::Element,
+    pub(crate) group_public_elgamal_key: elgamal::PublicKey<C>,
+}
+
+impl<C: Ciphersuite> ReshareContext<C> {
+    fn dealers(&self) -> Vec<u16> {
+        self.dealer_keys.iter().map(|keys| keys.dealer).collect()
+    }
+}
+
+/// An old moderator's shares of its key shares for every new moderator.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub(crate) struct Dealing<C: Ciphersuite> {
+    pub(crate) dealer: u16,
+    signing_commitments: Option<Commitments<C>>,
+    decryption_commitments: Option<Commitments<C>>,
+
+    /// Diffie-Hellman key that the shares are encrypted with.
+    encryption_key: C::Element,
+    shares: Vec<EncryptedShares>,
+}
+
+/// Reshares the shares of the moderator with the given identity to the new
+/// moderators, for the keys in `context`. The dealer MUST hold a share of
+/// every one of these keys.
+///
+/// `recipients` are the new moderators' signed encryption keys, which are
+/// checked against the dealer's roster. The dealer checks that `context`
+/// reports its own public keys correctly, but the caller MUST check that it
+/// has the right group keys.
+///
+/// Returns the signed dealing and the dealer's signature on `context`.
+#[allow(clippy::type_complexity)]
+pub(crate) fn deal<C: Ciphersuite, R: CryptoRng + RngCore>(
+    rng: &mut R,
+    identity: &ModeratorIdentity<C>,
+    signing_share: Option<&C::Scalar>,
+    decryption_share: Option<&C::Scalar>,
+    recipients: &[Signed<C, C::Element>],
+    context: &ReshareContext<C>,
+) -> Result<(Signed<C, Dealing<C>>, Signed<C, ReshareContext<C>>)> {
+    let dealer = u16::from(identity.identifier());
+    let dealers = context.dealers();
+    let own_keys = context
+        .dealer_keys
+        .iter()
+        .find(|keys| keys.dealer == dealer)
+        .ok_or("Dealer is not one of the dealers.")?;
+
+    let lambda = lagrange_coefficient::<C>(
+        &C::scalar_from_u64(dealer as u64),
+        &dealer_identifiers::<C>(&dealers)?,
+    );
+
+    let thresholds = context.thresholds;
+    let signing_polynomial =
+        deal_polynomial(rng, &lambda, signing_share, thresholds.signing)?;
+    let decryption_polynomial =
+        deal_polynomial(rng, &lambda, decryption_share, thresholds.decryption)?;
+    let encryption_secret = C::random_scalar(rng);
+
+    // the new moderators check our dealing against these keys
+    let expected_keys = DealerKeys {
+        dealer,
+        signing_verifying_share: signing_polynomial
+            .as_ref()
+            .and(signing_share)
+            .map(C::base_mul),
+        decryption_verification_key: decryption_polynomial
+            .as_ref()
+            .and(decryption_share)
+            .map(C::base_mul),
+    };
+    if *own_keys != expected_keys {
+        return Err("Coordinator misreported this moderator's keys.".into());
+    }
+
+    let mut recipient_ids = HashSet::with_capacity(recipients.len());
+    let shares = recipients
+        .iter()
+        .map(|signed_key| {
+            // only the recipient itself can vouch for its encryption key,
+            // and only moderators in the roster can join the committee
+            let recipient_encryption_key =
+                identity.verify(RECIPIENT_LABEL, signed_key)?;
+            let recipient = u16::from(signed_key.signer);
+            if !recipient_ids.insert(recipient) {
+                return Err("Received duplicate recipients.".into());
+            }
+            if !C::is_torsion_free(recipient_encryption_key) {
+                return Err(format!(
+                    "Moderator {recipient} sent an invalid encryption key."
+                )
+                .into());
+            }
+
+            let x = C::scalar_from_u64(recipient as u64);
+
+            Ok(EncryptedShares::encrypt::<C>(
+                &encryption_secret,
+                dealer,
+                recipient,
+                recipient_encryption_key,
+                signing_polynomial
+                    .as_ref()
+                    .map(|polynomial| polynomial.evaluate(&x)),
+                decryption_polynomial
+                    .as_ref()
+                    .map(|polynomial| polynomial.evaluate(&x)),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let dealing = Dealing {
+        dealer,
+        signing_commitments: signing_polynomial
+            .as_ref()
+            .map(Polynomial::commitments),
+        decryption_commitments: decryption_polynomial
+            .as_ref()
+            .map(Polynomial::commitments),
+        encryption_key: C::base_mul(&encryption_secret),
+        shares,
+    };
+
+    Ok((
+        identity.sign(rng, DEALING_LABEL, dealing)?,
+        identity.sign(rng, CONTEXT_LABEL, context.clone())?,
+    ))
+}
+
+/// A polynomial with constant term `λ * share`, if the key is being
+/// reshared with the given threshold.
+fn deal_polynomial<C: Ciphersuite, R: CryptoRng + RngCore>(
+    rng: &mut R,
+    lambda: &C::Scalar,
+    share: Option<&C::Scalar>,
+    threshold: Option<usize>,
+) -> Result<Option<Polynomial<C>>> {
+    match (share, threshold) {
+        (Some(share), Some(threshold)) => {
+            Ok(Some(Polynomial::random(rng, *lambda * *share, threshold)))
+        }
+        (_, None) => Ok(None),
+        (None, Some(_)) => {
+            Err("Dealer has no share of a key being reshared.".into())
+        }
+    }
+}
+
+/// A new moderator's state while it waits for its shares.
+pub(crate) struct Recipient<C: Ciphersuite> {
+    identifier: u16,
+    encryption_secret: C::Scalar,
+    identity: ModeratorIdentity<C>,
+}
+
+impl<C: Ciphersuite> Recipient<C> {
+    /// Starts a reshare to the moderator with the given identity.
+    ///
+    /// Returns the recipient's state and the signed key that shares should
+    /// be encrypted to.
+    pub(crate) fn new<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<(Self, Signed<C, C::Element>)> {
+        let encryption_secret = C::random_scalar(rng);
+        let encryption_key = C::base_mul(&encryption_secret);
+        let signed_key = identity.sign(rng, RECIPIENT_LABEL, encryption_key)?;
+
+        let recipient = Self {
+            identifier: u16::from(identity.identifier()),
+            encryption_secret,
+            identity: identity.clone(),
+        };
+
+        Ok((recipient, signed_key))
+    }
+
+    /// Checks every dealing against its dealer's public keys and combines
+    /// the shares into this moderator's new shares of the keys being
+    /// reshared.
+    ///
+    /// Every dealer in `context` MUST have signed off on it in
+    /// `confirmations`. Fails if the new shares wouldn't correspond to the
+    /// existing group keys.
+    pub(crate) fn finish(
+        self,
+        dealings: &[Signed<C, Dealing<C>>],
+        confirmations: &[Signed<C, ReshareContext<C>>],
+        context: &ReshareContext<C>,
+    ) -> Result<KeyShares<C>> {
+        let dealers = context.dealers();
+        let dealer_ids = dealers
+            .iter()
+            .map(|&dealer| ModeratorId::try_from(dealer))
+            .collect::<Result<Vec<_>>>()?;
+
+        // every dealer vouches for its own keys and the group keys, so the
+        // coordinator can't misreport them
+        self.identity.verify_unanimous_among(
+            CONTEXT_LABEL,
+            confirmations,
+            context,
+            &dealer_ids,
+        )?;
+
+        let lambdas =
+            lagrange_coefficients::<C>(&dealer_identifiers::<C>(&dealers)?);
+        let thresholds = context.thresholds;
+
+        let mut dealings_by_dealer = HashMap::with_capacity(dealings.len());
+        for signed_dealing in dealings {
+            let dealing =
+                self.identity.verify(DEALING_LABEL, signed_dealing)?;
+            if dealing.dealer != u16::from(signed_dealing.signer) {
+                return Err(format!(
+                    "Moderator {} signed the dealing of moderator {}.",
+                    signed_dealing.signer, dealing.dealer
+                )
+                .into());
+            }
+
+            if dealings_by_dealer.insert(dealing.dealer, dealing).is_some() {
+                return Err("Received duplicate dealings.".into());
+            }
+        }
+        if dealings_by_dealer.len() != dealers.len() {
+            return Err("Received the wrong number of dealings.".into());
+        }
+
+        let x = C::scalar_from_u64(self.identifier as u64);
+        let zero = C::scalar_from_u64(0);
+        let mut signing_share = thresholds.signing.map(|_| zero);
+        let mut decryption_share = thresholds.decryption.map(|_| zero);
+
+        for (keys, lambda) in context.dealer_keys.iter().zip(lambdas) {
+            let dealing = dealings_by_dealer
+                .get(&keys.dealer)
+                .ok_or("Missing a dealing from one of the dealers.")?;
+
+            // the dealt secrets must be the dealer's (weighted) key shares
+            if !deals_share(
+                dealing.signing_commitments.as_ref(),
+                thresholds.signing,
+                keys.signing_verifying_share,
+                &lambda,
+            ) || !deals_share(
+                dealing.decryption_commitments.as_ref(),
+                thresholds.decryption,
+                keys.decryption_verification_key,
+                &lambda,
+            ) {
+                return Err(format!(
+                    "Moderator {} dealt the wrong secrets.",
+                    keys.dealer
+                )
+                .into());
+            }
+
+            let shares = dealing
+                .shares
+                .iter()
+                .find(|shares| {
+                    shares.recipient == self.identifier
+                        && shares.sender == keys.dealer
+                })
+                .ok_or("A dealing is missing our shares.")?;
+
+            if !C::is_torsion_free(&dealing.encryption_key) {
+                return Err(format!(
+                    "Moderator {} sent an invalid encryption key.",
+                    keys.dealer
+                )
+                .into());
+            }
+
+            let (dealt_signing_share, dealt_decryption_share) = shares
+                .decrypt::<C>(
+                    &self.encryption_secret,
+                    &dealing.encryption_key,
+                )?;
+
+            if !add_verified_share(
+                &mut signing_share,
+                dealt_signing_share,
+                dealing.signing_commitments.as_ref(),
+                &x,
+            ) || !add_verified_share(
+                &mut decryption_share,
+                dealt_decryption_share,
+                dealing.decryption_commitments.as_ref(),
+                &x,
+            ) {
+                return Err(format!(
+                    "Moderator {} sent an invalid share.",
+                    keys.dealer
+                )
+                .into());
+            }
+        }
+
+        let (signing_commitments, decryption_commitments) =
+            group_commitments(dealings_by_dealer.values().copied())?;
+
+        let changes_signing_key =
+            signing_commitments.as_ref().map_or(false, |commitments| {
+                commitments.public_key() != context.group_public_key
+            });
+        let changes_decryption_key =
+            decryption_commitments
+                .as_ref()
+                .map_or(false, |commitments| {
+                    commitments.public_key()
+                        != context.group_public_elgamal_key.0
+                });
+        if changes_signing_key || changes_decryption_key {
+            return Err("Resharing would change the group keys.".into());
+        }
+
+        Ok(KeyShares::new(
+            self.identifier,
+            signing_share.zip(signing_commitments),
+            decryption_share.zip(decryption_commitments),
+        ))
+    }
+}
+
+/// Whether a dealer committed to a polynomial with the given threshold
+/// whose constant term is `λ` times its share with public key `public_share`,
+/// or to none at all if the key isn't being reshared.
+fn deals_share<C: Ciphersuite>(
+    commitments: Option<&Commitments<C>>,
+    threshold: Option<usize>,
+    public_share: Option<C::Element>,
+    lambda: &C::Scalar,
+) -> bool {
+    match (commitments, threshold, public_share) {
+        (Some(commitments), Some(threshold), Some(public_share)) => {
+            commitments.threshold() == threshold
+                && commitments.public_key() == *lambda * public_share
+        }
+        (None, None, _) => true,
+        _ => false,
+    }
+}
+
+impl<C: Ciphersuite> Drop for Recipient<C> {
+    fn drop(&mut self) {
+        self.encryption_secret.zeroize();
+    }
+}
+
+/// Combines the dealings into commitments to the new committee's
+/// polynomials for the keys being reshared.
+#[allow(clippy::type_complexity)]
+pub(crate) fn group_commitments<'a, C: Ciphersuite, I>(
+    dealings: I,
+) -> Result<(Option<Commitments<C>>, Option<Commitments<C>>)>
+where
+    I: IntoIterator<Item = &'a Dealing<C>> + Clone,
+{
+    let thresholds = dealings
+        .clone()
+        .into_iter()
+        .next()
+        .ok_or("No dealings.")?
+        .thresholds();
+    if dealings
+        .clone()
+        .into_iter()
+        .any(|dealing| dealing.thresholds() != thresholds)
+    {
+        return Err("Moderators dealt different keys.".into());
+    }
+
+    Ok((
+        thresholds.signing.map(|threshold| {
+            Commitments::sum(
+                dealings
+                    .clone()
+                    .into_iter()
+                    .filter_map(|dealing| dealing.signing_commitments.as_ref()),
+                threshold,
+            )
+        }),
+        thresholds.decryption.map(|threshold| {
+            Commitments::sum(
+                dealings.into_iter().filter_map(|dealing| {
+                    dealing.decryption_commitments.as_ref()
+                }),
+                threshold,
+            )
+        }),
+    ))
+}
+
+impl<C: Ciphersuite> Dealing<C> {
+    /// The thresholds of the polynomials dealt.
+    fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            signing: self
+                .signing_commitments
+                .as_ref()
+                .map(Commitments::threshold),
+            decryption: self
+                .decryption_commitments
+                .as_ref()
+                .map(Commitments::threshold),
+        }
+    }
+}
+
+/// The dealers' identifiers as scalars, checking that they are distinct and non-zero.
+fn dealer_identifiers<C: Ciphersuite>(
+    dealers: &[u16],
+) -> Result<Vec<C::Scalar>> {
+    for (i, dealer) in dealers.iter().enumerate() {
+        if *dealer == 0 || dealers[..i].contains(dealer) {
+            return Err(
+                "Dealer identifiers must be distinct and non-zero.".into()
+            );
+        }
+    }
+
+    Ok(dealers
+        .iter()
+        .map(|dealer| C::scalar_from_u64(*dealer as u64))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        deal, group_commitments, DealerKeys, Dealing, Recipient, ReshareContext,
+    };
+    use crate::{
+        ciphersuite::{Ciphersuite, Ristretto255},
+        dkg::{self, tests::run_dkg, KeyShares, Thresholds},
+        identity::{ModeratorIdentity, Signed},
+        Result, UserId,
+    };
+
+    type Element = <Ristretto255 as Ciphersuite>::Element;
+
+    /// Starts a reshare to the moderators with the given identities.
+    #[allow(clippy::type_complexity)]
+    fn recipients(
+        identities: &[ModeratorIdentity<Ristretto255>],
+    ) -> Result<(
+        Vec<Recipient<Ristretto255>>,
+        Vec<Signed<Ristretto255, Element>>,
+    )> {
+        Ok(identities
+            .iter()
+            .map(|identity| Recipient::new(&mut rand::thread_rng(), identity))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip())
+    }
+
+    /// The public values of a reshare of the keys in `thresholds` by
+    /// `dealers`, as the coordinator would send them.
+    fn context(
+        old_key_shares: &[KeyShares<Ristretto255>],
+        dealers: &[u16],
+        thresholds: Thresholds,
+    ) -> Result<ReshareContext<Ristretto255>> {
+        let dealer_keys = dealers
+            .iter()
+            .map(|&i| {
+                let key_shares = &old_key_shares[i as usize - 1];
+                let signing_verifying_share = match thresholds.signing {
+                    Some(_) => key_shares.frost_key_package()?.map(|package| {
+                        Ristretto255::base_mul(
+                            &Ristretto255::signing_share_scalar(
+                                &package.secret_share,
+                            ),
+                        )
+                    }),
+                    None => None,
+                };
+
+                Ok(DealerKeys {
+                    dealer: i,
+                    signing_verifying_share,
+                    decryption_verification_key: thresholds
+                        .decryption
+                        .and(key_shares.elgamal_key_share())
+                        .map(|key_share| key_share.verification_key().0),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let unrelated_key = Ristretto255::base_mul(
+            &Ristretto255::random_scalar(&mut rand::thread_rng()),
+        );
+
+        Ok(ReshareContext {
+            dealer_keys,
+            thresholds,
+            group_public_key: old_key_shares[0]
+                .signing_public_key()
+                .unwrap_or(unrelated_key),
+            group_public_elgamal_key: old_key_shares[0]
+                .elgamal_public_key()
+                .unwrap(),
+        })
+    }
+
+    /// Has every dealer in `context` deal its shares to `recipients`.
+    #[allow(clippy::type_complexity)]
+    fn deal_all(
+        identities: &[ModeratorIdentity<Ristretto255>],
+        old_key_shares: &[KeyShares<Ristretto255>],
+        recipients: &[Signed<Ristretto255, Element>],
+        context: &ReshareContext<Ristretto255>,
+    ) -> Result<(
+        Vec<Signed<Ristretto255, Dealing<Ristretto255>>>,
+        Vec<Signed<Ristretto255, ReshareContext<Ristretto255>>>,
+    )> {
+        Ok(context
+            .dealers()
+            .into_iter()
+            .map(|i| {
+                let key_shares = &old_key_shares[i as usize - 1];
+                let signing_share =
+                    key_shares.frost_key_package()?.map(|package| {
+                        Ristretto255::signing_share_scalar(
+                            &package.secret_share,
+                        )
+                    });
+                let elgamal_key_share = key_shares.elgamal_key_share();
+
+                deal(
+                    &mut rand::thread_rng(),
+                    &identities[i as usize - 1],
+                    signing_share.as_ref(),
+                    elgamal_key_share.as_ref().map(|k| k.secret()),
+                    recipients,
+                    context,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip())
+    }
+
+    #[test]
+    fn test_reshare() -> Result<()> {
+        let mut rng = rand::thread_rng();
+
+        // reshare from a (3, 5) committee to a (4, 7) committee,
+        // whose roster covers both
+        let (new_n, new_threshold) = (7, 4);
+        let identities = ModeratorIdentity::generate_committee(&mut rng, new_n);
+        let (old_key_shares, _) =
+            run_dkg::<Ristretto255>(5, Thresholds::both(3, 3))?;
+        let group_public_key = old_key_shares[0].signing_public_key().unwrap();
+        let group_public_elgamal_key =
+            old_key_shares[0].elgamal_public_key().unwrap();
+        let thresholds = Thresholds::both(new_threshold, new_threshold);
+
+        let (recipients, encryption_keys) = recipients(&identities)?;
+
+        // only a threshold of the old committee needs to take part
+        let context = context(&old_key_shares, &[2, 3, 5], thresholds)?;
+        let (dealings, confirmations) =
+            deal_all(&identities, &old_key_shares, &encryption_keys, &context)?;
+
+        let new_key_shares = recipients
+            .into_iter()
+            .map(|recipient| {
+                recipient.finish(&dealings, &confirmations, &context)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        assert!(new_key_shares.iter().all(|key_shares| {
+            key_shares.signing_public_key() == Some(group_public_key)
+                && key_shares.elgamal_public_key()
+                    == Some(group_public_elgamal_key)
+        }));
+
+        // ciphertexts from before the reshare decrypt with the new shares
+        let id = UserId::random(&mut rng);
+        let x_1 = group_public_elgamal_key
+            .encrypt(&id, &Ristretto255::random_scalar(&mut rng));
+
+        let (_, decryption_commitments) =
+            group_commitments(dealings.iter().map(|dealing| &dealing.message))?;
+        let verification_keys = dkg::elgamal_verification_keys(
+            &decryption_commitments.unwrap(),
+            new_n,
+        );
+
+        let decryption_shares: Vec<_> = new_key_shares[new_n - new_threshold..]
+            .iter()
+            .map(|key_shares| {
+                key_shares
+                    .elgamal_key_share()
+                    .unwrap()
+                    .decryption_share(&x_1, &mut rng)
+            })
+            .collect();
+
+        assert_eq!(
+            id,
+            x_1.decrypt_with_shares(
+                &decryption_shares,
+                &verification_keys,
+                new_threshold
+            )?
+        );
+
+        // fewer than the new threshold isn't enough
+        assert!(x_1
+            .decrypt_with_shares(
+                &decryption_shares[1..],
+                &verification_keys,
+                new_threshold - 1
+            )
+            .map_or(true, |decrypted| decrypted != id));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reshare_decryption_committee() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let identities = ModeratorIdentity::generate_committee(&mut rng, 5);
+
+        // a decryption committee without a share of the signing key
+        let old_thresholds = Thresholds {
+            signing: None,
+            decryption: Some(3),
+        };
+        let (old_key_shares, _) = run_dkg::<Ristretto255>(5, old_thresholds)?;
+        let group_public_elgamal_key =
+            old_key_shares[0].elgamal_public_key().unwrap();
+
+        let thresholds = Thresholds {
+            signing: None,
+            decryption: Some(2),
+        };
+        let (recipients, encryption_keys) = recipients(&identities[..3])?;
+        let dealers = [1, 2, 4];
+
+        // the dealers can't be asked to reshare a key they don't hold
+        let both = context(&old_key_shares, &dealers, Thresholds::both(2, 2))?;
+        assert!(deal_all(
+            &identities,
+            &old_key_shares,
+            &encryption_keys,
+            &both
+        )
+        .is_err());
+
+        // the signing key isn't checked, since it isn't being reshared
+        let context = context(&old_key_shares, &dealers, thresholds)?;
+        let (dealings, confirmations) =
+            deal_all(&identities, &old_key_shares, &encryption_keys, &context)?;
+
+        for recipient in recipients {
+            let key_shares =
+                recipient.finish(&dealings, &confirmations, &context)?;
+
+            assert!(key_shares.frost_key_package()?.is_none());
+            assert_eq!(
+                key_shares.elgamal_public_key(),
+                Some(group_public_elgamal_key)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reshare_is_authenticated() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let identities = ModeratorIdentity::generate_committee(&mut rng, 4);
+        let (old_key_shares, _) =
+            run_dkg::<Ristretto255>(4, Thresholds::both(2, 2))?;
+        let (recipients, encryption_keys) = recipients(&identities)?;
+        let context =
+            context(&old_key_shares, &[1, 2, 3], Thresholds::both(3, 3))?;
+
+        // the coordinator can't swap in its own encryption key,
+        // whether unsigned or signed by someone outside the roster
+        let outsider =
+            &ModeratorIdentity::<Ristretto255>::generate_committee(&mut rng, 4)
+                [3];
+        let (_, outsider_key) = Recipient::new(&mut rng, outsider)?;
+        let mut swapped = encryption_keys.clone();
+        swapped[3] = outsider_key;
+        assert!(
+            deal_all(&identities, &old_key_shares, &swapped, &context).is_err()
+        );
+
+        let mut swapped = encryption_keys.clone();
+        swapped[3].message = encryption_keys[0].message;
+        assert!(
+            deal_all(&identities, &old_key_shares, &swapped, &context).is_err()
+        );
+
+        // nor misreport a dealer's keys to the dealer
+        let mut misreported = context.clone();
+        misreported.dealer_keys[1].decryption_verification_key =
+            misreported.dealer_keys[0].decryption_verification_key;
+        assert!(deal_all(
+            &identities,
+            &old_key_shares,
+            &encryption_keys,
+            &misreported
+        )
+        .is_err());
+
+        let (dealings, confirmations) =
+            deal_all(&identities, &old_key_shares, &encryption_keys, &context)?;
+        let mut recipients = recipients.into_iter();
+
+        // or to the new moderators
+        let mut misreported = context.clone();
+        misreported.group_public_key =
+            Ristretto255::base_mul(&Ristretto255::random_scalar(&mut rng));
+        assert!(recipients
+            .next()
+            .unwrap()
+            .finish(&dealings, &confirmations, &misreported)
+            .is_err());
+
+        // every dealer has to sign off
+        assert!(recipients
+            .next()
+            .unwrap()
+            .finish(&dealings, &confirmations[1..], &context)
+            .is_err());
+
+        // and dealings can't be passed off as someone else's
+        let mut misattributed = dealings.clone();
+        misattributed[2].signer = misattributed[1].signer;
+        assert!(recipients
+            .next()
+            .unwrap()
+            .finish(&misattributed, &confirmations, &context)
+            .is_err());
+
+        recipients.next().unwrap().finish(
+            &dealings,
+            &confirmations,
+            &context,
+        )?;
+
+        Ok(())
+    }
+}
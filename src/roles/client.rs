@@ -0,0 +1,409 @@
+use frost_core::VerifyingKey;
+
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    elgamal,
+    message::Envelope,
+    payload::Payload,
+    roles::coordinator::Coordinator,
+    token::{EphemeralSecretKey, SignedToken, TokenRequest},
+    wallet::TokenWallet,
+    Batch, PlatformPublicKey, Result, UserId,
+};
+
+/// An end user of the messaging platform.
+///
+/// Requests tokens from the [`Coordinator`], uses them to send messages,
+/// verifies the messages it receives, and reports abusive ones.
+pub struct Client<C: Ciphersuite = Ristretto255> {
+    user_id: UserId,
+
+    // public keys of the other parties
+    group_public_key: VerifyingKey<C::Frost>,
+    group_public_elgamal_key: elgamal::PublicKey<C>,
+    platform_public_key: PlatformPublicKey<C>,
+}
+
+impl<C: Ciphersuite> Client<C> {
+    pub fn new(
+        user_id: UserId,
+        group_public_key: VerifyingKey<C::Frost>,
+        group_public_elgamal_key: elgamal::PublicKey<C>,
+        platform_public_key: PlatformPublicKey<C>,
+    ) -> Self {
+        Self {
+            user_id,
+            group_public_key,
+            group_public_elgamal_key,
+            platform_public_key,
+        }
+    }
+
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    /// Requests a batch of `n_tokens` tokens from the coordinator.
+    ///
+    /// Every token is checked on receipt: it must be signed by the moderators,
+    /// contain the requested ephemeral key, and contain an encryption of
+    /// this client's id. Returns the tokens alongside their ephemeral secret keys.
+    pub async fn request_tokens(
+        &self,
+        coordinator: &mut Coordinator<C>,
+        n_tokens: usize,
+    ) -> Result<Batch<(SignedToken<C>, EphemeralSecretKey<C>)>> {
+        let requests = {
+            let mut rng = rand::thread_rng();
+            (0..n_tokens)
+                .map(|_| TokenRequest::new(self.user_id, &mut rng))
+                .collect()
+        };
+
+        self.request_tokens_for(coordinator, requests).await
+    }
+
+    /// Requests a batch of `n_tokens` tokens from the coordinator, each
+    /// carrying `payload`, which is revealed along with this client's id if
+    /// the token is reported.
+    ///
+    /// The tokens are checked as in [`Self::request_tokens`], as well as
+    /// for containing an encryption of `payload`.
+    pub async fn request_tokens_with_payload(
+        &self,
+        coordinator: &mut Coordinator<C>,
+        payload: &Payload,
+        n_tokens: usize,
+    ) -> Result<Batch<(SignedToken<C>, EphemeralSecretKey<C>)>> {
+        if payload.account_id != self.user_id {
+            return Err("Payload belongs to a different account.".into());
+        }
+
+        let requests = {
+            let mut rng = rand::thread_rng();
+            (0..n_tokens)
+                .map(|_| TokenRequest::with_payload(payload.clone(), &mut rng))
+                .collect()
+        };
+
+        self.request_tokens_for(coordinator, requests).await
+    }
+
+    /// Sends `requests` to the coordinator and checks the tokens it returns.
+    async fn request_tokens_for(
+        &self,
+        coordinator: &mut Coordinator<C>,
+        requests: Vec<(TokenRequest<C>, EphemeralSecretKey<C>)>,
+    ) -> Result<Batch<(SignedToken<C>, EphemeralSecretKey<C>)>> {
+        let (token_requests, secret_keys): (Vec<_>, Vec<_>) =
+            requests.into_iter().unzip();
+
+        let tokens = coordinator.create_tokens(&token_requests).await?;
+
+        if tokens.len() != token_requests.len() {
+            return Err(
+                "Coordinator returned the wrong number of tokens.".into()
+            );
+        }
+
+        for (token, token_request) in tokens.iter().zip(&token_requests) {
+            token_request.check_issued_token(
+                token,
+                &self.group_public_key,
+                &self.group_public_elgamal_key,
+            )?;
+        }
+
+        Ok(tokens.into_iter().zip(secret_keys).collect())
+    }
+
+    /// Requests `n_tokens` new tokens and adds them to `wallet`.
+    pub async fn refill_wallet(
+        &self,
+        coordinator: &mut Coordinator<C>,
+        wallet: &mut TokenWallet<C>,
+        n_tokens: usize,
+    ) -> Result<()> {
+        let tokens = self.request_tokens(coordinator, n_tokens).await?;
+        wallet.add(tokens);
+
+        Ok(())
+    }
+
+    /// Consumes a token to create the envelope for sending `message`.
+    pub fn seal(
+        &self,
+        token: SignedToken<C>,
+        sk_e: &EphemeralSecretKey<C>,
+        message: &[u8],
+    ) -> Result<Envelope<C>> {
+        Envelope::seal(token, sk_e, message)
+    }
+
+    /// Takes an unused token from `wallet` to create the envelope for sending `message`.
+    pub fn seal_from_wallet(
+        &self,
+        wallet: &mut TokenWallet<C>,
+        message: &[u8],
+    ) -> Result<Envelope<C>> {
+        let (token, sk_e) = wallet
+            .take()
+            .ok_or("No usable tokens left in the wallet.")?;
+
+        self.seal(token, &sk_e, message)
+    }
+
+    /// Checks an incoming message: the envelope must carry a valid token,
+    /// be bound to `message`, and have been stamped by the platform.
+    ///
+    /// Messages that pass this check can be reported.
+    pub fn verify_incoming(
+        &self,
+        envelope: &Envelope<C>,
+        message: &[u8],
+    ) -> Result<()> {
+        envelope.verify(message, &self.group_public_key)?;
+        envelope.verify_stamp(&self.platform_public_key)
+    }
+
+    /// Reports a received message to the moderators.
+    ///
+    /// The report is checked locally first so that invalid reports aren't sent.
+    /// Returns the id of the message's original sender if enough moderators
+    /// agree to reveal it.
+    pub async fn report(
+        &self,
+        coordinator: &Coordinator<C>,
+        envelope: &Envelope<C>,
+        message: &[u8],
+    ) -> Result<UserId> {
+        self.verify_incoming(envelope, message)?;
+
+        coordinator
+            .request_token_decryption(envelope, message)
+            .await
+    }
+
+    /// Reports a received message to the moderators, as in [`Self::report`],
+    /// but returns the payload carried by the message's token instead.
+    ///
+    /// Fails if the token carries no payload.
+    pub async fn report_payload(
+        &self,
+        coordinator: &Coordinator<C>,
+        envelope: &Envelope<C>,
+        message: &[u8],
+    ) -> Result<Payload> {
+        self.verify_incoming(envelope, message)?;
+
+        coordinator
+            .request_payload_decryption(envelope, message)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Client;
+    use crate::{
+        ciphersuite::Ristretto255,
+        token::{
+            tests::issue_token_for_request, EphemeralSecretKey, TokenRequest,
+        },
+        Payload, Platform, Result, UserId,
+    };
+
+    fn token_request() -> TokenRequest<Ristretto255> {
+        let mut rng = rand::thread_rng();
+        TokenRequest::new(UserId::random(&mut rng), &mut rng).0
+    }
+
+    #[test]
+    fn test_check_issued_token() -> Result<()> {
+        let token_request = token_request();
+        let (token, group_public_key, group_public_elgamal_key) =
+            issue_token_for_request(&token_request)?;
+
+        assert!(
+            token_request
+                .check_issued_token(
+                    &token,
+                    &group_public_key,
+                    &group_public_elgamal_key
+                )
+                .is_ok(),
+            "Valid token was rejected."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_bad_signature() -> Result<()> {
+        let token_request = token_request();
+        let (token, _, group_public_elgamal_key) =
+            issue_token_for_request(&token_request)?;
+
+        // signed by a different set of moderators
+        let (_, other_group_public_key, _) =
+            issue_token_for_request(&token_request)?;
+
+        assert!(
+            token_request
+                .check_issued_token(
+                    &token,
+                    &other_group_public_key,
+                    &group_public_elgamal_key
+                )
+                .is_err(),
+            "Token with an invalid signature was accepted."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_mismatched_pk_e() -> Result<()> {
+        let token_request = token_request();
+
+        let mut other_request = token_request.clone();
+        other_request.pk_e =
+            EphemeralSecretKey::<Ristretto255>::random(&mut rand::thread_rng())
+                .public_key();
+        let (token, group_public_key, group_public_elgamal_key) =
+            issue_token_for_request(&other_request)?;
+
+        assert!(
+            token_request
+                .check_issued_token(
+                    &token,
+                    &group_public_key,
+                    &group_public_elgamal_key
+                )
+                .is_err(),
+            "Token with someone else's ephemeral key was accepted."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_wrong_x_1() -> Result<()> {
+        let token_request = token_request();
+
+        let mut other_request = token_request.clone();
+        other_request.user_id = UserId::random(&mut rand::thread_rng());
+        let (token, group_public_key, group_public_elgamal_key) =
+            issue_token_for_request(&other_request)?;
+
+        assert!(
+            token_request
+                .check_issued_token(
+                    &token,
+                    &group_public_key,
+                    &group_public_elgamal_key
+                )
+                .is_err(),
+            "Token encrypting someone else's id was accepted."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_wrong_payload() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let payload = Payload {
+            account_id: UserId::random(&mut rng),
+            device_id: Some(b"phone".to_vec()),
+            issuance_context: Vec::new(),
+        };
+        let (token_request, _) = TokenRequest::<Ristretto255>::with_payload(
+            payload.clone(),
+            &mut rng,
+        );
+
+        let (token, group_public_key, group_public_elgamal_key) =
+            issue_token_for_request(&token_request)?;
+        token_request.check_issued_token(
+            &token,
+            &group_public_key,
+            &group_public_elgamal_key,
+        )?;
+
+        // a token carrying a different payload
+        let mut other_request = token_request.clone();
+        other_request.payload = Some(Payload {
+            device_id: None,
+            ..payload
+        });
+        let (token, group_public_key, group_public_elgamal_key) =
+            issue_token_for_request(&other_request)?;
+        assert!(
+            token_request
+                .check_issued_token(
+                    &token,
+                    &group_public_key,
+                    &group_public_elgamal_key
+                )
+                .is_err(),
+            "Token encrypting a different payload was accepted."
+        );
+
+        // or none at all
+        let mut other_request = token_request.clone();
+        other_request.payload = None;
+        let (token, group_public_key, group_public_elgamal_key) =
+            issue_token_for_request(&other_request)?;
+        assert!(
+            token_request
+                .check_issued_token(
+                    &token,
+                    &group_public_key,
+                    &group_public_elgamal_key
+                )
+                .is_err(),
+            "Token without the requested payload was accepted."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_incoming() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let user_id = UserId::random(&mut rng);
+        let (token_request, sk_e) = TokenRequest::new(user_id, &mut rng);
+        let (token, group_public_key, group_public_elgamal_key) =
+            issue_token_for_request::<Ristretto255>(&token_request)?;
+
+        let platform = Platform::new(group_public_key, &mut rng);
+        let client = Client::new(
+            user_id,
+            group_public_key,
+            group_public_elgamal_key,
+            platform.public_key(),
+        );
+
+        let message = b"hello, world";
+        let envelope = client.seal(token, &sk_e, message)?;
+        assert!(
+            client.verify_incoming(&envelope, message).is_err(),
+            "Unstamped envelope was accepted."
+        );
+
+        let envelope = platform.stamp(&envelope)?;
+        assert!(
+            client.verify_incoming(&envelope, message).is_ok(),
+            "Valid envelope was rejected."
+        );
+        assert!(
+            client
+                .verify_incoming(&envelope, b"some other message")
+                .is_err(),
+            "Envelope was accepted for the wrong message."
+        );
+
+        Ok(())
+    }
+}
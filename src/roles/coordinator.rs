@@ -1,33 +1,266 @@
-use std::error::Error;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
 
 use chrono::Utc;
-use curve25519_dalek::scalar::Scalar;
-use frost_ristretto255 as frost;
+use frost_core::{frost, VerifyingKey};
 use futures::future;
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{
-    communication, elgamal,
-    token::{SignedToken, UnsignedToken},
-    Batch, Result, UserId,
+    ciphersuite::{Ciphersuite, Ristretto255},
+    communication::{self, signing::IdEvidence},
+    dkg::{self, Thresholds},
+    elgamal::{self, DecryptionError, IdEncryption},
+    identity::Signed,
+    message::Envelope,
+    payload::Payload,
+    refresh, reshare,
+    shamir::LagrangeCache,
+    token::{SignedToken, TimestampPolicy, TokenRequest, UnsignedToken},
+    Batch, ModeratorId, PlatformPublicKey, Result, UserId,
 };
 
-/// Nonce commitments from from all the moderators. Good for ONE batch of token-signing.
-/// Indexed like `commitments [moderator_index] [batch_index]`
-type CommitmentBatch = Vec<Batch<frost::round1::SigningCommitments>>;
+/// Nonce commitments from the live moderators. Good for ONE batch of token-signing.
+/// Indexed like `commitments [&moderator] [batch_index]`
+type CommitmentBatch<C> = HashMap<
+    ModeratorId,
+    Batch<frost::round1::SigningCommitments<<C as Ciphersuite>::Frost>>,
+>;
+
+pub struct Coordinator<C: Ciphersuite = Ristretto255> {
+    pub(crate) frost_public_key_package:
+        frost::keys::PublicKeyPackage<C::Frost>,
+    pub(crate) group_public_elgamal_key: elgamal::PublicKey<C>,
+
+    /// Each moderator's key for checking its decryption shares, by identifier.
+    decryption_verification_keys: HashMap<u16, elgamal::VerificationKey<C>>,
+
+    /// Lagrange coefficients for the sets of moderators that answered
+    /// recent decryption requests. These don't change when keys are
+    /// refreshed or reshared, since they only depend on the identifiers.
+    lagrange_cache: LagrangeCache<C>,
 
-pub struct Coordinator {
-    pub(crate) frost_public_key_package: frost::keys::PublicKeyPackage,
-    pub(crate) group_public_elgamal_key: elgamal::PublicKey,
     client: reqwest::Client,
 
-    nonce_commitments: CommitmentBatch,
+    nonce_commitments: CommitmentBatch<C>,
 
     // parameters
     batch_size: usize,
+    timestamp_policy: TimestampPolicy,
+
+    /// The moderators holding shares of the signing key.
+    signing_committee: Committee,
+
+    /// The moderators holding shares of the decryption key,
+    /// which may be the same as the signing committee.
+    decryption_committee: Committee,
+
+    /// The platform's stamping key, once registered.
+    platform_public_key: Option<PlatformPublicKey<C>>,
+
+    id_encryption: IdEncryption,
+    signing_mode: SigningMode,
+    invalid_share_policy: InvalidSharePolicy,
+
+    /// Moderators that sent invalid signature shares and are no longer
+    /// asked to sign. Only used with [`InvalidSharePolicy::Retry`].
+    excluded_signers: HashSet<ModeratorId>,
+
+    /// The refreshed verification keys of moderators that haven't committed
+    /// their refreshed key shares yet, see
+    /// [`Coordinator::retry_refresh_commits`].
+    uncommitted_refreshes: HashMap<ModeratorId, elgamal::VerificationKey<C>>,
+}
+
+/// How often the coordinator tries to commit a moderator's refreshed key share
+/// before giving up until [`Coordinator::retry_refresh_commits`] is called.
+const REFRESH_COMMIT_ATTEMPTS: usize = 3;
+
+/// The hostname prefix of the moderators when one committee holds both keys.
+const DEFAULT_HOST: &str = "cerberus-moderator";
+
+/// A committee of moderators holding shares of one of the group keys.
+///
+/// Moderator `i` of the committee is reachable at `http://{host}-{i}:80`.
+/// Committees with the same `host` consist of the same moderators.
+///
+/// The host is only a hostname prefix: every committee serves the same
+/// paths, so separate committees are told apart by their hosts alone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Committee {
+    host: String,
     n_moderators: usize,
-    decryption_threshold: usize,
-    _signing_threshold: usize, // TODO: do we need this?
+    threshold: usize,
+}
+
+impl Committee {
+    /// A committee of `n_moderators` moderators, any `threshold` of which
+    /// can use the key they share.
+    pub fn new(host: &str, n_moderators: usize, threshold: usize) -> Self {
+        assert!(n_moderators >= threshold);
+        assert!(threshold >= 1);
+
+        Self {
+            host: host.to_owned(),
+            n_moderators,
+            threshold,
+        }
+    }
+
+    fn moderators(&self) -> impl Iterator<Item = ModeratorId> {
+        ModeratorId::all(self.n_moderators)
+    }
+
+    fn url(&self, moderator: ModeratorId, endpoint: &str) -> String {
+        format!("http://{}-{moderator}:80/{endpoint}", self.host)
+    }
+}
+
+/// How the moderators' signing and decryption keys are generated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyGeneration {
+    /// The moderators run a distributed key generation protocol,
+    /// so that no single party ever learns the group secrets.
+    #[default]
+    Distributed,
+
+    /// The coordinator generates every key and sends out the shares.
+    TrustedDealer,
+}
+
+/// How the coordinator convinces the moderators that a token's `x_1` is a
+/// well-formed encryption of the id of the user that requested it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SigningMode {
+    /// Every moderator is sent the id and encryption randomness, and checks
+    /// the encryption itself. This lets every moderator link tokens to users.
+    #[default]
+    RevealId,
+
+    /// Every moderator is sent a commitment to the id and a zero-knowledge
+    /// proof that `x_1` encrypts the committed id, and never sees the id.
+    ///
+    /// `x_1` additionally encrypts the id with standard ElGamal, which the
+    /// proof is about, and decryption fails unless both encryptions agree.
+    /// Requests carrying a payload can't be signed in this mode, since the
+    /// payload contains the id.
+    ZeroKnowledge,
+}
+
+/// What the coordinator does when a moderator sends an invalid signature share.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InvalidSharePolicy {
+    /// Fail with a [`SigningError::InvalidSignatureShare`] naming the moderator.
+    #[default]
+    Abort,
+
+    /// Stop asking the moderator to sign, and sign the batch again without it.
+    Retry,
+}
+
+/// Errors that can occur while signing a batch of tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SigningError {
+    /// Fewer than `threshold` moderators are live and trusted to sign.
+    NotEnoughSigners { available: usize, threshold: usize },
+
+    /// `moderator` sent a signature share that doesn't verify against its
+    /// verifying share, or didn't send one share for every token.
+    InvalidSignatureShare { moderator: ModeratorId },
+
+    /// `moderator` refused to sign the batch, e.g., because one of the token
+    /// requests is invalid. The moderator is still live and keeps its nonces.
+    RequestRejected {
+        moderator: ModeratorId,
+        reason: String,
+    },
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughSigners {
+                available,
+                threshold,
+            } => write!(
+                f,
+                "Only {available} moderators are available to sign, \
+                but {threshold} are needed."
+            ),
+            Self::InvalidSignatureShare { moderator } => write!(
+                f,
+                "Moderator {moderator} sent an invalid signature share."
+            ),
+            Self::RequestRejected { moderator, reason } => write!(
+                f,
+                "Moderator {moderator} refused to sign the batch: {reason}"
+            ),
+        }
+    }
+}
+
+impl Error for SigningError {}
+
+/// A moderator responded to a request with an error, as opposed to not
+/// responding at all.
+#[derive(Debug)]
+struct Rejection {
+    moderator: ModeratorId,
+    reason: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Moderator {} rejected the request: {}",
+            self.moderator, self.reason
+        )
+    }
+}
+
+impl Error for Rejection {}
+
+/// The decryption shares sent by the decryption committee, along with the
+/// moderators that didn't send their own.
+struct DecryptionShares<C: Ciphersuite> {
+    shares: Vec<elgamal::DecryptionShare<C>>,
+    misaddressed: Vec<u16>,
+}
+
+impl<C: Ciphersuite> DecryptionShares<C> {
+    /// Decrypts with the shares, blaming the moderators that sent someone
+    /// else's share too if there aren't enough valid ones.
+    fn decrypt<T>(
+        self,
+        decrypt: impl FnOnce(&[elgamal::DecryptionShare<C>]) -> Result<T>,
+    ) -> Result<T> {
+        decrypt(&self.shares).map_err(|err| {
+            match err.downcast::<DecryptionError>() {
+                Ok(err) => {
+                    let DecryptionError::NotEnoughShares {
+                        valid,
+                        threshold,
+                        mut invalid_moderators,
+                    } = *err;
+                    invalid_moderators.extend(self.misaddressed);
+                    invalid_moderators.sort_unstable();
+                    invalid_moderators.dedup();
+
+                    DecryptionError::NotEnoughShares {
+                        valid,
+                        threshold,
+                        invalid_moderators,
+                    }
+                    .into()
+                }
+                Err(err) => err,
+            }
+        })
+    }
 }
 
 /// Enum representing whether or not the requests to each moderator
@@ -40,8 +273,9 @@ enum ModeratorRequest<'a, T> {
 /// A wrapper type around a vector storing moderator responses.
 type ModeratorResponses<Res> = Vec<Res>;
 
-impl Coordinator {
+impl<C: Ciphersuite> Coordinator<C> {
     /// Sets up the coordinator and moderators
+    /// using the default [`TimestampPolicy`].
     ///
     /// Returns a new coordinator object if successful.
     pub async fn init(
@@ -50,219 +284,1561 @@ impl Coordinator {
         decryption_threshold: usize,
         batch_size: usize,
     ) -> Result<Self> {
-        assert!(n_moderators >= signing_threshold);
-        assert!(n_moderators >= decryption_threshold);
+        Self::init_with_timestamp_policy(
+            n_moderators,
+            signing_threshold,
+            decryption_threshold,
+            batch_size,
+            TimestampPolicy::default(),
+        )
+        .await
+    }
+
+    /// Sets up the coordinator and moderators. The moderators enforce
+    /// `timestamp_policy` when signing and decrypting tokens.
+    ///
+    /// Returns a new coordinator object if successful.
+    pub async fn init_with_timestamp_policy(
+        n_moderators: usize,
+        signing_threshold: usize,
+        decryption_threshold: usize,
+        batch_size: usize,
+        timestamp_policy: TimestampPolicy,
+    ) -> Result<Self> {
+        Self::init_with_key_generation(
+            n_moderators,
+            signing_threshold,
+            decryption_threshold,
+            batch_size,
+            timestamp_policy,
+            KeyGeneration::default(),
+        )
+        .await
+    }
+
+    /// Sets up the coordinator and moderators, generating the moderators'
+    /// keys as specified by `key_generation`.
+    ///
+    /// Returns a new coordinator object if successful.
+    pub async fn init_with_key_generation(
+        n_moderators: usize,
+        signing_threshold: usize,
+        decryption_threshold: usize,
+        batch_size: usize,
+        timestamp_policy: TimestampPolicy,
+        key_generation: KeyGeneration,
+    ) -> Result<Self> {
+        Self::init_with_committees(
+            Committee::new(DEFAULT_HOST, n_moderators, signing_threshold),
+            Committee::new(DEFAULT_HOST, n_moderators, decryption_threshold),
+            batch_size,
+            timestamp_policy,
+            key_generation,
+            IdEncryption::default(),
+        )
+        .await
+    }
+
+    /// Sets up the coordinator with separate committees for signing tokens
+    /// and for decrypting reported ones. Each moderator only receives
+    /// shares of the keys for the committees that it's on.
+    ///
+    /// With [`KeyGeneration::Distributed`], committees with different hosts
+    /// each run their own key generation, and each learns the other
+    /// committee's group key from the coordinator.
+    ///
+    /// Sender ids are encrypted as specified by `id_encryption`, which the
+    /// moderators enforce when signing and decrypting tokens.
+    ///
+    /// Returns a new coordinator object if successful.
+    pub async fn init_with_committees(
+        signing_committee: Committee,
+        decryption_committee: Committee,
+        batch_size: usize,
+        timestamp_policy: TimestampPolicy,
+        key_generation: KeyGeneration,
+        id_encryption: IdEncryption,
+    ) -> Result<Self> {
         assert!(batch_size >= 1);
 
+        let shared_committee =
+            signing_committee.host == decryption_committee.host;
+        if shared_committee
+            && signing_committee.n_moderators
+                != decryption_committee.n_moderators
+        {
+            return Err("Committees with the same host must have the same \
+                moderators."
+                .into());
+        }
+
         let client = reqwest::Client::new();
 
         let (
             frost_public_key_package,
             group_public_elgamal_key,
+            decryption_verification_keys,
             nonce_commitments,
-        ) = Self::setup_moderators(
-            &client,
-            batch_size,
-            n_moderators,
-            signing_threshold,
-            decryption_threshold,
-        )
-        .await?;
+        ) = match key_generation {
+            KeyGeneration::Distributed => {
+                Self::setup_moderators_with_dkg(
+                    &client,
+                    batch_size,
+                    &signing_committee,
+                    &decryption_committee,
+                    timestamp_policy,
+                    id_encryption,
+                )
+                .await?
+            }
+            KeyGeneration::TrustedDealer => {
+                Self::setup_moderators_with_dealer(
+                    &client,
+                    batch_size,
+                    &signing_committee,
+                    &decryption_committee,
+                    timestamp_policy,
+                    id_encryption,
+                )
+                .await?
+            }
+        };
 
         Ok(Coordinator {
             client,
             frost_public_key_package,
             group_public_elgamal_key,
+            decryption_verification_keys,
+            lagrange_cache: LagrangeCache::default(),
             nonce_commitments,
-            n_moderators,
-            _signing_threshold: signing_threshold,
-            decryption_threshold,
             batch_size,
+            timestamp_policy,
+            signing_committee,
+            decryption_committee,
+            platform_public_key: None,
+            id_encryption,
+            signing_mode: SigningMode::default(),
+            invalid_share_policy: InvalidSharePolicy::default(),
+            excluded_signers: HashSet::new(),
+            uncommitted_refreshes: HashMap::new(),
         })
     }
 
-    async fn setup_moderators(
+    /// Whether the same moderators hold shares of both group keys.
+    fn shares_committee(&self) -> bool {
+        self.signing_committee.host == self.decryption_committee.host
+    }
+
+    /// Sets how the moderators check the id encryptions of the tokens they
+    /// sign.
+    pub fn set_signing_mode(&mut self, signing_mode: SigningMode) {
+        self.signing_mode = signing_mode;
+    }
+
+    /// Sets what happens when a moderator sends an invalid signature share.
+    pub fn set_invalid_share_policy(
+        &mut self,
+        invalid_share_policy: InvalidSharePolicy,
+    ) {
+        self.invalid_share_policy = invalid_share_policy;
+    }
+
+    /// The moderators that were caught sending invalid signature shares
+    /// and are no longer asked to sign.
+    pub fn excluded_signers(&self) -> impl Iterator<Item = &ModeratorId> {
+        self.excluded_signers.iter()
+    }
+
+    /// The moderators' group verifying key, used to check the signatures on issued tokens.
+    pub fn group_verifying_key(&self) -> VerifyingKey<C::Frost> {
+        self.frost_public_key_package.group_public
+    }
+
+    /// The moderators' group encryption key, under which sender ids are encrypted.
+    pub fn group_elgamal_key(&self) -> elgamal::PublicKey<C> {
+        self.group_public_elgamal_key
+    }
+
+    /// Generates the moderators' keys centrally and sends each moderator its shares.
+    ///
+    /// The coordinator learns both group secrets, so this is only suitable for testing.
+    async fn setup_moderators_with_dealer(
         client: &reqwest::Client,
         batch_size: usize,
-        n_moderators: usize,
-        signing_threshold: usize,
-        decryption_threshold: usize,
+        signing_committee: &Committee,
+        decryption_committee: &Committee,
+        timestamp_policy: TimestampPolicy,
+        id_encryption: IdEncryption,
     ) -> Result<(
-        frost::keys::PublicKeyPackage,
-        elgamal::PublicKey,
-        CommitmentBatch,
+        frost::keys::PublicKeyPackage<C::Frost>,
+        elgamal::PublicKey<C>,
+        HashMap<u16, elgamal::VerificationKey<C>>,
+        CommitmentBatch<C>,
     )> {
         let mut rng = rand::thread_rng();
 
         let (frost_secret_shares, frost_public_key) =
-            frost::keys::keygen_with_dealer(
-                n_moderators as u16,
-                signing_threshold as u16,
+            frost::keys::keygen_with_dealer::<C::Frost, _>(
+                signing_committee.n_moderators as u16,
+                signing_committee.threshold as u16,
                 &mut rng,
             )?;
 
-        let (elgamal_public_key, elgamal_key_shares) =
-            elgamal::generate_private_key_shares(
+        let (elgamal_public_key, elgamal_key_shares, elgamal_commitments) =
+            elgamal::generate_private_key_shares::<C, _>(
                 &mut rng,
-                n_moderators,
-                decryption_threshold,
+                decryption_committee.n_moderators,
+                decryption_committee.threshold,
             );
 
-        let mut request_bodies = Vec::with_capacity(n_moderators);
-        request_bodies.extend((0..n_moderators).map(|i| {
+        let setup_request = |frost_secret_share, elgamal_secret_share| {
             communication::setup::Request {
-                frost_secret_share: frost_secret_shares[i].clone(),
-                elgamal_secret_share: elgamal_key_shares[i].clone(),
+                frost_secret_share,
+                elgamal_secret_share,
+                group_public_key: C::verifying_key_to_bytes(
+                    &frost_public_key.group_public,
+                ),
+                elgamal_commitments: elgamal_commitments.clone(),
                 batch_size,
+                timestamp_policy,
+                id_encryption,
             }
-        }));
+        };
 
-        let responses = query_moderators::<_, communication::setup::Response>(
-            client,
-            "setup",
-            ModeratorRequest::Unique(&request_bodies),
-            n_moderators,
-        )
-        .await?;
+        // a shared committee gets both shares in one request,
+        // otherwise each committee only gets its own shares
+        let signing_requests: Vec<_> =
+            if signing_committee.host == decryption_committee.host {
+                frost_secret_shares
+                    .iter()
+                    .zip(&elgamal_key_shares)
+                    .map(|(frost_share, elgamal_share)| {
+                        setup_request(
+                            Some(frost_share.clone()),
+                            Some(elgamal_share.clone()),
+                        )
+                    })
+                    .collect()
+            } else {
+                let decryption_requests: Vec<_> = elgamal_key_shares
+                    .iter()
+                    .map(|share| setup_request(None, Some(share.clone())))
+                    .collect();
 
-        let nonce_commitments = responses
-            .into_iter()
-            .map(|response| response.nonce_commitments)
+                let responses =
+                    query_moderators::<_, communication::setup::Response<C>>(
+                        client,
+                        "setup",
+                        ModeratorRequest::Unique(&decryption_requests),
+                        decryption_committee,
+                    )
+                    .await?;
+                Self::confirm_setup(client, &responses, decryption_committee)
+                    .await?;
+
+                frost_secret_shares
+                    .iter()
+                    .map(|share| setup_request(Some(share.clone()), None))
+                    .collect()
+            };
+
+        let responses =
+            query_moderators::<_, communication::setup::Response<C>>(
+                client,
+                "setup",
+                ModeratorRequest::Unique(&signing_requests),
+                signing_committee,
+            )
+            .await?;
+        Self::confirm_setup(client, &responses, signing_committee).await?;
+
+        let nonce_commitments = signing_committee
+            .moderators()
+            .zip(responses)
+            .map(|(moderator, response)| {
+                (moderator, response.nonce_commitments)
+            })
+            .collect();
+
+        let verification_keys = elgamal_key_shares
+            .iter()
+            .map(|share| (share.identifier(), share.verification_key()))
             .collect();
 
-        Ok((frost_public_key, elgamal_public_key, nonce_commitments))
+        Ok((
+            frost_public_key,
+            elgamal_public_key,
+            verification_keys,
+            nonce_commitments,
+        ))
     }
 
-    pub async fn create_tokens(
-        &mut self,
-        user_ids: &Batch<UserId>,
-    ) -> Result<Batch<SignedToken>> {
-        // create signing requests to sent to the moderators
-        let signing_requests = self.create_signing_requests(user_ids);
+    /// Relays every moderator's confirmation of a dealt setup to the rest of
+    /// its committee, who only start serving requests once everyone has
+    /// confirmed that they were sent the same public values.
+    async fn confirm_setup(
+        client: &reqwest::Client,
+        responses: &[communication::setup::Response<C>],
+        committee: &Committee,
+    ) -> Result<()> {
+        let request = communication::setup::ConfirmRequest {
+            confirmations: responses
+                .iter()
+                .map(|response| response.confirmation.clone())
+                .collect(),
+        };
 
-        let request = communication::signing::Request {
-            // FIX: this clone doesn't seem like it should be necessary...
-            signing_requests: signing_requests.clone(),
+        query_moderators::<_, ()>(
+            client,
+            "setup/confirm",
+            ModeratorRequest::Same(&request),
+            committee,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Runs distributed key generation between the moderators.
+    ///
+    /// The coordinator only relays messages: it never sees any secret shares,
+    /// since these are encrypted to their recipients, and it can't tamper
+    /// with the encryption keys or commitments, since the moderators sign
+    /// them with their identity keys.
+    ///
+    /// A single committee generates both keys together. Separate committees
+    /// each run key generation for their own key, and are told the other
+    /// committee's group key by the coordinator.
+    async fn setup_moderators_with_dkg(
+        client: &reqwest::Client,
+        batch_size: usize,
+        signing_committee: &Committee,
+        decryption_committee: &Committee,
+        timestamp_policy: TimestampPolicy,
+        id_encryption: IdEncryption,
+    ) -> Result<(
+        frost::keys::PublicKeyPackage<C::Frost>,
+        elgamal::PublicKey<C>,
+        HashMap<u16, elgamal::VerificationKey<C>>,
+        CommitmentBatch<C>,
+    )> {
+        let committees = if signing_committee.host == decryption_committee.host
+        {
+            vec![(
+                signing_committee,
+                Thresholds::both(
+                    signing_committee.threshold,
+                    decryption_committee.threshold,
+                ),
+            )]
+        } else {
+            vec![
+                (
+                    signing_committee,
+                    Thresholds {
+                        signing: Some(signing_committee.threshold),
+                        decryption: None,
+                    },
+                ),
+                (
+                    decryption_committee,
+                    Thresholds {
+                        signing: None,
+                        decryption: Some(decryption_committee.threshold),
+                    },
+                ),
+            ]
         };
 
-        // get signature shares from each moderator for all tokens in the batch
-        let moderator_responses =
-            query_moderators::<_, communication::signing::Response>(
-                &self.client,
-                "signing",
-                ModeratorRequest::Same(&request),
-                self.n_moderators,
+        // round 1: every moderator commits to its polynomials, which already
+        // fixes the group keys before any shares are sent out
+        let mut round1_packages = Vec::with_capacity(committees.len());
+        let mut signing_commitments = None;
+        let mut decryption_commitments = None;
+        for (committee, thresholds) in &committees {
+            let packages = Self::keygen_round1(
+                client,
+                committee,
+                *thresholds,
+                batch_size,
+                timestamp_policy,
+                id_encryption,
             )
             .await?;
 
-        // package the results as a SignedToken batch
-        let mut signed_tokens = Vec::with_capacity(self.batch_size);
+            let (signing, decryption) = dkg::group_commitments(
+                packages.iter().map(|package| &package.message),
+            )?;
+            if signing.is_some() != thresholds.signing.is_some()
+                || decryption.is_some() != thresholds.decryption.is_some()
+            {
+                return Err("Moderators committed to the wrong keys.".into());
+            }
+            signing_commitments = signing_commitments.or(signing);
+            decryption_commitments = decryption_commitments.or(decryption);
 
-        for (i, signing_request) in signing_requests.into_iter().enumerate() {
-            let signature_shares: Vec<_> = moderator_responses
-                .iter()
-                .map(|response| response.signature_shares[i])
-                .collect();
+            round1_packages.push(packages);
+        }
 
-            let token = bincode::deserialize(
-                signing_request.signing_package.message(),
-            )?;
+        let signing_commitments =
+            signing_commitments.ok_or("No signing key was generated.")?;
+        let decryption_commitments =
+            decryption_commitments.ok_or("No decryption key was generated.")?;
 
-            let signature = frost::aggregate(
-                &signing_request.signing_package,
-                &signature_shares,
-                &self.frost_public_key_package,
-            )?;
+        let frost_public_key_package = dkg::frost_public_key_package(
+            &signing_commitments,
+            signing_committee.n_moderators,
+        )?;
+        let elgamal_public_key =
+            elgamal::PublicKey(decryption_commitments.public_key());
+        let verification_keys = dkg::elgamal_verification_keys(
+            &decryption_commitments,
+            decryption_committee.n_moderators,
+        );
 
-            signed_tokens.push(SignedToken { signature, token });
+        // rounds 2 and 3: every committee finishes its key generation
+        let mut nonce_commitments = HashMap::new();
+        for ((committee, thresholds), packages) in
+            committees.into_iter().zip(round1_packages)
+        {
+            let round3_responses = Self::keygen_shares(
+                client,
+                committee,
+                packages,
+                signing_commitments.public_key(),
+                elgamal_public_key,
+            )
+            .await?;
+
+            if thresholds.signing.is_some() {
+                nonce_commitments = committee
+                    .moderators()
+                    .zip(round3_responses)
+                    .map(|(moderator, response)| {
+                        (moderator, response.nonce_commitments)
+                    })
+                    .collect();
+            }
         }
 
-        self.nonce_commitments = moderator_responses
-            .into_iter()
-            .map(|response| response.new_nonce_commitments)
+        Ok((
+            frost_public_key_package,
+            elgamal_public_key,
+            verification_keys,
+            nonce_commitments,
+        ))
+    }
+
+    /// Round 1 of key generation: asks every moderator of `committee` to
+    /// commit to polynomials for the keys in `thresholds`.
+    async fn keygen_round1(
+        client: &reqwest::Client,
+        committee: &Committee,
+        thresholds: Thresholds,
+        batch_size: usize,
+        timestamp_policy: TimestampPolicy,
+        id_encryption: IdEncryption,
+    ) -> Result<Vec<Signed<C, dkg::Round1Package<C>>>> {
+        let n_moderators = committee.n_moderators;
+        let round1_requests: Vec<_> = (1..=n_moderators)
+            .map(|i| communication::keygen::Round1Request {
+                identifier: i as u16,
+                n_moderators,
+                thresholds,
+                batch_size,
+                timestamp_policy,
+                id_encryption,
+            })
             .collect();
 
-        Ok(signed_tokens)
+        Ok(
+            query_moderators::<_, communication::keygen::Round1Response<C>>(
+                client,
+                "keygen/round1",
+                ModeratorRequest::Unique(&round1_requests),
+                committee,
+            )
+            .await?
+            .into_iter()
+            .map(|response| response.package)
+            .collect(),
+        )
     }
 
-    pub async fn request_token_decryption(
-        &self,
-        token: &SignedToken,
-    ) -> Result<UserId> {
-        let request = communication::decryption::Request {
-            message: "some abusive message".as_bytes().to_owned(),
-            token: token.clone(),
+    /// Rounds 2 and 3 of key generation: relays the moderators' encrypted
+    /// shares, from which every moderator of `committee` derives its keys.
+    ///
+    /// The moderators check that the keys they hold shares of are the given
+    /// group keys.
+    async fn keygen_shares(
+        client: &reqwest::Client,
+        committee: &Committee,
+        round1_packages: Vec<Signed<C, dkg::Round1Package<C>>>,
+        group_public_key: C::Element,
+        group_public_elgamal_key: elgamal::PublicKey<C>,
+    ) -> Result<Vec<communication::keygen::Round3Response<C>>> {
+        // round 2: every moderator checks everyone's commitments
+        // and sends out encrypted shares
+        let round2_request = communication::keygen::Round2Request {
+            packages: round1_packages,
         };
 
-        let responses =
-            query_moderators::<_, communication::decryption::Response>(
-                &self.client,
-                "decryption",
-                ModeratorRequest::Same(&request),
-                self.n_moderators,
+        let round2_responses =
+            query_moderators::<_, communication::keygen::Round2Response>(
+                client,
+                "keygen/round2",
+                ModeratorRequest::Same(&round2_request),
+                committee,
             )
             .await?;
 
-        let decryption_shares: Vec<_> = responses
-            .into_iter()
-            .map(|response| response.decryption_share)
-            .collect();
+        // route every share to its recipient
+        let round3_requests: Vec<_> = route_shares(
+            round2_responses
+                .into_iter()
+                .flat_map(|response| response.encrypted_shares),
+            |shares| shares.recipient,
+            committee.n_moderators,
+        )?
+        .into_iter()
+        .map(|encrypted_shares| communication::keygen::Round3Request {
+            encrypted_shares,
+            group_public_key,
+            group_public_elgamal_key,
+        })
+        .collect();
 
-        token.token.x_1.decrypt_with_shares(
-            &decryption_shares[..self.decryption_threshold],
+        // round 3: every moderator checks its shares and derives its keys
+        query_moderators::<_, communication::keygen::Round3Response<C>>(
+            client,
+            "keygen/round3",
+            ModeratorRequest::Unique(&round3_requests),
+            committee,
         )
+        .await
     }
 
-    fn create_signing_requests(
-        &self,
-        user_ids: &Batch<UserId>,
-    ) -> Batch<communication::signing::SigningRequest> {
-        let mut rng = rand::thread_rng();
+    /// Proactively refreshes the moderators' ElGamal key shares.
+    ///
+    /// The group key is unchanged, so existing tokens can still be reported,
+    /// but shares leaked before the refresh are useless afterwards.
+    /// Should be run periodically, e.g., daily.
+    ///
+    /// Messages are relayed as in distributed key generation. The moderators
+    /// keep using their old shares until every refreshed share has been
+    /// checked against the commitments, and only then are they told to
+    /// commit to the new ones, so a refresh that fails before that point
+    /// leaves every moderator's share untouched.
+    ///
+    /// Commits are retried a few times. A moderator that still hasn't
+    /// committed keeps its refreshed share pending, but its decryption shares
+    /// don't count until [`Self::retry_refresh_commits`] succeeds. No new
+    /// refresh is started until then, since that would discard the pending
+    /// share.
+    ///
+    /// Only the decryption committee takes part.
+    pub async fn refresh_decryption_shares(&mut self) -> Result<()> {
+        self.retry_refresh_commits().await?;
 
-        let mut requests = Vec::with_capacity(self.batch_size);
-        for (i, user_id) in user_ids.iter().enumerate() {
-            let elgamal_randomness = Scalar::random(&mut rng);
+        let committee = &self.decryption_committee;
 
-            let signing_package = {
-                // create unsigned token struct
-                let token = UnsignedToken {
-                    timestamp: Utc::now().timestamp(),
-                    x_1: self
-                        .group_public_elgamal_key
-                        .encrypt(user_id, &elgamal_randomness),
-                    pk_e: [0u8; 32], // TODO make this a real key
-                };
+        // round 1: every moderator commits to a zero polynomial
+        let round1_request = communication::refresh::Round1Request {
+            n_moderators: committee.n_moderators,
+            decryption_threshold: committee.threshold,
+        };
 
-                // serialize the token so it can be passed to frost::sign()
-                let token_bytes = bincode::serialize(&token).unwrap();
+        let packages: Vec<_> =
+            query_moderators::<_, communication::refresh::Round1Response<C>>(
+                &self.client,
+                "refresh/round1",
+                ModeratorRequest::Same(&round1_request),
+                committee,
+            )
+            .await?
+            .into_iter()
+            .map(|response| response.package)
+            .collect();
 
-                // collect the signing_commitments
-                let signing_commitments = (0..self.n_moderators)
-                    .map(|moderator_index| {
-                        self.nonce_commitments[moderator_index][i]
-                    })
-                    .collect();
+        // round 2: every moderator checks the commitments
+        // and sends out encrypted shares
+        let round2_request = communication::refresh::Round2Request {
+            packages: packages.clone(),
+        };
 
-                // create the signing package
-                frost::round2::SigningPackage::new(
-                    signing_commitments,
-                    token_bytes,
-                )
-            };
+        let round2_responses =
+            query_moderators::<_, communication::refresh::Round2Response>(
+                &self.client,
+                "refresh/round2",
+                ModeratorRequest::Same(&round2_request),
+                committee,
+            )
+            .await?;
 
-            requests.push(communication::signing::SigningRequest {
-                signing_package,
-                elgamal_randomness,
-                user_id: user_id.to_owned(),
-            })
+        let round3_requests: Vec<_> = route_shares(
+            round2_responses
+                .into_iter()
+                .flat_map(|response| response.encrypted_shares),
+            |share| share.recipient,
+            committee.n_moderators,
+        )?
+        .into_iter()
+        .map(|encrypted_shares| communication::refresh::Round3Request {
+            encrypted_shares,
+        })
+        .collect();
+
+        // round 3: every moderator checks its shares and updates its key share
+        let round3_responses =
+            query_moderators::<_, communication::refresh::Round3Response<C>>(
+                &self.client,
+                "refresh/round3",
+                ModeratorRequest::Unique(&round3_requests),
+                committee,
+            )
+            .await?;
+
+        // update the keys used to check decryption shares
+        let packages: Vec<_> = packages
+            .into_iter()
+            .map(|package| package.message)
+            .collect();
+        let verification_keys = refresh::refreshed_verification_keys(
+            &self.decryption_verification_keys,
+            &packages,
+        )?;
+
+        for (i, response) in (1..).zip(round3_responses) {
+            if verification_keys.get(&i) != Some(&response.verification_key) {
+                return Err(format!(
+                    "Moderator {i} refreshed its key share incorrectly."
+                )
+                .into());
+            }
+        }
+
+        // round 4: every refreshed share checks out, so the moderators
+        // can switch over to them
+        self.begin_refresh_commits(verification_keys);
+        self.retry_refresh_commits().await
+    }
+
+    /// Switches over to the refreshed verification keys, with every moderator
+    /// yet to commit its refreshed key share.
+    ///
+    /// Shares from moderators that haven't committed fail verification
+    /// against their new keys, so they can't be mixed with refreshed ones.
+    fn begin_refresh_commits(
+        &mut self,
+        verification_keys: HashMap<u16, elgamal::VerificationKey<C>>,
+    ) {
+        self.uncommitted_refreshes = self
+            .decryption_committee
+            .moderators()
+            .filter_map(|moderator| {
+                let key = verification_keys.get(&u16::from(moderator))?;
+                Some((moderator, *key))
+            })
+            .collect();
+        self.decryption_verification_keys = verification_keys;
+    }
+
+    /// Tells the moderators that haven't committed their refreshed key shares
+    /// yet to do so, trying each one up to [`REFRESH_COMMIT_ATTEMPTS`] times.
+    ///
+    /// Moderators keep their refreshed shares pending until they commit, so
+    /// this can be called again later, e.g., once an unreachable moderator
+    /// is back. Fails if any moderator still hasn't committed.
+    pub async fn retry_refresh_commits(&mut self) -> Result<()> {
+        for _ in 0..REFRESH_COMMIT_ATTEMPTS {
+            if self.uncommitted_refreshes.is_empty() {
+                break;
+            }
+
+            let mut moderators: Vec<_> =
+                self.uncommitted_refreshes.keys().copied().collect();
+            moderators.sort_unstable();
+            let commit_requests: Vec<_> = moderators
+                .iter()
+                .map(|moderator| communication::refresh::CommitRequest {
+                    verification_key: self.uncommitted_refreshes[moderator],
+                })
+                .collect();
+
+            let commit_responses = query_each_moderator_in::<_, ()>(
+                &self.client,
+                "refresh/commit",
+                ModeratorRequest::Unique(&commit_requests),
+                &self.decryption_committee,
+                &moderators,
+            )
+            .await;
+
+            self.record_refresh_commits(&moderators, commit_responses);
+        }
+
+        if self.uncommitted_refreshes.is_empty() {
+            return Ok(());
+        }
+
+        let mut uncommitted: Vec<_> =
+            self.uncommitted_refreshes.keys().copied().collect();
+        uncommitted.sort_unstable();
+        let uncommitted: Vec<_> =
+            uncommitted.iter().map(ModeratorId::to_string).collect();
+
+        let committed = self.decryption_committee.n_moderators
+            - self.uncommitted_refreshes.len();
+        let consequence = match committed >= self.decryption_committee.threshold
+        {
+            true => "Their decryption shares don't count until they do.",
+            false => "Reports can't be decrypted until they do.",
+        };
+
+        Err(format!(
+            "Moderators {} failed to commit their refreshed key shares. {}",
+            uncommitted.join(", "),
+            consequence,
+        )
+        .into())
+    }
+
+    /// Marks the moderators that acknowledged their commit as committed.
+    fn record_refresh_commits(
+        &mut self,
+        moderators: &[ModeratorId],
+        commit_responses: Vec<Result<()>>,
+    ) {
+        for (moderator, response) in moderators.iter().zip(commit_responses) {
+            if response.is_ok() {
+                self.uncommitted_refreshes.remove(moderator);
+            }
+        }
+    }
+
+    /// Moves the moderators' keys to a new committee of `n_moderators`
+    /// moderators with the given thresholds, without changing the group keys.
+    /// Existing tokens remain valid and reportable.
+    ///
+    /// Every current moderator deals its shares to the new committee, which
+    /// consists of moderators `1..=n_moderators`. Moderators that are only
+    /// in the new committee must be waiting for setup, and moderators that
+    /// are only in the old committee are shut down afterwards.
+    ///
+    /// Only supported when one committee holds both keys. Separate
+    /// committees are reshared with [`Self::reshare_signing_committee`]
+    /// and [`Self::reshare_decryption_committee`].
+    pub async fn reshare(
+        &mut self,
+        n_moderators: usize,
+        signing_threshold: usize,
+        decryption_threshold: usize,
+    ) -> Result<()> {
+        if !self.shares_committee() {
+            return Err(
+                "Separate committees must be reshared one at a time.".into()
+            );
+        }
+
+        let host = self.signing_committee.host.clone();
+        self.reshare_keys(
+            self.signing_committee.clone(),
+            Some(Committee::new(&host, n_moderators, signing_threshold)),
+            Some(Committee::new(&host, n_moderators, decryption_threshold)),
+        )
+        .await
+    }
+
+    /// Moves the signing key to a new signing committee of `n_moderators`
+    /// moderators, as in [`Self::reshare`]. The decryption committee is
+    /// unaffected.
+    ///
+    /// Only supported when the signing committee is separate from the
+    /// decryption committee.
+    pub async fn reshare_signing_committee(
+        &mut self,
+        n_moderators: usize,
+        threshold: usize,
+    ) -> Result<()> {
+        if self.shares_committee() {
+            return Err("One committee holds both keys, so both must be \
+                reshared together."
+                .into());
+        }
+
+        let old_committee = self.signing_committee.clone();
+        let new_committee =
+            Committee::new(&old_committee.host, n_moderators, threshold);
+        self.reshare_keys(old_committee, Some(new_committee), None)
+            .await
+    }
+
+    /// Moves the decryption key to a new decryption committee of
+    /// `n_moderators` moderators, as in [`Self::reshare`]. The signing
+    /// committee is unaffected.
+    ///
+    /// Only supported when the decryption committee is separate from the
+    /// signing committee.
+    pub async fn reshare_decryption_committee(
+        &mut self,
+        n_moderators: usize,
+        threshold: usize,
+    ) -> Result<()> {
+        if self.shares_committee() {
+            return Err("One committee holds both keys, so both must be \
+                reshared together."
+                .into());
+        }
+
+        let old_committee = self.decryption_committee.clone();
+        let new_committee =
+            Committee::new(&old_committee.host, n_moderators, threshold);
+        self.reshare_keys(old_committee, None, Some(new_committee))
+            .await
+    }
+
+    /// Reshares the keys held by `old_committee` to the new committees,
+    /// which MUST have the same moderators as each other. Only the keys
+    /// with a new committee are reshared.
+    async fn reshare_keys(
+        &mut self,
+        old_committee: Committee,
+        signing_committee: Option<Committee>,
+        decryption_committee: Option<Committee>,
+    ) -> Result<()> {
+        let new_committee = signing_committee
+            .as_ref()
+            .or(decryption_committee.as_ref())
+            .expect("No keys to reshare.")
+            .clone();
+        let n_moderators = new_committee.n_moderators;
+        let thresholds = Thresholds {
+            signing: signing_committee
+                .as_ref()
+                .map(|committee| committee.threshold),
+            decryption: decryption_committee
+                .as_ref()
+                .map(|committee| committee.threshold),
+        };
+
+        // a moderator with an uncommitted refresh would deal its old share
+        if thresholds.decryption.is_some() {
+            self.retry_refresh_commits().await?;
+        }
+
+        let group_public_key = C::verifying_key_element(
+            &self.frost_public_key_package.group_public,
+        )?;
+
+        // round 1: the new moderators generate encryption keys
+        let round1_requests: Vec<_> = (1..=n_moderators)
+            .map(|i| communication::reshare::Round1Request {
+                identifier: i as u16,
+                batch_size: self.batch_size,
+                timestamp_policy: self.timestamp_policy,
+                id_encryption: self.id_encryption,
+            })
+            .collect();
+
+        let recipients: Vec<_> =
+            query_moderators::<_, communication::reshare::Round1Response<C>>(
+                &self.client,
+                "reshare/round1",
+                ModeratorRequest::Unique(&round1_requests),
+                &new_committee,
+            )
+            .await?
+            .into_iter()
+            .map(|response| response.encryption_key)
+            .collect();
+
+        // round 2: the old moderators vouch for their public keys and the
+        // group keys, and deal their shares to the new moderators
+        let dealer_keys = old_committee
+            .moderators()
+            .map(u16::from)
+            .map(|i| {
+                let signing_verifying_share = match thresholds.signing {
+                    Some(_) => {
+                        let verifying_share = self
+                            .frost_public_key_package
+                            .signer_pubkeys
+                            .get(&frost::Identifier::try_from(i)?)
+                            .ok_or("Missing verifying share.")?;
+                        Some(C::verifying_share_element(verifying_share)?)
+                    }
+                    None => None,
+                };
+                let decryption_verification_key = match thresholds.decryption {
+                    Some(_) => Some(
+                        self.decryption_verification_keys
+                            .get(&i)
+                            .ok_or("Missing decryption verification key.")?
+                            .0,
+                    ),
+                    None => None,
+                };
+
+                Ok(reshare::DealerKeys {
+                    dealer: i,
+                    signing_verifying_share,
+                    decryption_verification_key,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let context = reshare::ReshareContext {
+            dealer_keys,
+            thresholds,
+            group_public_key,
+            group_public_elgamal_key: self.group_public_elgamal_key,
+        };
+        let round2_request = communication::reshare::Round2Request {
+            recipients,
+            context: context.clone(),
+        };
+
+        let (dealings, confirmations): (Vec<_>, Vec<_>) =
+            query_moderators::<_, communication::reshare::Round2Response<C>>(
+                &self.client,
+                "reshare/round2",
+                ModeratorRequest::Same(&round2_request),
+                &old_committee,
+            )
+            .await?
+            .into_iter()
+            .map(|response| (response.dealing, response.confirmation))
+            .unzip();
+
+        // round 3: the new moderators check the dealings against
+        // the old moderators' public keys and combine their shares
+        let round3_request = communication::reshare::Round3Request {
+            dealings,
+            confirmations,
+            context,
+        };
+
+        let round3_responses =
+            query_moderators::<_, communication::reshare::Round3Response<C>>(
+                &self.client,
+                "reshare/round3",
+                ModeratorRequest::Same(&round3_request),
+                &new_committee,
+            )
+            .await?;
+
+        let (signing_commitments, decryption_commitments) =
+            reshare::group_commitments(
+                round3_request
+                    .dealings
+                    .iter()
+                    .map(|dealing| &dealing.message),
+            )?;
+        if signing_commitments.is_some() != thresholds.signing.is_some()
+            || decryption_commitments.is_some()
+                != thresholds.decryption.is_some()
+        {
+            return Err("Moderators dealt the wrong keys.".into());
+        }
+        let frost_public_key_package = signing_commitments
+            .map(|commitments| {
+                dkg::frost_public_key_package(&commitments, n_moderators)
+            })
+            .transpose()?;
+
+        // the new moderators check this too, but don't rely on them
+        let changes_signing_key =
+            frost_public_key_package.as_ref().map_or(false, |package| {
+                C::verifying_key_to_bytes(&package.group_public)
+                    != C::verifying_key_to_bytes(
+                        &self.frost_public_key_package.group_public,
+                    )
+            });
+        let changes_decryption_key =
+            decryption_commitments
+                .as_ref()
+                .map_or(false, |commitments| {
+                    commitments.public_key() != self.group_public_elgamal_key.0
+                });
+        if changes_signing_key || changes_decryption_key {
+            return Err("Resharing changed the group keys.".into());
+        }
+
+        // retire the moderators that aren't part of the new committee
+        let retired: Vec<_> =
+            old_committee.moderators().skip(n_moderators).collect();
+        self.shutdown_moderators_in(&old_committee, &retired)
+            .await?;
+
+        if let Some(frost_public_key_package) = frost_public_key_package {
+            self.frost_public_key_package = frost_public_key_package;
+            self.nonce_commitments = new_committee
+                .moderators()
+                .zip(round3_responses)
+                .map(|(moderator, response)| {
+                    (moderator, response.nonce_commitments)
+                })
+                .collect();
+
+            // every moderator in the new committee starts with fresh commitments
+            self.excluded_signers.clear();
+        }
+        if let Some(decryption_commitments) = decryption_commitments {
+            self.decryption_verification_keys = dkg::elgamal_verification_keys(
+                &decryption_commitments,
+                n_moderators,
+            );
+        }
+        if let Some(signing_committee) = signing_committee {
+            self.signing_committee = signing_committee;
+        }
+        if let Some(decryption_committee) = decryption_committee {
+            self.decryption_committee = decryption_committee;
+
+            // new moderators don't know the platform's key yet
+            if let Some(platform_public_key) = self.platform_public_key {
+                self.register_platform(&platform_public_key).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Registers the platform's stamping key with the decryption committee.
+    ///
+    /// Moderators only respond to reports of messages stamped with this key.
+    /// Each moderator accepts a single platform key, so this fails for any
+    /// other key once a platform has been registered.
+    pub async fn register_platform(
+        &mut self,
+        platform_public_key: &PlatformPublicKey<C>,
+    ) -> Result<()> {
+        self.platform_public_key = Some(*platform_public_key);
+
+        let request = communication::platform::Request {
+            platform_public_key: *platform_public_key,
+        };
+
+        query_moderators::<_, ()>(
+            &self.client,
+            "platform",
+            ModeratorRequest::Same(&request),
+            &self.decryption_committee,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Creates a batch of signed tokens, one for each request.
+    ///
+    /// The ephemeral key pairs are generated by the clients (see [`TokenRequest::new`]),
+    /// so only their public halves are ever seen by the coordinator and moderators.
+    ///
+    /// Each batch is signed by `signing_threshold` of the live moderators. If
+    /// any of them fail to respond, the batch is signed again without them,
+    /// so this only fails once fewer than `signing_threshold` moderators are
+    /// live. Moderators that dropped out are asked to rejoin on the next call.
+    ///
+    /// Every signature share is checked against its signer's verifying share.
+    /// An invalid share is handled as set by [`Self::set_invalid_share_policy`].
+    ///
+    /// Fails with [`SigningError::RequestRejected`] if a moderator refuses to
+    /// sign the batch, e.g., because a token request is invalid.
+    pub async fn create_tokens(
+        &mut self,
+        token_requests: &Batch<TokenRequest<C>>,
+    ) -> Result<Batch<SignedToken<C>>> {
+        self.fetch_missing_nonce_commitments().await;
+
+        loop {
+            let signers = self.choose_signers()?;
+
+            if let Some(signed_tokens) =
+                self.sign_tokens(token_requests, &signers).await?
+            {
+                return Ok(signed_tokens);
+            }
+        }
+    }
+
+    /// The first `signing_threshold` moderators that the coordinator
+    /// holds nonce commitments for.
+    fn choose_signers(&self) -> Result<Vec<ModeratorId>> {
+        let mut live: Vec<_> = self.nonce_commitments.keys().copied().collect();
+        let threshold = self.signing_committee.threshold;
+        if live.len() < threshold {
+            return Err(SigningError::NotEnoughSigners {
+                available: live.len(),
+                threshold,
+            }
+            .into());
+        }
+
+        live.sort();
+        live.truncate(threshold);
+
+        Ok(live)
+    }
+
+    /// Asks `signers` to sign a batch of tokens.
+    ///
+    /// Returns `None` if any of the signers failed to respond, in which case
+    /// their nonce commitments are discarded since they may have been used,
+    /// or if a signer sent an invalid share and the batch should be retried.
+    ///
+    /// A signer that refuses to sign hasn't used its nonces, so it is kept,
+    /// and the refusal is returned as a [`SigningError::RequestRejected`]
+    /// since a bad token request would be refused by any other signer too.
+    async fn sign_tokens(
+        &mut self,
+        token_requests: &Batch<TokenRequest<C>>,
+        signers: &[ModeratorId],
+    ) -> Result<Option<Batch<SignedToken<C>>>> {
+        // create signing requests to sent to the moderators
+        let signing_requests =
+            self.create_signing_requests(token_requests, signers)?;
+
+        let request = communication::signing::Request {
+            // FIX: this clone doesn't seem like it should be necessary...
+            signing_requests: signing_requests.clone(),
+        };
+
+        // get signature shares from each signer for all tokens in the batch
+        let responses =
+            query_each_moderator_in::<_, communication::signing::Response<C>>(
+                &self.client,
+                "signing",
+                ModeratorRequest::Same(&request),
+                &self.signing_committee,
+                signers,
+            )
+            .await;
+
+        let mut signature_shares = Vec::with_capacity(signers.len());
+        let mut rejection = None;
+        for (moderator, response) in signers.iter().zip(responses) {
+            match response {
+                // the signer has moved on to new nonces
+                Ok(response) => {
+                    self.nonce_commitments
+                        .insert(*moderator, response.new_nonce_commitments);
+                    signature_shares.push(response.signature_shares);
+                }
+                // the signer refused before signing, so its nonces are unused
+                Err(err) if err.is::<Rejection>() => {
+                    rejection = rejection.or(err.downcast::<Rejection>().ok());
+                }
+                // the signer may or may not have used its nonces
+                Err(_) => {
+                    self.nonce_commitments.remove(moderator);
+                }
+            }
+        }
+
+        if let Some(rejection) = rejection {
+            return Err(SigningError::RequestRejected {
+                moderator: rejection.moderator,
+                reason: rejection.reason,
+            }
+            .into());
+        }
+
+        if signature_shares.len() < signers.len() {
+            return Ok(None);
+        }
+
+        // shares can only be matched up with the tokens if every signer
+        // sent one for each of them
+        if let Some(culprit) = miscounted_shares::<C>(
+            &signature_shares,
+            signers,
+            signing_requests.len(),
+        ) {
+            self.handle_invalid_share(culprit)?;
+            return Ok(None);
+        }
+
+        // package the results as a SignedToken batch
+        let mut signed_tokens = Vec::with_capacity(token_requests.len());
+
+        for (i, signing_request) in signing_requests.into_iter().enumerate() {
+            let signature_shares: Vec<_> =
+                signature_shares.iter().map(|shares| shares[i]).collect();
+
+            let token = bincode::deserialize(
+                signing_request.signing_package.message(),
+            )?;
+
+            // check every share on its own, so that an invalid one
+            // can be blamed on whoever sent it
+            let culprit = misattributed_share::<C>(&signature_shares, signers)
+                .or_else(|| {
+                    invalid_signer::<C>(
+                        &signing_request.signing_package,
+                        &signature_shares,
+                        signers,
+                        &self.frost_public_key_package,
+                    )
+                });
+
+            if let Some(culprit) = culprit {
+                self.handle_invalid_share(culprit)?;
+                return Ok(None);
+            }
+
+            let signature = frost::aggregate(
+                &signing_request.signing_package,
+                &signature_shares,
+                &self.frost_public_key_package,
+            )?;
+            signed_tokens.push(SignedToken { signature, token });
+        }
+
+        Ok(Some(signed_tokens))
+    }
+
+    /// Applies the [`InvalidSharePolicy`] to a moderator that sent an invalid
+    /// signature share. Returns an error if signing should be aborted.
+    fn handle_invalid_share(&mut self, culprit: ModeratorId) -> Result<()> {
+        match self.invalid_share_policy {
+            InvalidSharePolicy::Retry => {
+                self.nonce_commitments.remove(&culprit);
+                self.excluded_signers.insert(culprit);
+
+                Ok(())
+            }
+            InvalidSharePolicy::Abort => {
+                Err(SigningError::InvalidSignatureShare { moderator: culprit }
+                    .into())
+            }
+        }
+    }
+
+    /// Asks the moderators that the coordinator holds no nonce commitments
+    /// for, e.g., because they failed to sign an earlier batch, for new ones.
+    ///
+    /// Moderators that still don't respond are left out of signing.
+    async fn fetch_missing_nonce_commitments(&mut self) {
+        let missing: Vec<_> = self
+            .signing_committee
+            .moderators()
+            .filter(|moderator| {
+                !self.nonce_commitments.contains_key(moderator)
+                    && !self.excluded_signers.contains(moderator)
+            })
+            .collect();
+
+        if missing.is_empty() {
+            return;
+        }
+
+        let responses = query_each_moderator_in::<
+            _,
+            communication::commitments::Response<C>,
+        >(
+            &self.client,
+            "commitments",
+            ModeratorRequest::Same(&()),
+            &self.signing_committee,
+            &missing,
+        )
+        .await;
+
+        for (moderator, response) in missing.into_iter().zip(responses) {
+            if let Ok(response) = response {
+                self.nonce_commitments
+                    .insert(moderator, response.nonce_commitments);
+            }
+        }
+    }
+
+    /// Reports `message` to the decryption committee, whose moderators each
+    /// check that `envelope` binds it to a valid token and is stamped by the
+    /// platform before responding with a decryption share.
+    ///
+    /// Succeeds as long as `decryption_threshold` moderators respond with
+    /// valid shares. Returns the id of the message's original sender.
+    ///
+    /// Otherwise fails with a [`DecryptionError::NotEnoughShares`] naming the
+    /// moderators that sent invalid shares or shares that weren't their own,
+    /// or with a moderator's reason for rejecting the report if none did.
+    pub async fn request_token_decryption(
+        &self,
+        envelope: &Envelope<C>,
+        message: &[u8],
+    ) -> Result<UserId> {
+        let responses = self.query_decryption_shares(envelope, message).await;
+
+        self.decrypt_with_responses(&envelope.token.token.x_1, responses)
+    }
+
+    /// Reports `message` as in [`Self::request_token_decryption`], but
+    /// returns the payload carried by the message's token.
+    ///
+    /// Fails if the token carries no payload.
+    pub async fn request_payload_decryption(
+        &self,
+        envelope: &Envelope<C>,
+        message: &[u8],
+    ) -> Result<Payload> {
+        let token = &envelope.token.token;
+        let payload = token
+            .payload
+            .as_ref()
+            .ok_or("The reported token carries no payload.")?;
+
+        let responses = self.query_decryption_shares(envelope, message).await;
+
+        let decryption_shares = self
+            .collect_decryption_shares(responses, |response| {
+                response.payload_share
+            })?;
+        decryption_shares.decrypt(|shares| {
+            payload.decrypt_with_cached_coefficients(
+                shares,
+                &self.decryption_verification_keys,
+                self.decryption_committee.threshold,
+                token.pk_e.as_ref(),
+                &self.lagrange_cache,
+            )
+        })
+    }
+
+    /// Asks every moderator on the decryption committee for its decryption
+    /// shares of the token in `envelope`.
+    async fn query_decryption_shares(
+        &self,
+        envelope: &Envelope<C>,
+        message: &[u8],
+    ) -> Vec<Result<communication::decryption::Response<C>>> {
+        let request = communication::decryption::Request {
+            message: message.to_owned(),
+            envelope: envelope.clone(),
+        };
+
+        query_each_moderator::<_, communication::decryption::Response<C>>(
+            &self.client,
+            "decryption",
+            ModeratorRequest::Same(&request),
+            &self.decryption_committee,
+        )
+        .await
+    }
+
+    /// Decrypts `x_1` with the decryption committee's responses, indexed
+    /// by moderator.
+    fn decrypt_with_responses(
+        &self,
+        x_1: &elgamal::EncryptedUserId<C>,
+        responses: Vec<Result<communication::decryption::Response<C>>>,
+    ) -> Result<UserId> {
+        let decryption_shares = self
+            .collect_decryption_shares(responses, |response| {
+                Some(response.decryption_share)
+            })?;
+
+        decryption_shares.decrypt(|shares| {
+            x_1.decrypt_with_cached_coefficients(
+                shares,
+                &self.decryption_verification_keys,
+                self.decryption_committee.threshold,
+                &self.lagrange_cache,
+            )
+        })
+    }
+
+    /// Picks out the decryption shares selected by `share` from the
+    /// decryption committee's responses, indexed by moderator.
+    ///
+    /// Moderators that leave out their share or send someone else's are
+    /// blamed if decryption fails. If too few moderators responded and none
+    /// of them misbehaved, the report was most likely rejected, so the first
+    /// moderator's reason for rejecting it is returned instead.
+    fn collect_decryption_shares(
+        &self,
+        responses: Vec<Result<communication::decryption::Response<C>>>,
+        share: impl Fn(
+            communication::decryption::Response<C>,
+        ) -> Option<elgamal::DecryptionShare<C>>,
+    ) -> Result<DecryptionShares<C>> {
+        let mut shares =
+            Vec::with_capacity(self.decryption_committee.n_moderators);
+        let mut misaddressed = Vec::new();
+        let mut errors = Vec::new();
+        for (i, response) in (1..).zip(responses) {
+            match response.map(&share) {
+                // a moderator may only send its own share
+                Ok(Some(share)) if share.identifier == i => shares.push(share),
+                Ok(_) => misaddressed.push(i),
+                Err(err) => errors.push(err),
+            }
         }
 
-        requests
+        if shares.len() < self.decryption_committee.threshold
+            && misaddressed.is_empty()
+        {
+            if let Some(err) = errors.into_iter().next() {
+                return Err(err);
+            }
+        }
+
+        Ok(DecryptionShares {
+            shares,
+            misaddressed,
+        })
     }
 
+    /// Creates a signing request for every token, with signing packages
+    /// built from the nonce commitments of `signers` only.
+    ///
+    /// Fails if a signer has fewer nonce commitments than there are tokens,
+    /// e.g., because more tokens were requested than fit in a batch.
+    fn create_signing_requests(
+        &self,
+        token_requests: &Batch<TokenRequest<C>>,
+        signers: &[ModeratorId],
+    ) -> Result<Batch<communication::signing::SigningRequest<C>>> {
+        // every token is signed with a nonce of its own from each signer
+        for moderator in signers {
+            let available = self
+                .nonce_commitments
+                .get(moderator)
+                .map_or(0, |commitments| commitments.len());
+            if available < token_requests.len() {
+                return Err(format!(
+                    "Moderator {moderator} only has {available} nonce \
+                    commitments, but {} tokens were requested (at most {} \
+                    per batch).",
+                    token_requests.len(),
+                    self.batch_size
+                )
+                .into());
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+
+        let mut requests = Vec::with_capacity(self.batch_size);
+        for (i, token_request) in token_requests.iter().enumerate() {
+            let elgamal_randomness = token_request.elgamal_randomness;
+
+            let x_1 = match self.signing_mode {
+                SigningMode::RevealId => self
+                    .group_public_elgamal_key
+                    .encrypt(&token_request.user_id, &elgamal_randomness),
+                SigningMode::ZeroKnowledge => {
+                    if token_request.payload.is_some() {
+                        return Err("Payloads can't be signed without \
+                            revealing the id to the moderators."
+                            .into());
+                    }
+
+                    self.group_public_elgamal_key.encrypt_provable(
+                        &token_request.user_id,
+                        &elgamal_randomness,
+                    )
+                }
+            };
+            let x_1 = match self.id_encryption {
+                IdEncryption::Hashed => x_1,
+                IdEncryption::Labeled => x_1.labeled(
+                    &self.group_public_elgamal_key,
+                    &token_request.user_id,
+                    &elgamal_randomness,
+                    token_request.pk_e.as_ref(),
+                ),
+            };
+
+            // create unsigned token struct
+            let token = UnsignedToken {
+                timestamp: Utc::now().timestamp(),
+                x_1,
+                pk_e: token_request.pk_e,
+                payload: token_request
+                    .encrypt_payload(&self.group_public_elgamal_key)?,
+            };
+
+            let signing_package = {
+                // serialize the token so it can be passed to frost::sign()
+                let token_bytes = bincode::serialize(&token).unwrap();
+
+                // collect the signers' signing_commitments
+                let signing_commitments = signers
+                    .iter()
+                    .map(|moderator| self.nonce_commitments[moderator][i])
+                    .collect();
+
+                // create the signing package
+                frost::SigningPackage::new(signing_commitments, token_bytes)
+            };
+
+            let id_evidence = match self.signing_mode {
+                SigningMode::RevealId => IdEvidence::Opening {
+                    user_id: token_request.user_id,
+                    elgamal_randomness,
+                },
+                SigningMode::ZeroKnowledge => {
+                    let id_commitment = elgamal::IdCommitment::new(
+                        &token_request.user_id,
+                        &token_request.id_blinding,
+                    );
+                    // the proof is bound to the whole token, so it can't be
+                    // replayed with a different timestamp or ephemeral key
+                    let proof = token.x_1.prove_committed_id(
+                        &self.group_public_elgamal_key,
+                        &id_commitment,
+                        &elgamal_randomness,
+                        &token_request.id_blinding,
+                        signing_package.message(),
+                        &mut rng,
+                    )?;
+
+                    IdEvidence::Proof {
+                        id_commitment,
+                        proof,
+                    }
+                }
+            };
+
+            requests.push(communication::signing::SigningRequest {
+                signing_package,
+                id_evidence,
+                payload: token_request.payload.clone(),
+                payload_randomness: token_request.payload_randomness,
+            })
+        }
+
+        Ok(requests)
+    }
+
+    /// Shuts down the moderators on both committees.
     pub async fn shutdown_moderators(&self) -> Result<()> {
-        future::try_join_all((1..=self.n_moderators).map(|i| async move {
-            let url = format!("http://cerberus-moderator-{i}:80/shutdown");
+        let signers: Vec<_> = self.signing_committee.moderators().collect();
+        self.shutdown_moderators_in(&self.signing_committee, &signers)
+            .await?;
+
+        if !self.shares_committee() {
+            let decryptors: Vec<_> =
+                self.decryption_committee.moderators().collect();
+            self.shutdown_moderators_in(
+                &self.decryption_committee,
+                &decryptors,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Shuts down the given moderators of `committee`.
+    async fn shutdown_moderators_in(
+        &self,
+        committee: &Committee,
+        moderators: &[ModeratorId],
+    ) -> Result<()> {
+        future::try_join_all(moderators.iter().map(|&i| async move {
+            let url = committee.url(i, "shutdown");
             let response = self.client.get(&url).send().await?;
 
             if response.status() == 200 {
@@ -277,6 +1853,86 @@ impl Coordinator {
     }
 }
 
+/// The first of `signers` whose batch of shares (sent in the same order)
+/// doesn't hold exactly one share for each of the `n_tokens` tokens.
+fn miscounted_shares<C: Ciphersuite>(
+    signature_shares: &[Batch<frost::round2::SignatureShare<C::Frost>>],
+    signers: &[ModeratorId],
+    n_tokens: usize,
+) -> Option<ModeratorId> {
+    signers
+        .iter()
+        .zip(signature_shares)
+        .find(|(_, shares)| shares.len() != n_tokens)
+        .map(|(moderator, _)| *moderator)
+}
+
+/// The first of `signers` whose share (sent in the same order) claims to be
+/// from another signer, which would get an invalid share blamed on them.
+fn misattributed_share<C: Ciphersuite>(
+    signature_shares: &[frost::round2::SignatureShare<C::Frost>],
+    signers: &[ModeratorId],
+) -> Option<ModeratorId> {
+    signers
+        .iter()
+        .zip(signature_shares)
+        .find(|(moderator, share)| {
+            share.identifier != frost::Identifier::<C::Frost>::from(**moderator)
+        })
+        .map(|(moderator, _)| *moderator)
+}
+
+/// The first of `signers` whose share (sent in the same order) doesn't
+/// verify against its verifying share, i.e., `z_i * G != R_i + c * λ_i * Y_i`.
+fn invalid_signer<C: Ciphersuite>(
+    signing_package: &frost::SigningPackage<C::Frost>,
+    signature_shares: &[frost::round2::SignatureShare<C::Frost>],
+    signers: &[ModeratorId],
+    public_key_package: &frost::keys::PublicKeyPackage<C::Frost>,
+) -> Option<ModeratorId> {
+    signers
+        .iter()
+        .zip(signature_shares)
+        .find(|(moderator, share)| {
+            let identifier = frost::Identifier::<C::Frost>::from(**moderator);
+
+            public_key_package.signer_pubkeys.get(&identifier).map_or(
+                true,
+                |verifying_share| {
+                    frost::verify_signature_share(
+                        identifier,
+                        verifying_share,
+                        share,
+                        signing_package,
+                        &public_key_package.group_public,
+                    )
+                    .is_err()
+                },
+            )
+        })
+        .map(|(moderator, _)| *moderator)
+}
+
+/// Groups `shares` by their (1-indexed) recipient.
+fn route_shares<S>(
+    shares: impl IntoIterator<Item = S>,
+    recipient: impl Fn(&S) -> u16,
+    n_moderators: usize,
+) -> Result<Vec<Vec<S>>> {
+    let mut routed: Vec<_> = (0..n_moderators)
+        .map(|_| Vec::with_capacity(n_moderators - 1))
+        .collect();
+
+    for share in shares {
+        routed
+            .get_mut((recipient(&share) as usize).wrapping_sub(1))
+            .ok_or("Moderator sent a share to an unknown recipient.")?
+            .push(share);
+    }
+
+    Ok(routed)
+}
+
 /// Sends a query to every moderator at the provided endpoint and with the provided body.
 ///
 /// Returns an array of type [`Res`; [`N_MODERATORS`]]
@@ -284,43 +1940,611 @@ async fn query_moderators<Req, Res>(
     client: &reqwest::Client,
     endpoint: &str,
     payload: ModeratorRequest<'_, Req>,
-    n_moderators: usize,
+    committee: &Committee,
 ) -> Result<ModeratorResponses<Res>>
 where
     Req: Serialize + DeserializeOwned,
     Res: Serialize + DeserializeOwned,
 {
-    let payload = &payload;
-    let responses =
-        future::try_join_all((1..=n_moderators).map(|i| async move {
-            let url = format!("http://cerberus-moderator-{i}:80/{endpoint}");
-            let body = {
-                let body_struct = match payload {
-                    ModeratorRequest::Same(body) => body,
-                    ModeratorRequest::Unique(bodies) => &bodies[i - 1], // zero-indexed
-                };
+    query_each_moderator(client, endpoint, payload, committee)
+        .await
+        .into_iter()
+        .collect()
+}
+
+/// Like [`query_moderators`], but returns each moderator's response
+/// (or error) separately instead of failing if any moderator does.
+async fn query_each_moderator<Req, Res>(
+    client: &reqwest::Client,
+    endpoint: &str,
+    payload: ModeratorRequest<'_, Req>,
+    committee: &Committee,
+) -> Vec<Result<Res>>
+where
+    Req: Serialize + DeserializeOwned,
+    Res: Serialize + DeserializeOwned,
+{
+    let moderators: Vec<_> = committee.moderators().collect();
+
+    query_each_moderator_in(client, endpoint, payload, committee, &moderators)
+        .await
+}
 
-                bincode::serialize(body_struct)?
+/// Like [`query_each_moderator`], but only queries `moderators`.
+///
+/// Unique requests are indexed like `moderators`.
+async fn query_each_moderator_in<Req, Res>(
+    client: &reqwest::Client,
+    endpoint: &str,
+    payload: ModeratorRequest<'_, Req>,
+    committee: &Committee,
+    moderators: &[ModeratorId],
+) -> Vec<Result<Res>>
+where
+    Req: Serialize + DeserializeOwned,
+    Res: Serialize + DeserializeOwned,
+{
+    let payload = &payload;
+    future::join_all((0..).zip(moderators).map(|(index, &i)| async move {
+        let url = committee.url(i, endpoint);
+        let body = {
+            let body_struct = match payload {
+                ModeratorRequest::Same(body) => body,
+                ModeratorRequest::Unique(bodies) => &bodies[index],
             };
 
-            let response = client.get(&url).body(body).send().await?;
+            bincode::serialize(body_struct)?
+        };
+
+        let response = client.get(&url).body(body).send().await?;
 
-            // error on non-200 responses
-            if response.status() != reqwest::StatusCode::OK {
-                return Err(format!(
-                    "Received unsuccessful response from moderator {i}"
-                )
-                .into());
+        // the moderator refused the request, and said why
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Err(Rejection {
+                moderator: i,
+                reason: response.text().await?,
             }
+            .into());
+        }
+
+        // error on other non-200 responses
+        if response.status() != reqwest::StatusCode::OK {
+            return Err(format!(
+                "Received unsuccessful response from moderator {i}"
+            )
+            .into());
+        }
+
+        Ok::<Res, Box<dyn Error>>({
+            let bytes = response.bytes().await?;
+            let body: Res = bincode::deserialize(&bytes)?;
+
+            body
+        })
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        invalid_signer, misattributed_share, miscounted_shares, Committee,
+        Coordinator, IdEncryption, InvalidSharePolicy, SigningError,
+        SigningMode, DEFAULT_HOST,
+    };
+    use crate::{
+        ciphersuite::{Ciphersuite, Ristretto255},
+        communication::{decryption, signing::IdEvidence},
+        elgamal::{generate_private_key_shares, DecryptionError, KeyShare},
+        payload::Payload,
+        shamir::{LagrangeCache, Polynomial},
+        token::{TimestampPolicy, TokenRequest, UnsignedToken},
+        ModeratorId, Result, UserId,
+    };
+    use frost_core::frost;
+    use std::collections::{HashMap, HashSet};
+
+    type Frost = <Ristretto255 as Ciphersuite>::Frost;
+
+    /// Signs `message` with every one of `key_packages`, returning the
+    /// signing package and everyone's shares in order.
+    fn sign(
+        key_packages: &[frost::keys::KeyPackage<Frost>],
+        message: &[u8],
+    ) -> Result<(
+        frost::SigningPackage<Frost>,
+        Vec<frost::round2::SignatureShare<Frost>>,
+    )> {
+        let mut rng = rand::thread_rng();
+        let (nonces, commitments): (Vec<_>, Vec<_>) = key_packages
+            .iter()
+            .map(|key_package| {
+                frost::round1::commit(
+                    key_package.identifier,
+                    &key_package.secret_share,
+                    &mut rng,
+                )
+            })
+            .unzip();
+
+        let signing_package =
+            frost::SigningPackage::new(commitments, message.to_vec());
+        let signature_shares = key_packages
+            .iter()
+            .zip(&nonces)
+            .map(|(key_package, nonces)| {
+                frost::round2::sign(&signing_package, nonces, key_package)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok((signing_package, signature_shares))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn dealt_keys(
+        n: usize,
+        threshold: usize,
+    ) -> Result<(
+        Vec<frost::keys::KeyPackage<Frost>>,
+        frost::keys::PublicKeyPackage<Frost>,
+    )> {
+        let (secret_shares, public_key_package) =
+            frost::keys::keygen_with_dealer::<Frost, _>(
+                n as u16,
+                threshold as u16,
+                &mut rand::thread_rng(),
+            )?;
 
-            Ok::<Res, Box<dyn Error>>({
-                let bytes = response.bytes().await?;
-                let body: Res = bincode::deserialize(&bytes)?;
+        let key_packages = secret_shares
+            .into_iter()
+            .map(frost::keys::KeyPackage::try_from)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
 
-                body
+        // put moderator `i`'s key package at index `i - 1`
+        let key_packages = ModeratorId::all(n)
+            .map(|moderator| {
+                key_packages
+                    .iter()
+                    .find(|key_package| {
+                        key_package.identifier
+                            == frost::Identifier::from(moderator)
+                    })
+                    .cloned()
+                    .ok_or_else(|| "Missing key package.".into())
             })
-        }))
-        .await?;
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((key_packages, public_key_package))
+    }
+
+    /// A coordinator that holds nonce commitments for every moderator,
+    /// without any moderators behind it, and the moderators' ElGamal shares.
+    fn coordinator(
+        n: usize,
+        threshold: usize,
+    ) -> Result<(Coordinator, Vec<KeyShare<Ristretto255>>)> {
+        let (_, frost_public_key_package) = dealt_keys(n, threshold)?;
+        let (group_public_elgamal_key, elgamal_key_shares, _) =
+            generate_private_key_shares::<Ristretto255, _>(
+                &mut rand::thread_rng(),
+                n,
+                threshold,
+            );
+
+        let coordinator = Coordinator {
+            client: reqwest::Client::new(),
+            frost_public_key_package,
+            group_public_elgamal_key,
+            decryption_verification_keys: elgamal_key_shares
+                .iter()
+                .map(|share| (share.identifier(), share.verification_key()))
+                .collect(),
+            lagrange_cache: LagrangeCache::default(),
+            nonce_commitments: ModeratorId::all(n)
+                .map(|moderator| (moderator, Vec::new()))
+                .collect::<HashMap<_, _>>(),
+            batch_size: 1,
+            timestamp_policy: TimestampPolicy::default(),
+            signing_committee: Committee::new(DEFAULT_HOST, n, threshold),
+            decryption_committee: Committee::new(DEFAULT_HOST, n, threshold),
+            platform_public_key: None,
+            id_encryption: IdEncryption::default(),
+            signing_mode: SigningMode::default(),
+            invalid_share_policy: InvalidSharePolicy::default(),
+            excluded_signers: HashSet::new(),
+            uncommitted_refreshes: HashMap::new(),
+        };
+
+        Ok((coordinator, elgamal_key_shares))
+    }
+
+    /// Gives the coordinator a single nonce commitment for each of the `n`
+    /// moderators.
+    fn add_nonce_commitments(
+        coordinator: &mut Coordinator,
+        n: usize,
+    ) -> Result<()> {
+        let (key_packages, _) = dealt_keys(n, n)?;
+        for (moderator, key_package) in ModeratorId::all(n).zip(&key_packages) {
+            let (_, commitments) = frost::round1::commit(
+                key_package.identifier,
+                &key_package.secret_share,
+                &mut rand::thread_rng(),
+            );
+            coordinator
+                .nonce_commitments
+                .insert(moderator, vec![commitments]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_misattributed_share() -> Result<()> {
+        let (key_packages, _) = dealt_keys(3, 3)?;
+        let (_, mut signature_shares) = sign(&key_packages, b"token")?;
+        let signers: Vec<_> = ModeratorId::all(3).collect();
+
+        assert_eq!(
+            misattributed_share::<Ristretto255>(&signature_shares, &signers),
+            None
+        );
+
+        // moderator 2 passes off moderator 3's share as its own
+        signature_shares[1] = signature_shares[2];
+        assert_eq!(
+            misattributed_share::<Ristretto255>(&signature_shares, &signers),
+            Some(signers[1])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_miscounted_shares() -> Result<()> {
+        let (key_packages, _) = dealt_keys(3, 3)?;
+        let signers: Vec<_> = ModeratorId::all(3).collect();
+        let mut signature_shares = Vec::new();
+        for token in [b"token 1", b"token 2"] {
+            let (_, shares) = sign(&key_packages, token)?;
+            signature_shares.push(shares);
+        }
+        // one batch of shares per signer, with a share for each token
+        let mut batches: Vec<Vec<_>> = (0..3)
+            .map(|i| signature_shares.iter().map(|shares| shares[i]).collect())
+            .collect();
+
+        assert_eq!(
+            miscounted_shares::<Ristretto255>(&batches, &signers, 2),
+            None
+        );
+
+        // moderator 2 only signs the first token
+        batches[1].truncate(1);
+        assert_eq!(
+            miscounted_shares::<Ristretto255>(&batches, &signers, 2),
+            Some(signers[1])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_signer() -> Result<()> {
+        let (key_packages, public_key_package) = dealt_keys(3, 3)?;
+        let (signing_package, mut signature_shares) =
+            sign(&key_packages, b"token")?;
+        let signers: Vec<_> = ModeratorId::all(3).collect();
+
+        assert_eq!(
+            invalid_signer::<Ristretto255>(
+                &signing_package,
+                &signature_shares,
+                &signers,
+                &public_key_package
+            ),
+            None
+        );
+
+        // moderator 3 signs a different message with its nonces
+        let (_, other_shares) = sign(&key_packages, b"another token")?;
+        signature_shares[2] = other_shares[2];
+
+        assert_eq!(
+            invalid_signer::<Ristretto255>(
+                &signing_package,
+                &signature_shares,
+                &signers,
+                &public_key_package
+            ),
+            Some(signers[2])
+        );
+
+        // and the remaining shares still verify on their own
+        assert_eq!(
+            invalid_signer::<Ristretto255>(
+                &signing_package,
+                &signature_shares[..2],
+                &signers[..2],
+                &public_key_package
+            ),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_abort_on_invalid_share() -> Result<()> {
+        let (mut coordinator, _) = coordinator(3, 2)?;
+        let culprit = ModeratorId::try_from(2)?;
+
+        let err = coordinator.handle_invalid_share(culprit).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SigningError>(),
+            Some(&SigningError::InvalidSignatureShare { moderator: culprit })
+        );
+
+        // the moderator is still asked to sign
+        assert_eq!(coordinator.excluded_signers().count(), 0);
+        assert!(coordinator.choose_signers()?.contains(&culprit));
 
-    Ok(responses)
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_on_invalid_share() -> Result<()> {
+        let (mut coordinator, _) = coordinator(3, 2)?;
+        coordinator.set_invalid_share_policy(InvalidSharePolicy::Retry);
+        let culprit = ModeratorId::try_from(1)?;
+
+        coordinator.handle_invalid_share(culprit)?;
+
+        // the batch is signed again without the moderator
+        assert_eq!(
+            coordinator.excluded_signers().collect::<Vec<_>>(),
+            vec![&culprit]
+        );
+        assert_eq!(
+            coordinator.choose_signers()?,
+            ModeratorId::all(3).skip(1).collect::<Vec<_>>()
+        );
+
+        // until too few moderators are left
+        coordinator.handle_invalid_share(ModeratorId::try_from(2)?)?;
+        let err = coordinator.choose_signers().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SigningError>(),
+            Some(&SigningError::NotEnoughSigners {
+                available: 1,
+                threshold: 2
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_knowledge_signing_requests() -> Result<()> {
+        let (mut coordinator, key_shares) = coordinator(3, 2)?;
+        coordinator.set_signing_mode(SigningMode::ZeroKnowledge);
+        let mut rng = rand::thread_rng();
+
+        add_nonce_commitments(&mut coordinator, 3)?;
+        let signers = coordinator.choose_signers()?;
+
+        let id = UserId::random(&mut rng);
+        let (token_request, _) = TokenRequest::new(id, &mut rng);
+        let requests = coordinator
+            .create_signing_requests(&vec![token_request], &signers)?;
+
+        // the moderators are only sent a commitment to the id, and a proof
+        // that the token encrypts it
+        let message = requests[0].signing_package.message();
+        let token: UnsignedToken<Ristretto255> = bincode::deserialize(message)?;
+        let IdEvidence::Proof {
+            id_commitment,
+            proof,
+        } = &requests[0].id_evidence
+        else {
+            panic!("Id evidence reveals the id.");
+        };
+        token.x_1.verify_committed_id(
+            &coordinator.group_public_elgamal_key,
+            id_commitment,
+            proof,
+            message,
+        )?;
+
+        // while the id still decrypts as usual
+        let decryption_shares: Vec<_> = key_shares
+            .iter()
+            .map(|key_share| key_share.decryption_share(&token.x_1, &mut rng))
+            .collect();
+        assert_eq!(
+            id,
+            token.x_1.decrypt_with_shares(
+                &decryption_shares,
+                &coordinator.decryption_verification_keys,
+                2
+            )?
+        );
+
+        // payloads would reveal the id
+        let (token_request, _) = TokenRequest::with_payload(
+            Payload {
+                account_id: id,
+                device_id: None,
+                issuance_context: Vec::new(),
+            },
+            &mut rng,
+        );
+        assert!(coordinator
+            .create_signing_requests(&vec![token_request], &signers)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_larger_than_nonce_commitments() -> Result<()> {
+        let (mut coordinator, _) = coordinator(3, 2)?;
+        let mut rng = rand::thread_rng();
+
+        add_nonce_commitments(&mut coordinator, 3)?;
+        let signers = coordinator.choose_signers()?;
+
+        let token_requests: Vec<_> = (0..2)
+            .map(|_| TokenRequest::new(UserId::random(&mut rng), &mut rng).0)
+            .collect();
+        assert!(coordinator
+            .create_signing_requests(&token_requests, &signers)
+            .is_err());
+        assert_eq!(
+            coordinator
+                .create_signing_requests(
+                    &token_requests[..1].to_vec(),
+                    &signers
+                )?
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decryption_blames_misbehaving_moderators() -> Result<()> {
+        let (coordinator, key_shares) = coordinator(3, 2)?;
+        let mut rng = rand::thread_rng();
+
+        let id = UserId::random(&mut rng);
+        let x_1 = coordinator
+            .group_public_elgamal_key
+            .encrypt(&id, &Ristretto255::random_scalar(&mut rng));
+        let response = |key_share: &KeyShare<Ristretto255>| {
+            Ok(decryption::Response {
+                decryption_share: key_share
+                    .decryption_share(&x_1, &mut rand::thread_rng()),
+                payload_share: None,
+            })
+        };
+
+        let responses = key_shares.iter().map(response).collect();
+        assert_eq!(coordinator.decrypt_with_responses(&x_1, responses)?, id);
+
+        // moderator 2 relays moderator 1's share, and moderator 3 is offline
+        let responses = vec![
+            response(&key_shares[0]),
+            response(&key_shares[0]),
+            Err("Moderator 3 is offline.".into()),
+        ];
+
+        let err = coordinator
+            .decrypt_with_responses(&x_1, responses)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<DecryptionError>(),
+            Some(&DecryptionError::NotEnoughShares {
+                valid: 1,
+                threshold: 2,
+                invalid_moderators: vec![2],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_refresh_commit() -> Result<()> {
+        let (mut coordinator, key_shares) = coordinator(4, 3)?;
+        let mut rng = rand::thread_rng();
+
+        // refresh every share with the same zero polynomial
+        let zero = Polynomial::<Ristretto255>::random(
+            &mut rng,
+            Ristretto255::scalar_from_u64(0),
+            3,
+        );
+        let refreshed_shares: Vec<_> = key_shares
+            .iter()
+            .map(|key_share| {
+                key_share.refreshed(&zero.evaluate(
+                    &Ristretto255::scalar_from_u64(
+                        key_share.identifier() as u64
+                    ),
+                ))
+            })
+            .collect();
+        coordinator.begin_refresh_commits(
+            refreshed_shares
+                .iter()
+                .map(|share| (share.identifier(), share.verification_key()))
+                .collect(),
+        );
+
+        // moderator 2's commit is dropped, so it still uses its old share
+        let moderators: Vec<_> = ModeratorId::all(4).collect();
+        coordinator.record_refresh_commits(
+            &moderators,
+            vec![Ok(()), Err("Connection reset.".into()), Ok(()), Ok(())],
+        );
+        assert_eq!(
+            coordinator.uncommitted_refreshes.keys().collect::<Vec<_>>(),
+            vec![&moderators[1]]
+        );
+
+        let id = UserId::random(&mut rng);
+        let x_1 = coordinator
+            .group_public_elgamal_key
+            .encrypt(&id, &Ristretto255::random_scalar(&mut rng));
+        let response = |key_share: &KeyShare<Ristretto255>| {
+            Ok(decryption::Response {
+                decryption_share: key_share
+                    .decryption_share(&x_1, &mut rand::thread_rng()),
+                payload_share: None,
+            })
+        };
+
+        // its old share isn't mixed with the refreshed ones...
+        let responses = vec![
+            response(&refreshed_shares[0]),
+            response(&key_shares[1]),
+            response(&refreshed_shares[2]),
+            response(&refreshed_shares[3]),
+        ];
+        assert_eq!(coordinator.decrypt_with_responses(&x_1, responses)?, id);
+
+        let responses = vec![
+            response(&refreshed_shares[0]),
+            response(&key_shares[1]),
+            response(&refreshed_shares[2]),
+            Err("Moderator 4 is offline.".into()),
+        ];
+        let err = coordinator
+            .decrypt_with_responses(&x_1, responses)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<DecryptionError>(),
+            Some(&DecryptionError::NotEnoughShares {
+                valid: 2,
+                threshold: 3,
+                invalid_moderators: vec![2],
+            })
+        );
+
+        // ...until a retried commit goes through
+        coordinator.record_refresh_commits(&moderators[1..2], vec![Ok(())]);
+        assert!(coordinator.uncommitted_refreshes.is_empty());
+
+        let responses = vec![
+            response(&refreshed_shares[0]),
+            response(&refreshed_shares[1]),
+            response(&refreshed_shares[2]),
+            Err("Moderator 4 is offline.".into()),
+        ];
+        assert_eq!(coordinator.decrypt_with_responses(&x_1, responses)?, id);
+
+        Ok(())
+    }
 }
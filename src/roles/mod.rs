@@ -1,2 +1,4 @@
+pub mod client;
 pub mod coordinator;
 pub mod moderator;
+pub mod platform;
@@ -1,37 +1,80 @@
 use std::error::Error;
 
-use crate::{communication, elgamal, token::UnsignedToken, Batch, Result};
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    communication::{self, signing::IdEvidence},
+    dkg,
+    elgamal::{self, Ciphertext, IdEncryption},
+    identity::ModeratorIdentity,
+    refresh, reshare,
+    shamir::Commitments,
+    token::{TimestampPolicy, UnsignedToken},
+    Batch, PlatformPublicKey, Result,
+};
+use chrono::Utc;
 use frost::{
     round1::{SigningCommitments, SigningNonces},
     round2::SignatureShare,
 };
-use frost_ristretto255 as frost;
+use frost_core::{frost, VerifyingKey};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+pub struct Moderator<C: Ciphersuite = Ristretto255> {
+    /// Authenticates this moderator to the rest of its committee.
+    identity: ModeratorIdentity<C>,
+
+    // key material, for the committees that this moderator is on
+    sk_signing: Option<frost::keys::KeyPackage<C::Frost>>,
+    encryption_keys: Option<elgamal::KeyShare<C>>,
 
-pub struct Moderator {
-    // key material
-    sk_signing: frost::keys::KeyPackage,
-    encryption_keys: elgamal::KeyShare,
+    // the group keys, which are needed to check requests
+    // even by moderators without a share of them
+    group_public_key: VerifyingKey<C::Frost>,
+    group_public_elgamal_key: elgamal::PublicKey<C>,
 
     /// The size of the token-creation batches requested from the user/coordinator.
     batch_size: usize,
 
+    /// Bounds on the timestamps of tokens being signed or reported.
+    timestamp_policy: TimestampPolicy,
+
+    /// How the ids in tokens being signed or reported must be encrypted.
+    id_encryption: IdEncryption,
+
+    /// The key used by the platform to stamp delivered messages.
+    ///
+    /// Reports are rejected until this is registered by the coordinator.
+    platform_public_key: Option<PlatformPublicKey<C>>,
+
     /// The next batch of nonces to use
     ///
     /// These MUST be kept in sync with the commitment values sent to the coordinator.
-    nonces: Batch<SigningNonces>,
+    nonces: Batch<SigningNonces<C::Frost>>,
+
+    /// The state of a key share refresh, if one is in progress.
+    pending_refresh: Option<refresh::Refresh<C>>,
+
+    /// The refreshed ElGamal key share, which replaces the current one
+    /// once the coordinator has checked everyone's refreshed shares.
+    pending_key_share: Option<elgamal::KeyShare<C>>,
+
+    /// The state of a reshare to a committee that this moderator is part of,
+    /// if one is in progress.
+    pending_reshare: Option<PendingReshare<C>>,
 }
 
-impl Moderator {
+impl<C: Ciphersuite> Moderator<C> {
     /// Runs the Moderator's HTTP server until it receives a shutdown request.
-    pub fn run_server(server: &tiny_http::Server) -> Result<()> {
-        // wait for setup request and handle it.
-        let request = server.recv()?;
-        assert_eq!(
-            request.url(),
-            "/setup",
-            "First request to moderator was not a setup request"
-        );
-        let mut moderator = Self::new_from_setup_request(request)?;
+    ///
+    /// The `identity` authenticates the moderator to the rest of its
+    /// committee during key generation.
+    pub fn run_server(
+        server: &tiny_http::Server,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<()> {
+        // wait for setup or key generation requests and handle them.
+        let mut moderator = Self::setup(server, identity)?;
 
         println!("Setup successful.");
 
@@ -41,7 +84,45 @@ impl Moderator {
             let request = server.recv()?;
             match request.url() {
                 "/signing" => moderator.handle_signing(request)?,
+                "/commitments" => moderator.handle_commitments(request)?,
                 "/decryption" => moderator.handle_decryption(request)?,
+                "/platform" => {
+                    moderator.handle_platform_registration(request)?
+                }
+                "/refresh/round1" => {
+                    moderator.handle_refresh_round1(request)?
+                }
+                "/refresh/round2" => {
+                    moderator.handle_refresh_round2(request)?
+                }
+                "/refresh/round3" => {
+                    moderator.handle_refresh_round3(request)?
+                }
+                "/refresh/commit" => {
+                    moderator.handle_refresh_commit(request)?
+                }
+                "/reshare/round1" => {
+                    // the old keys are kept if the reshare fails
+                    moderator.pending_reshare =
+                        PendingReshare::round1(request, identity).ok()
+                }
+                "/reshare/round2" => {
+                    moderator.handle_reshare_round2(request)?
+                }
+                "/reshare/round3" => {
+                    // the old keys are kept if the reshare fails
+                    let pending_reshare = moderator.pending_reshare.take();
+                    if let Ok(mut new_moderator) = PendingReshare::round3(
+                        pending_reshare,
+                        request,
+                        identity,
+                    ) {
+                        new_moderator.platform_public_key =
+                            moderator.platform_public_key;
+                        moderator = new_moderator;
+                        println!("Reshare successful.");
+                    }
+                }
                 "/shutdown" => {
                     request.respond(tiny_http::Response::empty(200))?;
                     println!("Shutdown successful.");
@@ -52,35 +133,144 @@ impl Moderator {
         }
     }
 
+    /// Obtains the moderator's keys, either from a single setup request
+    /// sent by a trusted dealer, by running distributed key generation
+    /// with the other moderators, or by joining an existing committee
+    /// through a reshare.
+    fn setup(
+        server: &tiny_http::Server,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<Self> {
+        let mut dealt_setup: Option<PendingSetup<C>> = None;
+        let mut key_generation: Option<PendingKeyGeneration<C>> = None;
+        let mut reshare: Option<PendingReshare<C>> = None;
+
+        loop {
+            let request = server.recv()?;
+            match request.url() {
+                "/setup" => {
+                    dealt_setup =
+                        Some(Self::new_from_setup_request(request, identity)?)
+                }
+                "/setup/confirm" => {
+                    break dealt_setup
+                        .take()
+                        .ok_or("No setup request has been received.")?
+                        .confirm(request, identity)
+                }
+                "/keygen/round1" => {
+                    key_generation =
+                        Some(PendingKeyGeneration::round1(request, identity)?)
+                }
+                "/keygen/round2" => key_generation
+                    .as_mut()
+                    .ok_or("Key generation round 1 has not been run.")?
+                    .round2(request)?,
+                "/keygen/round3" => {
+                    break key_generation
+                        .take()
+                        .ok_or("Key generation round 1 has not been run.")?
+                        .round3(request, identity)
+                }
+                "/reshare/round1" => {
+                    reshare = Some(PendingReshare::round1(request, identity)?)
+                }
+                "/reshare/round3" => {
+                    break PendingReshare::round3(
+                        reshare.take(),
+                        request,
+                        identity,
+                    )
+                }
+                other => println!("Invalid endpoint before setup: {other}"),
+            }
+        }
+    }
+
     /// Creates a new [`Moderator`] object from a [`communication::setup::Request`] sent by the [`Coordinator`].
+    ///
+    /// The moderator only starts serving requests once the rest of its
+    /// committee confirms that they were sent the same public values, see
+    /// [`PendingSetup::confirm`].
     fn new_from_setup_request(
         mut request: tiny_http::Request,
-    ) -> Result<Moderator> {
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<PendingSetup<C>> {
         // println!("Received setup request from coordinator.");
 
         // deserialize request body
-        let body: communication::setup::Request =
+        let body: communication::setup::Request<C> =
             bincode::deserialize_from(request.as_reader())?;
 
-        // unpack the FROST key package
-        let frost_key_package =
-            frost::keys::KeyPackage::try_from(body.frost_secret_share)?;
+        let group_public_key =
+            match C::verifying_key_from_bytes(body.group_public_key) {
+                Ok(group_public_key) => group_public_key,
+                Err(err) => return reject_setup(request, err),
+            };
+        let group_public_elgamal_key =
+            elgamal::PublicKey(body.elgamal_commitments.public_key());
+
+        // unpack the FROST key package, verifying the share against
+        // the dealer's commitments
+        let frost_key_package = body
+            .frost_secret_share
+            .map(frost::keys::KeyPackage::try_from)
+            .transpose()?;
+
+        if let Some(key_package) = &frost_key_package {
+            if C::verifying_key_to_bytes(&key_package.group_public)
+                != body.group_public_key
+            {
+                return reject_setup(
+                    request,
+                    "FROST key share doesn't match the group public key."
+                        .into(),
+                );
+            }
+        }
+
+        // make sure the ElGamal key share is consistent with everyone else's
+        if let Some(key_share) = &body.elgamal_secret_share {
+            if let Err(err) = key_share.verify(&body.elgamal_commitments) {
+                return reject_setup(request, err);
+            }
+        }
 
         // create `Moderator` object and the first batch of FROST nonce commitments
-        let (moderator, nonce_commitments) = Moderator::new(
+        let (moderator, nonce_commitments) = Self::new(
+            identity,
             frost_key_package,
             body.elgamal_secret_share,
+            group_public_key,
+            group_public_elgamal_key,
             body.batch_size,
+            body.timestamp_policy,
+            body.id_encryption,
         );
 
+        // vouch for the public values that we were sent
+        let digest = setup_digest(
+            &body.group_public_key,
+            &body.elgamal_commitments,
+            body.id_encryption,
+        )?;
+        let confirmation =
+            match identity.sign(&mut rand::thread_rng(), SETUP_LABEL, digest) {
+                Ok(confirmation) => confirmation,
+                Err(err) => return reject_setup(request, err),
+            };
+
         // send response back to coordinator
         request.respond({
-            let body = communication::setup::Response { nonce_commitments };
+            let body = communication::setup::Response {
+                nonce_commitments,
+                confirmation,
+            };
             let bytes = bincode::serialize(&body)?;
 
             tiny_http::Response::from_data(bytes)
         })?;
-        Ok(moderator)
+        Ok(PendingSetup { moderator, digest })
     }
 
     /// Handles a signing request from the [`Coordinator`]
@@ -88,11 +278,18 @@ impl Moderator {
         &mut self,
         mut request: tiny_http::Request,
     ) -> Result<()> {
-        let body: communication::signing::Request =
+        let body: communication::signing::Request<C> =
             bincode::deserialize_from(request.as_reader())?;
 
+        // refuse to sign invalid requests, but keep the server running
         let (signature_shares, new_nonce_commitments) =
-            self.sign_batch(&body.signing_requests)?;
+            match self.sign_batch(&body.signing_requests) {
+                Ok(signed) => signed,
+                Err(err) => {
+                    println!("Rejected signing request: {err}");
+                    return respond_with_error(request, err.as_ref());
+                }
+            };
 
         request.respond({
             let body = communication::signing::Response {
@@ -106,30 +303,100 @@ impl Moderator {
         Ok(())
     }
 
+    /// Replaces the moderator's nonces and sends the coordinator commitments
+    /// to the new ones.
+    ///
+    /// The coordinator asks for these when it has discarded the commitments
+    /// to the old nonces, e.g., after this moderator failed to sign a batch.
+    fn handle_commitments(
+        &mut self,
+        request: tiny_http::Request,
+    ) -> Result<()> {
+        let signing_keys = match self.signing_keys() {
+            Ok(signing_keys) => signing_keys,
+            Err(err) => return respond_with_error(request, err.as_ref()),
+        };
+        let (new_nonces, nonce_commitments) =
+            Self::generate_nonces(signing_keys, self.batch_size);
+
+        self.nonces.zeroize();
+        self.nonces = new_nonces;
+
+        request.respond({
+            let body =
+                communication::commitments::Response::<C> { nonce_commitments };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(())
+    }
+
+    /// Creates a moderator with the key shares of the committees it's on.
+    ///
+    /// Only moderators on the signing committee generate nonces.
+    #[allow(clippy::too_many_arguments)]
     fn new(
-        signing_keys: frost::keys::KeyPackage,
-        encryption_keys: elgamal::KeyShare,
+        identity: &ModeratorIdentity<C>,
+        signing_keys: Option<frost::keys::KeyPackage<C::Frost>>,
+        encryption_keys: Option<elgamal::KeyShare<C>>,
+        group_public_key: VerifyingKey<C::Frost>,
+        group_public_elgamal_key: elgamal::PublicKey<C>,
         batch_size: usize,
-    ) -> (Self, Batch<SigningCommitments>) {
-        let (nonces, commitments) =
-            Moderator::generate_nonces(&signing_keys, batch_size);
+        timestamp_policy: TimestampPolicy,
+        id_encryption: IdEncryption,
+    ) -> (Self, Batch<SigningCommitments<C::Frost>>) {
+        let (nonces, commitments) = match &signing_keys {
+            Some(signing_keys) => {
+                Self::generate_nonces(signing_keys, batch_size)
+            }
+            None => (Vec::new(), Vec::new()),
+        };
 
         (
             Self {
+                identity: identity.clone(),
                 sk_signing: signing_keys,
                 nonces,
                 encryption_keys,
+                group_public_key,
+                group_public_elgamal_key,
                 batch_size,
+                timestamp_policy,
+                id_encryption,
+                platform_public_key: None,
+                pending_refresh: None,
+                pending_key_share: None,
+                pending_reshare: None,
             },
             commitments,
         )
     }
 
+    /// This moderator's share of the signing key.
+    fn signing_keys(&self) -> Result<&frost::keys::KeyPackage<C::Frost>> {
+        self.sk_signing.as_ref().ok_or_else(|| {
+            "This moderator is not on the signing committee.".into()
+        })
+    }
+
+    /// This moderator's share of the decryption key.
+    fn decryption_keys(&self) -> Result<&elgamal::KeyShare<C>> {
+        self.encryption_keys.as_ref().ok_or_else(|| {
+            "This moderator is not on the decryption committee.".into()
+        })
+    }
+
     /// Signs a new batch of tokens. This method also internally updates the stored nonces and returns a new batch of commitments.
     fn sign_batch(
         &mut self,
-        signing_requests: &Batch<communication::signing::SigningRequest>,
-    ) -> Result<(Batch<SignatureShare>, Batch<SigningCommitments>)> {
+        signing_requests: &Batch<communication::signing::SigningRequest<C>>,
+    ) -> Result<(
+        Batch<SignatureShare<C::Frost>>,
+        Batch<SigningCommitments<C::Frost>>,
+    )> {
+        let signing_keys = self.signing_keys()?;
+
         //  create signatures
         let mut signatures = Vec::with_capacity(self.batch_size);
 
@@ -142,31 +409,33 @@ impl Moderator {
 
         // create new nonces
         let (new_nonces, new_commitments) =
-            Moderator::generate_nonces(&self.sk_signing, self.batch_size);
+            Self::generate_nonces(signing_keys, self.batch_size);
 
-        // store the secrets, and return the new commitments alongside the signatures
+        // wipe the used nonces, store the new ones, and return
+        // the new commitments alongside the signatures
+        self.nonces.zeroize();
         self.nonces = new_nonces;
         Ok((signatures, new_commitments))
     }
 
     fn process_signing_request(
         &self,
-        signing_request: &communication::signing::SigningRequest,
-        nonces: &SigningNonces,
-    ) -> Result<SignatureShare> {
+        signing_request: &communication::signing::SigningRequest<C>,
+        nonces: &SigningNonces<C::Frost>,
+    ) -> Result<SignatureShare<C::Frost>> {
         self.verify_signing_request(signing_request)?;
         self.sign_signing_request(signing_request, nonces)
     }
 
     fn sign_signing_request(
         &self,
-        signing_request: &communication::signing::SigningRequest,
-        nonces: &SigningNonces,
-    ) -> Result<SignatureShare> {
+        signing_request: &communication::signing::SigningRequest<C>,
+        nonces: &SigningNonces<C::Frost>,
+    ) -> Result<SignatureShare<C::Frost>> {
         frost::round2::sign(
             &signing_request.signing_package,
             nonces,
-            &self.sk_signing,
+            self.signing_keys()?,
         )
         // deal with special frost error type
         .map_err(|_| "Failed to create signature share".into())
@@ -174,42 +443,98 @@ impl Moderator {
 
     fn verify_signing_request(
         &self,
-        signing_request: &communication::signing::SigningRequest,
+        signing_request: &communication::signing::SigningRequest<C>,
     ) -> Result<()> {
         // check that the thing being signed really is an encryption of
-        // the claimed UserId with the claimed randomness
-        let deserialized_token: UnsignedToken = {
+        // the claimed UserId, or of the id in the claimed commitment
+        let deserialized_token: UnsignedToken<C> = {
             let bytes = signing_request.signing_package.message();
             bincode::deserialize(bytes)
                     .map_err::<Box<dyn Error>, _>(
                         |_| "Failed to deserialize unsigned token in moderator signing request.".into())?
         };
 
-        let encryption_matches = {
-            let encryption_claimed = deserialized_token.x_1;
-            let encryption_calculated = self.encryption_keys.encrypt(
-                &signing_request.user_id,
-                &signing_request.elgamal_randomness,
-            );
-
-            match encryption_calculated == encryption_claimed {
+        let encryption_matches = match &signing_request.id_evidence {
+            IdEvidence::Opening {
+                user_id,
+                elgamal_randomness,
+            } => match deserialized_token.x_1.is_encryption_of(
+                &self.group_public_elgamal_key,
+                user_id,
+                elgamal_randomness,
+                deserialized_token.pk_e.as_ref(),
+            ) {
                 true => Ok(()),
                 false => {
                     Err("ID encryption doesn't match what is claimed.".into())
                 }
+            },
+            // the proof is bound to the whole token, so it can't be
+            // replayed with a different timestamp or ephemeral key
+            IdEvidence::Proof {
+                id_commitment,
+                proof,
+            } => deserialized_token.x_1.verify_committed_id(
+                &self.group_public_elgamal_key,
+                id_commitment,
+                proof,
+                signing_request.signing_package.message(),
+            ),
+        };
+
+        // labeled encryptions are bound to the token's ephemeral key
+        let label_valid = deserialized_token.x_1.check_policy(
+            self.id_encryption,
+            &self.group_public_elgamal_key,
+            deserialized_token.pk_e.as_ref(),
+        );
+
+        // the payload must belong to the same account, and be encrypted
+        // as claimed, which can only be checked if the id is revealed
+        let payload_matches = match (
+            &deserialized_token.payload,
+            &signing_request.payload,
+            &signing_request.id_evidence,
+        ) {
+            (None, None, _) => Ok(()),
+            (
+                Some(encrypted),
+                Some(payload),
+                IdEvidence::Opening { user_id, .. },
+            ) if payload.account_id == *user_id
+                && encrypted.is_encryption_of(
+                    &self.group_public_elgamal_key,
+                    payload,
+                    &signing_request.payload_randomness,
+                    deserialized_token.pk_e.as_ref(),
+                ) =>
+            {
+                Ok(())
+            }
+            _ => {
+                Err("Payload encryption doesn't match what is claimed.".into())
             }
         };
 
-        let timestamp_valid = Ok(());
+        let timestamp_valid = self.timestamp_policy.check_issuance(
+            deserialized_token.timestamp,
+            Utc::now().timestamp(),
+        );
 
-        encryption_matches.and(timestamp_valid)
+        encryption_matches
+            .and(label_valid)
+            .and(payload_matches)
+            .and(timestamp_valid)
     }
 
     // not a &self method because it's called by init
     fn generate_nonces(
-        frost_keys: &frost::keys::KeyPackage,
+        frost_keys: &frost::keys::KeyPackage<C::Frost>,
         batch_size: usize,
-    ) -> (Batch<SigningNonces>, Batch<SigningCommitments>) {
+    ) -> (
+        Batch<SigningNonces<C::Frost>>,
+        Batch<SigningCommitments<C::Frost>>,
+    ) {
         let mut rng = rand::thread_rng();
 
         // allocate vectors
@@ -230,21 +555,640 @@ impl Moderator {
         (nonces, commitments)
     }
 
+    /// Handles a request from the [`Coordinator`] registering the platform's stamping key.
+    ///
+    /// The key can only be registered once, since anyone able to replace it
+    /// could forge stamps. Registering the same key again is allowed.
+    fn handle_platform_registration(
+        &mut self,
+        mut request: tiny_http::Request,
+    ) -> Result<()> {
+        let body: communication::platform::Request<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        match self.platform_public_key {
+            Some(registered) if registered != body.platform_public_key => {
+                let err: Box<dyn Error> =
+                    "A different platform is already registered.".into();
+                println!("Rejected platform registration: {err}");
+                return respond_with_error(request, err.as_ref());
+            }
+            _ => self.platform_public_key = Some(body.platform_public_key),
+        }
+
+        request.respond(tiny_http::Response::empty(200))?;
+
+        Ok(())
+    }
+
+    /// Starts refreshing this moderator's ElGamal key share.
+    fn handle_refresh_round1(
+        &mut self,
+        mut request: tiny_http::Request,
+    ) -> Result<()> {
+        let body: communication::refresh::Round1Request =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let started = self.decryption_keys().and_then(|key_share| {
+            if key_share.identifier() != u16::from(self.identity.identifier()) {
+                return Err(
+                    "Key share belongs to a different moderator.".into()
+                );
+            }
+
+            refresh::Refresh::new(
+                &mut rand::thread_rng(),
+                &self.identity,
+                body.n_moderators,
+                body.decryption_threshold,
+            )
+        });
+        let (refresh, package) = match started {
+            Ok(started) => started,
+            Err(err) => return respond_with_error(request, err.as_ref()),
+        };
+        self.pending_refresh = Some(refresh);
+
+        // a refreshed share that was never committed is abandoned
+        self.pending_key_share = None;
+
+        request.respond({
+            let body = communication::refresh::Round1Response { package };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(())
+    }
+
+    fn handle_refresh_round2(
+        &mut self,
+        mut request: tiny_http::Request,
+    ) -> Result<()> {
+        let body: communication::refresh::Round2Request<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let encrypted_shares = match self.pending_refresh.as_mut() {
+            Some(refresh) => refresh.round2(body.packages),
+            None => Err("No refresh is in progress.".into()),
+        };
+
+        let encrypted_shares = match encrypted_shares {
+            Ok(encrypted_shares) => encrypted_shares,
+            Err(err) => {
+                println!("Refresh failed: {err}");
+                self.pending_refresh = None;
+                return respond_with_error(request, err.as_ref());
+            }
+        };
+
+        request.respond({
+            let body =
+                communication::refresh::Round2Response { encrypted_shares };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(())
+    }
+
+    /// Computes this moderator's refreshed ElGamal key share.
+    ///
+    /// The refreshed share is only used once the coordinator commits to it,
+    /// see [`Self::handle_refresh_commit`].
+    fn handle_refresh_round3(
+        &mut self,
+        mut request: tiny_http::Request,
+    ) -> Result<()> {
+        let body: communication::refresh::Round3Request =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let key_share = match self.pending_refresh.take() {
+            Some(refresh) => self.decryption_keys().and_then(|key_share| {
+                refresh.finish(body.encrypted_shares, key_share)
+            }),
+            None => Err("No refresh is in progress.".into()),
+        };
+
+        let key_share = match key_share {
+            Ok(key_share) => key_share,
+            Err(err) => {
+                println!("Refresh failed: {err}");
+                return respond_with_error(request, err.as_ref());
+            }
+        };
+
+        let verification_key = key_share.verification_key();
+        self.pending_key_share = Some(key_share);
+
+        request.respond({
+            let body =
+                communication::refresh::Round3Response { verification_key };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(())
+    }
+
+    /// Replaces this moderator's ElGamal key share with the refreshed one,
+    /// once the coordinator has checked every moderator's refreshed share.
+    ///
+    /// Committing again to the same share is a no-op, so the coordinator can
+    /// safely retry.
+    fn handle_refresh_commit(
+        &mut self,
+        mut request: tiny_http::Request,
+    ) -> Result<()> {
+        let body: communication::refresh::CommitRequest<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let already_committed =
+            self.encryption_keys.as_ref().map_or(false, |key_share| {
+                key_share.verification_key() == body.verification_key
+            });
+
+        if !already_committed {
+            let key_share = match self.pending_key_share.take() {
+                Some(key_share)
+                    if key_share.verification_key()
+                        == body.verification_key =>
+                {
+                    key_share
+                }
+                _ => {
+                    let err: Box<dyn Error> =
+                        "No matching refreshed key share to commit.".into();
+                    println!("Refresh failed: {err}");
+                    return respond_with_error(request, err.as_ref());
+                }
+            };
+
+            self.encryption_keys = Some(key_share);
+            println!("Refreshed decryption key share.");
+        }
+
+        request.respond(tiny_http::Response::empty(200))?;
+
+        Ok(())
+    }
+
+    /// Reshares this moderator's key shares to the new committee.
+    ///
+    /// The moderator signs off on the reshare's public values, which the new
+    /// moderators check its dealing against, so it only deals if the group
+    /// keys are the ones it holds.
+    fn handle_reshare_round2(
+        &self,
+        mut request: tiny_http::Request,
+    ) -> Result<()> {
+        let body: communication::reshare::Round2Request<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let agrees_on_signing_key =
+            C::verifying_key_element(&self.group_public_key)
+                .map_or(false, |key| key == body.context.group_public_key);
+        if !agrees_on_signing_key
+            || body.context.group_public_elgamal_key
+                != self.group_public_elgamal_key
+        {
+            let err: Box<dyn Error> =
+                "Coordinator disagrees on the group public keys.".into();
+            println!("Reshare failed: {err}");
+            return respond_with_error(request, err.as_ref());
+        }
+
+        // only the keys held by this moderator's committee are reshared
+        let mut signing_share = self.sk_signing.as_ref().map(|signing_keys| {
+            C::signing_share_scalar(&signing_keys.secret_share)
+        });
+
+        let dealt = reshare::deal(
+            &mut rand::thread_rng(),
+            &self.identity,
+            signing_share.as_ref(),
+            self.encryption_keys.as_ref().map(elgamal::KeyShare::secret),
+            &body.recipients,
+            &body.context,
+        );
+        signing_share.zeroize();
+
+        let (dealing, confirmation) = match dealt {
+            Ok(dealt) => dealt,
+            Err(err) => {
+                println!("Reshare failed: {err}");
+                return respond_with_error(request, err.as_ref());
+            }
+        };
+
+        request.respond({
+            let body = communication::reshare::Round2Response {
+                dealing,
+                confirmation,
+            };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(())
+    }
+
     fn handle_decryption(&self, mut request: tiny_http::Request) -> Result<()> {
-        let body: communication::decryption::Request =
+        let body: communication::decryption::Request<C> =
             bincode::deserialize_from(request.as_reader())?;
 
-        body.token.verify()?;
+        // refuse to respond to reports that fail verification,
+        // but keep the server running
+        let (decryption_share, payload_share) =
+            match self.process_decryption_request(&body) {
+                Ok(shares) => shares,
+                Err(err) => {
+                    println!("Rejected decryption request: {err}");
+                    request.respond(
+                        tiny_http::Response::from_string(err.to_string())
+                            .with_status_code(400),
+                    )?;
+                    return Ok(());
+                }
+            };
+
+        request.respond({
+            let body = communication::decryption::Response {
+                decryption_share,
+                payload_share,
+            };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
 
-        let decryption_share =
-            self.encryption_keys.decryption_share(&body.token.token.x_1);
+        Ok(())
+    }
+
+    /// Checks a report, returning decryption shares of the token's id and
+    /// of its payload, if it has one.
+    fn process_decryption_request(
+        &self,
+        body: &communication::decryption::Request<C>,
+    ) -> Result<(
+        elgamal::DecryptionShare<C>,
+        Option<elgamal::DecryptionShare<C>>,
+    )> {
+        // only release a decryption share if the reported message really
+        // was sent with the token and delivered by the platform
+        body.envelope
+            .verify(&body.message, &self.group_public_key)?;
+
+        let platform_public_key = self
+            .platform_public_key
+            .as_ref()
+            .ok_or("No platform has been registered.")?;
+        body.envelope.verify_stamp(platform_public_key)?;
+
+        let token = &body.envelope.token.token;
+        self.timestamp_policy
+            .check_age(token.timestamp, Utc::now().timestamp())?;
+
+        // never decrypt a labeled ciphertext that has been tampered with,
+        // or an unlabeled one if labels are required
+        token.x_1.check_policy(
+            self.id_encryption,
+            &self.group_public_elgamal_key,
+            token.pk_e.as_ref(),
+        )?;
+
+        // a share of a small-order c_1 would leak part of our key share
+        if !C::is_torsion_free(token.x_1.c_1()) {
+            return Err("ID encryption is invalid.".into());
+        }
+        if let Some(payload) = &token.payload {
+            if !C::is_torsion_free(payload.c_1()) {
+                return Err("Payload encryption is invalid.".into());
+            }
+        }
+
+        let key_share = self.decryption_keys()?;
+        let mut rng = rand::thread_rng();
+
+        Ok((
+            key_share.decryption_share(&token.x_1, &mut rng),
+            token
+                .payload
+                .as_ref()
+                .map(|payload| key_share.decryption_share(payload, &mut rng)),
+        ))
+    }
+}
+
+impl<C: Ciphersuite> Drop for Moderator<C> {
+    fn drop(&mut self) {
+        self.nonces.zeroize();
+    }
+}
+
+/// Label for the signatures confirming a dealt setup.
+const SETUP_LABEL: &[u8] = b"setup";
+
+/// A moderator that has received its keys from a trusted dealer, but
+/// doesn't know yet whether the rest of its committee received the same
+/// public values.
+struct PendingSetup<C: Ciphersuite> {
+    moderator: Moderator<C>,
+    digest: [u8; 32],
+}
+
+impl<C: Ciphersuite> PendingSetup<C> {
+    /// Checks that every moderator on the committee signed off on the same
+    /// public values as this one.
+    ///
+    /// Otherwise, the coordinator could send each moderator different
+    /// commitments and so hand out shares that aren't on the same polynomial,
+    /// even though each moderator's checks pass.
+    fn confirm(
+        self,
+        mut request: tiny_http::Request,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<Moderator<C>> {
+        let body: communication::setup::ConfirmRequest<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        if let Err(err) = identity.verify_unanimous(
+            SETUP_LABEL,
+            &body.confirmations,
+            &self.digest,
+        ) {
+            return reject_setup(request, err);
+        }
+
+        request.respond(tiny_http::Response::empty(200))?;
+
+        Ok(self.moderator)
+    }
+}
+
+/// A digest of the public values sent to every moderator in a dealt setup.
+fn setup_digest<C: Ciphersuite>(
+    group_public_key: &C::ElementBytes,
+    elgamal_commitments: &Commitments<C>,
+    id_encryption: IdEncryption,
+) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"cerberus-setup");
+    hasher.update(group_public_key.as_ref());
+    hasher.update(bincode::serialize(elgamal_commitments)?);
+    hasher.update(bincode::serialize(&id_encryption)?);
+
+    Ok(hasher.finalize().into())
+}
+
+/// A moderator's state while distributed key generation is in progress.
+struct PendingKeyGeneration<C: Ciphersuite> {
+    participant: dkg::Participant<C>,
+    batch_size: usize,
+    timestamp_policy: TimestampPolicy,
+    id_encryption: IdEncryption,
+}
+
+impl<C: Ciphersuite> PendingKeyGeneration<C> {
+    fn round1(
+        mut request: tiny_http::Request,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<Self> {
+        let body: communication::keygen::Round1Request =
+            bincode::deserialize_from(request.as_reader())?;
+
+        // our identifier is fixed by our identity key
+        if body.identifier != u16::from(identity.identifier()) {
+            return reject_setup(
+                request,
+                format!(
+                    "Asked to act as moderator {}, but this is moderator {}.",
+                    body.identifier,
+                    identity.identifier()
+                )
+                .into(),
+            );
+        }
+
+        let (participant, package) = match dkg::Participant::new(
+            &mut rand::thread_rng(),
+            identity,
+            body.n_moderators,
+            body.thresholds,
+        ) {
+            Ok(started) => started,
+            Err(err) => return reject_setup(request, err),
+        };
+
+        request.respond({
+            let body = communication::keygen::Round1Response { package };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(Self {
+            participant,
+            batch_size: body.batch_size,
+            timestamp_policy: body.timestamp_policy,
+            id_encryption: body.id_encryption,
+        })
+    }
+
+    fn round2(&mut self, mut request: tiny_http::Request) -> Result<()> {
+        let body: communication::keygen::Round2Request<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let encrypted_shares = match self.participant.round2(body.packages) {
+            Ok(encrypted_shares) => encrypted_shares,
+            Err(err) => return reject_setup(request, err),
+        };
 
         request.respond({
-            let body = communication::decryption::Response { decryption_share };
+            let body =
+                communication::keygen::Round2Response { encrypted_shares };
             let bytes = bincode::serialize(&body)?;
             tiny_http::Response::from_data(bytes)
         })?;
 
         Ok(())
     }
+
+    fn round3(
+        self,
+        mut request: tiny_http::Request,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<Moderator<C>> {
+        let body: communication::keygen::Round3Request<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let key_shares = match self.participant.finish(body.encrypted_shares) {
+            Ok(key_shares) => key_shares,
+            Err(err) => return reject_setup(request, err),
+        };
+
+        // make sure the coordinator agrees on the keys that we hold shares of
+        let changes_signing_key = key_shares
+            .signing_public_key()
+            .map_or(false, |key| key != body.group_public_key);
+        let changes_decryption_key = key_shares
+            .elgamal_public_key()
+            .map_or(false, |key| key != body.group_public_elgamal_key);
+        if changes_signing_key || changes_decryption_key {
+            return reject_setup(
+                request,
+                "Coordinator disagrees on the group public keys.".into(),
+            );
+        }
+
+        let group_public_key = match C::verifying_key(&body.group_public_key) {
+            Ok(group_public_key) => group_public_key,
+            Err(err) => return reject_setup(request, err),
+        };
+        let (moderator, nonce_commitments) = Moderator::new(
+            identity,
+            key_shares.frost_key_package()?,
+            key_shares.elgamal_key_share(),
+            group_public_key,
+            body.group_public_elgamal_key,
+            self.batch_size,
+            self.timestamp_policy,
+            self.id_encryption,
+        );
+
+        request.respond({
+            let body =
+                communication::keygen::Round3Response { nonce_commitments };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(moderator)
+    }
+}
+
+/// A moderator's state while it waits for its shares in a reshare.
+struct PendingReshare<C: Ciphersuite> {
+    recipient: reshare::Recipient<C>,
+    batch_size: usize,
+    timestamp_policy: TimestampPolicy,
+    id_encryption: IdEncryption,
+}
+
+impl<C: Ciphersuite> PendingReshare<C> {
+    fn round1(
+        mut request: tiny_http::Request,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<Self> {
+        let body: communication::reshare::Round1Request =
+            bincode::deserialize_from(request.as_reader())?;
+
+        // our identifier is fixed by our identity key
+        if body.identifier != u16::from(identity.identifier()) {
+            return reject_setup(
+                request,
+                format!(
+                    "Asked to act as moderator {}, but this is moderator {}.",
+                    body.identifier,
+                    identity.identifier()
+                )
+                .into(),
+            );
+        }
+
+        let (recipient, encryption_key) =
+            match reshare::Recipient::new(&mut rand::thread_rng(), identity) {
+                Ok(started) => started,
+                Err(err) => return reject_setup(request, err),
+            };
+
+        request.respond({
+            let body =
+                communication::reshare::Round1Response { encryption_key };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(Self {
+            recipient,
+            batch_size: body.batch_size,
+            timestamp_policy: body.timestamp_policy,
+            id_encryption: body.id_encryption,
+        })
+    }
+
+    /// Creates the moderator with its new key shares.
+    fn round3(
+        pending_reshare: Option<Self>,
+        mut request: tiny_http::Request,
+        identity: &ModeratorIdentity<C>,
+    ) -> Result<Moderator<C>> {
+        let body: communication::reshare::Round3Request<C> =
+            bincode::deserialize_from(request.as_reader())?;
+
+        let pending_reshare = match pending_reshare {
+            Some(pending_reshare) => pending_reshare,
+            None => {
+                return reject_setup(
+                    request,
+                    "No reshare is in progress.".into(),
+                )
+            }
+        };
+
+        let key_shares = match pending_reshare.recipient.finish(
+            &body.dealings,
+            &body.confirmations,
+            &body.context,
+        ) {
+            Ok(key_shares) => key_shares,
+            Err(err) => return reject_setup(request, err),
+        };
+
+        let group_public_key =
+            match C::verifying_key(&body.context.group_public_key) {
+                Ok(group_public_key) => group_public_key,
+                Err(err) => return reject_setup(request, err),
+            };
+        let (moderator, nonce_commitments) = Moderator::new(
+            identity,
+            key_shares.frost_key_package()?,
+            key_shares.elgamal_key_share(),
+            group_public_key,
+            body.context.group_public_elgamal_key,
+            pending_reshare.batch_size,
+            pending_reshare.timestamp_policy,
+            pending_reshare.id_encryption,
+        );
+
+        request.respond({
+            let body =
+                communication::reshare::Round3Response { nonce_commitments };
+            let bytes = bincode::serialize(&body)?;
+            tiny_http::Response::from_data(bytes)
+        })?;
+
+        Ok(moderator)
+    }
+}
+
+/// Tells the coordinator why setup or key generation failed, then aborts it.
+fn reject_setup<T>(
+    request: tiny_http::Request,
+    err: Box<dyn Error>,
+) -> Result<T> {
+    println!("Setup failed: {err}");
+    respond_with_error(request, err.as_ref())?;
+
+    Err(err)
+}
+
+/// Responds to a request that couldn't be processed with the reason why.
+fn respond_with_error(
+    request: tiny_http::Request,
+    err: &dyn Error,
+) -> Result<()> {
+    request.respond(
+        tiny_http::Response::from_string(err.to_string()).with_status_code(400),
+    )?;
+
+    Ok(())
 }
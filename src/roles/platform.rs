@@ -0,0 +1,75 @@
+use chrono::Utc;
+use frost_core::{SigningKey, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    message::{Envelope, PlatformStamp},
+    PlatformPublicKey, Result,
+};
+
+/// The messaging platform (i.e., the server) that relays messages between clients.
+///
+/// The platform never sees message plaintexts. It stamps every envelope it
+/// delivers with the time of delivery and its own signature so that reporters
+/// can't fabricate when (or whether) a message was delivered.
+pub struct Platform<C: Ciphersuite = Ristretto255> {
+    /// The secret stamping key, which is wiped when the platform is dropped.
+    secret: C::Scalar,
+
+    /// The moderators' group verifying key, used to check tokens before stamping.
+    group_public_key: VerifyingKey<C::Frost>,
+}
+
+impl<C: Ciphersuite> Platform<C> {
+    /// Creates a new platform with a fresh stamping key.
+    pub fn new<R: CryptoRng + RngCore>(
+        group_public_key: VerifyingKey<C::Frost>,
+        rng: &mut R,
+    ) -> Self {
+        Self {
+            secret: C::random_scalar(rng),
+            group_public_key,
+        }
+    }
+
+    /// The key that moderators use to verify the platform's stamps.
+    pub fn public_key(&self) -> PlatformPublicKey<C> {
+        C::verifying_key_to_bytes(&VerifyingKey::from(&self.signing_key()))
+    }
+
+    /// Stamps an envelope with the current time as it is relayed to its recipient.
+    ///
+    /// The token and sender signature are checked first; the message binding
+    /// can't be, since the platform doesn't see the plaintext.
+    pub fn stamp(&self, envelope: &Envelope<C>) -> Result<Envelope<C>> {
+        envelope.token.verify(&self.group_public_key)?;
+        envelope.verify_sender_signature()?;
+
+        let timestamp = Utc::now().timestamp();
+        let signature = self.signing_key().sign(
+            rand::thread_rng(),
+            &PlatformStamp::<C>::signed_bytes(&envelope.x_2, timestamp),
+        );
+
+        Ok(Envelope {
+            stamp: Some(PlatformStamp {
+                timestamp,
+                signature,
+            }),
+            ..envelope.clone()
+        })
+    }
+
+    fn signing_key(&self) -> SigningKey<C::Frost> {
+        C::signing_key(&self.secret)
+            .expect("Platform secret key is not a valid FROST signing key.")
+    }
+}
+
+impl<C: Ciphersuite> Drop for Platform<C> {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
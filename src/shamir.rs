@@ -0,0 +1,374 @@
+//! Shamir secret sharing over the scalars of a [`Ciphersuite`].
+//!
+//! Polynomials are evaluated with Horner's method directly over scalars, so
+//! shares can be dealt to any number of participants, and the Lagrange
+//! coefficients of a set of shares are computed with a single inversion.
+//! Coefficients for recurring sets of shares are kept in a [`LagrangeCache`].
+
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+use zeroize::Zeroize;
+
+use crate::ciphersuite::Ciphersuite;
+
+/// A secret polynomial `f(x) = a_0 + a_1 x + ... + a_{k-1} x^{k-1}`
+/// used for Shamir secret sharing.
+pub(crate) struct Polynomial<C: Ciphersuite>(Vec<C::Scalar>);
+
+impl<C: Ciphersuite> Polynomial<C> {
+    /// A random polynomial with `threshold` coefficients and constant term `constant`.
+    pub(crate) fn random<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        constant: C::Scalar,
+        threshold: usize,
+    ) -> Self {
+        let mut coefficients = vec![constant];
+        for _ in 1..threshold {
+            coefficients.push(C::random_scalar(rng))
+        }
+
+        Self(coefficients)
+    }
+
+    /// Evaluates `f(x)` using Horner's method.
+    pub(crate) fn evaluate(&self, x: &C::Scalar) -> C::Scalar {
+        let mut coefficients = self.0.iter().rev();
+        let leading = *coefficients.next().expect("Polynomial is empty.");
+
+        coefficients.fold(leading, |acc, a| acc * *x + *a)
+    }
+
+    /// Feldman commitments to the coefficients of the polynomial.
+    pub(crate) fn commitments(&self) -> Commitments<C> {
+        Commitments::from_coefficients(&self.0)
+    }
+}
+
+impl<C: Ciphersuite> Drop for Polynomial<C> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Feldman commitments `C_k = a_k * G` to the coefficients of a [`Polynomial`].
+///
+/// These allow anyone to compute the public key `f(x) * G` corresponding to any
+/// share without learning the share itself.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(bound = "")]
+pub(crate) struct Commitments<C: Ciphersuite>(Vec<C::Element>);
+
+impl<C: Ciphersuite> Commitments<C> {
+    /// Commits to the coefficients `a_0, ..., a_{k-1}` of a polynomial.
+    pub(crate) fn from_coefficients(coefficients: &[C::Scalar]) -> Self {
+        Self(coefficients.iter().map(C::base_mul).collect())
+    }
+
+    /// The number of shares needed to recover the secret.
+    pub(crate) fn threshold(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The public key `f(0) * G` corresponding to the shared secret.
+    pub(crate) fn public_key(&self) -> C::Element {
+        self.0[0]
+    }
+
+    /// Computes `f(x) * G` from the commitments using Horner's method.
+    pub(crate) fn evaluate(&self, x: &C::Scalar) -> C::Element {
+        let mut commitments = self.0.iter().rev();
+        let leading = *commitments.next().expect("Commitments are empty.");
+
+        commitments.fold(leading, |acc, c| acc * *x + *c)
+    }
+
+    /// Checks that `share = f(x)`.
+    pub(crate) fn verify_share(
+        &self,
+        x: &C::Scalar,
+        share: &C::Scalar,
+    ) -> bool {
+        C::base_mul(share) == self.evaluate(x)
+    }
+
+    /// The commitments to the sum of the committed polynomials.
+    ///
+    /// All commitments MUST have the same threshold.
+    pub(crate) fn sum<'a, I: IntoIterator<Item = &'a Commitments<C>>>(
+        commitments: I,
+        threshold: usize,
+    ) -> Self {
+        commitments.into_iter().fold(
+            Self(vec![C::identity(); threshold]),
+            |mut acc, commitments| {
+                for (a, c) in acc.0.iter_mut().zip(&commitments.0) {
+                    *a += *c;
+                }
+                acc
+            },
+        )
+    }
+}
+
+/// The Lagrange coefficient `λ_i` of `identifier` among `identifiers`,
+/// such that `f(0) = Σ λ_i f(x_i)` for any polynomial of degree less than
+/// `identifiers.len()`.
+///
+/// `identifiers` MUST be distinct and non-zero, and contain `identifier`.
+pub(crate) fn lagrange_coefficient<C: Ciphersuite>(
+    identifier: &C::Scalar,
+    identifiers: &[C::Scalar],
+) -> C::Scalar {
+    let (numerator, denominator) = identifiers
+        .iter()
+        .filter(|other| *other != identifier)
+        .fold(
+            (C::scalar_from_u64(1), C::scalar_from_u64(1)),
+            |(numerator, denominator), other| {
+                (numerator * *other, denominator * (*other - *identifier))
+            },
+        );
+
+    numerator * C::invert(&denominator)
+}
+
+/// The Lagrange coefficients of every identifier in `identifiers`, as in
+/// [`lagrange_coefficient`], using a single inversion for all of them.
+pub(crate) fn lagrange_coefficients<C: Ciphersuite>(
+    identifiers: &[C::Scalar],
+) -> Vec<C::Scalar> {
+    let one = C::scalar_from_u64(1);
+
+    // Π_{j != i} x_j, from the products of the identifiers before and after i
+    let mut numerators = vec![one; identifiers.len()];
+    let mut product = one;
+    for (numerator, x) in numerators.iter_mut().zip(identifiers) {
+        *numerator = product;
+        product = product * *x;
+    }
+    product = one;
+    for (numerator, x) in numerators.iter_mut().zip(identifiers).rev() {
+        *numerator = *numerator * product;
+        product = product * *x;
+    }
+
+    // Π_{j != i} (x_j - x_i)
+    let mut denominators: Vec<_> = identifiers
+        .iter()
+        .map(|x_i| {
+            identifiers
+                .iter()
+                .filter(|x_j| *x_j != x_i)
+                .fold(one, |acc, x_j| acc * (*x_j - *x_i))
+        })
+        .collect();
+    batch_invert::<C>(&mut denominators);
+
+    numerators
+        .into_iter()
+        .zip(denominators)
+        .map(|(numerator, denominator)| numerator * denominator)
+        .collect()
+}
+
+/// The number of identifier sets a [`LagrangeCache`] holds before it is cleared.
+const MAX_CACHED_IDENTIFIER_SETS: usize = 64;
+
+/// Lagrange coefficients of recently seen sets of identifiers.
+///
+/// The coefficients only depend on which participants contributed shares,
+/// and that tends to be the same set of responsive moderators every time.
+#[derive(Default)]
+pub(crate) struct LagrangeCache<C: Ciphersuite> {
+    coefficients: Mutex<HashMap<Vec<u16>, Vec<C::Scalar>>>,
+}
+
+impl<C: Ciphersuite> LagrangeCache<C> {
+    /// The Lagrange coefficients of `identifiers`, as in [`lagrange_coefficients`].
+    ///
+    /// `identifiers` MUST be distinct and non-zero. Sets with the same
+    /// identifiers in a different order are cached separately.
+    pub(crate) fn coefficients(&self, identifiers: &[u16]) -> Vec<C::Scalar> {
+        let mut cache = self
+            .coefficients
+            .lock()
+            .expect("Lagrange cache lock is poisoned.");
+
+        if let Some(coefficients) = cache.get(identifiers) {
+            return coefficients.clone();
+        }
+
+        let scalars: Vec<_> = identifiers
+            .iter()
+            .map(|identifier| C::scalar_from_u64(*identifier as u64))
+            .collect();
+        let coefficients = lagrange_coefficients::<C>(&scalars);
+
+        if cache.len() >= MAX_CACHED_IDENTIFIER_SETS {
+            cache.clear();
+        }
+        cache.insert(identifiers.to_vec(), coefficients.clone());
+
+        coefficients
+    }
+
+    /// The number of identifier sets currently cached.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.coefficients.lock().unwrap().len()
+    }
+}
+
+/// Inverts every scalar in place with Montgomery's trick, which needs a single
+/// inversion and `3(n - 1)` multiplications.
+///
+/// The scalars MUST be non-zero.
+pub(crate) fn batch_invert<C: Ciphersuite>(scalars: &mut [C::Scalar]) {
+    // products[i] = s_0 * ... * s_{i-1}
+    let mut products = Vec::with_capacity(scalars.len());
+    let mut product = C::scalar_from_u64(1);
+    for scalar in scalars.iter() {
+        products.push(product);
+        product = product * *scalar;
+    }
+
+    // walk back down, peeling one scalar off the inverted product at a time
+    let mut inverse = C::invert(&product);
+    for (scalar, product) in scalars.iter_mut().zip(products).rev() {
+        let scalar_inverse = inverse * product;
+        inverse = inverse * *scalar;
+        *scalar = scalar_inverse;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        batch_invert, lagrange_coefficient, lagrange_coefficients,
+        LagrangeCache, Polynomial, MAX_CACHED_IDENTIFIER_SETS,
+    };
+    use crate::ciphersuite::{Ciphersuite, Ed25519, Ristretto255};
+
+    /// Shares a random secret between `n` participants and recovers it from
+    /// the last `threshold` shares.
+    fn interpolation<C: Ciphersuite>(n: usize, threshold: usize) {
+        let mut rng = rand::thread_rng();
+        let secret = C::random_scalar(&mut rng);
+        let polynomial = Polynomial::<C>::random(&mut rng, secret, threshold);
+
+        let identifiers: Vec<_> = (n - threshold + 1..=n)
+            .map(|i| C::scalar_from_u64(i as u64))
+            .collect();
+        let shares: Vec<_> =
+            identifiers.iter().map(|x| polynomial.evaluate(x)).collect();
+
+        let recovered: C::Scalar = lagrange_coefficients::<C>(&identifiers)
+            .into_iter()
+            .zip(&shares)
+            .map(|(lambda, share)| lambda * *share)
+            .sum();
+        assert_eq!(secret, recovered);
+
+        // one share too few recovers something else
+        let recovered: C::Scalar =
+            lagrange_coefficients::<C>(&identifiers[1..])
+                .into_iter()
+                .zip(&shares[1..])
+                .map(|(lambda, share)| lambda * *share)
+                .sum();
+        assert_ne!(secret, recovered);
+    }
+
+    #[test]
+    fn test_interpolation() {
+        interpolation::<Ristretto255>(5, 3);
+        interpolation::<Ed25519>(5, 3);
+    }
+
+    #[test]
+    fn test_large_committee_interpolation() {
+        // x^k overflows a u64 long before this
+        interpolation::<Ristretto255>(1000, 667);
+    }
+
+    #[test]
+    fn test_horner_evaluation() {
+        let mut rng = rand::thread_rng();
+        let polynomial = Polynomial::<Ristretto255>::random(
+            &mut rng,
+            Ristretto255::random_scalar(&mut rng),
+            50,
+        );
+        let x = Ristretto255::scalar_from_u64(400);
+
+        // a_0 + a_1 x + ... + a_{k-1} x^{k-1}, term by term
+        let mut power = Ristretto255::scalar_from_u64(1);
+        let mut expected = Ristretto255::scalar_from_u64(0);
+        for a in &polynomial.0 {
+            expected += *a * power;
+            power = power * x;
+        }
+
+        assert_eq!(expected, polynomial.evaluate(&x));
+        assert!(polynomial.commitments().verify_share(&x, &expected));
+    }
+
+    #[test]
+    fn test_batch_invert() {
+        let mut rng = rand::thread_rng();
+        let scalars: Vec<_> = (0..100)
+            .map(|_| Ristretto255::random_scalar(&mut rng))
+            .collect();
+
+        let mut inverses = scalars.clone();
+        batch_invert::<Ristretto255>(&mut inverses);
+
+        assert!(scalars
+            .iter()
+            .zip(&inverses)
+            .all(|(s, inverse)| Ristretto255::invert(s) == *inverse));
+
+        // empty batches are fine too
+        batch_invert::<Ristretto255>(&mut []);
+    }
+
+    #[test]
+    fn test_batched_lagrange_coefficients() {
+        let identifiers: Vec<_> = [1u64, 4, 5, 9, 300]
+            .into_iter()
+            .map(Ristretto255::scalar_from_u64)
+            .collect();
+
+        let lambdas = lagrange_coefficients::<Ristretto255>(&identifiers);
+
+        for (identifier, lambda) in identifiers.iter().zip(lambdas) {
+            assert_eq!(
+                lagrange_coefficient::<Ristretto255>(identifier, &identifiers),
+                lambda
+            );
+        }
+    }
+
+    #[test]
+    fn test_lagrange_cache() {
+        let cache = LagrangeCache::<Ristretto255>::default();
+        let identifiers = [2u16, 3, 7];
+        let scalars: Vec<_> = identifiers
+            .iter()
+            .map(|i| Ristretto255::scalar_from_u64(*i as u64))
+            .collect();
+
+        let expected = lagrange_coefficients::<Ristretto255>(&scalars);
+        assert_eq!(cache.coefficients(&identifiers), expected);
+        assert_eq!(cache.coefficients(&identifiers), expected);
+        assert_eq!(cache.len(), 1);
+
+        // the cache is bounded
+        for i in 1..=MAX_CACHED_IDENTIFIER_SETS as u16 {
+            cache.coefficients(&[i, i + 1]);
+        }
+        assert!(cache.len() <= MAX_CACHED_IDENTIFIER_SETS);
+        assert_eq!(cache.coefficients(&identifiers), expected);
+    }
+}
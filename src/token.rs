@@ -1,27 +1,460 @@
-use crate::{elgamal::EncryptedUserId, UserPublicKey};
-use frost_ristretto255 as frost;
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    elgamal::{EncryptedUserId, PublicKey},
+    payload::{EncryptedPayload, Payload},
+    Result, UserId, UserPublicKey,
+};
+use frost_core::{Signature, SigningKey, VerifyingKey};
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use zeroize::Zeroize;
 
 #[derive(Serialize, Deserialize, Clone)]
-pub struct SignedToken {
-    pub signature: frost::Signature,
-    pub token: UnsignedToken,
+#[serde(bound = "")]
+pub struct SignedToken<C: Ciphersuite = Ristretto255> {
+    pub signature: Signature<C::Frost>,
+    pub token: UnsignedToken<C>,
 }
 
-impl SignedToken {
-    pub fn verify(&self) -> Result<(), Box<dyn Error>> {
-        // in practice, there would be more here
+impl<C: Ciphersuite> SignedToken<C> {
+    /// Checks that `signature` is a valid FROST signature on the (serialized)
+    /// unsigned token under the moderators' group verifying key.
+    pub fn verify(
+        &self,
+        group_public_key: &VerifyingKey<C::Frost>,
+    ) -> Result<(), Box<dyn Error>> {
+        let token_bytes = bincode::serialize(&self.token)?;
 
-        Ok(())
+        group_public_key
+            .verify(&token_bytes, &self.signature)
+            .map_err(|_| "Token signature is invalid.".into())
     }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
-pub struct UnsignedToken {
+#[serde(bound = "")]
+pub struct UnsignedToken<C: Ciphersuite = Ristretto255> {
     pub(crate) timestamp: i64,
-    pub(crate) x_1: EncryptedUserId,
-    pub(crate) pk_e: UserPublicKey,
+    pub(crate) x_1: EncryptedUserId<C>,
+    pub(crate) pk_e: UserPublicKey<C>,
+
+    /// More information about the sender, revealed alongside `x_1` when the
+    /// token is reported. Labeled with `pk_e`.
+    pub(crate) payload: Option<EncryptedPayload<C>>,
+}
+
+/// Bounds on token timestamps enforced by the moderators. All values are in seconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct TimestampPolicy {
+    /// How far a token's timestamp may differ from a moderator's clock
+    /// at the time of signing.
+    pub max_clock_skew: i64,
+
+    /// How old a token may be at the time it is reported.
+    pub max_token_age: i64,
+}
+
+impl Default for TimestampPolicy {
+    fn default() -> Self {
+        Self {
+            max_clock_skew: 60,              // one minute
+            max_token_age: 7 * 24 * 60 * 60, // one week
+        }
+    }
+}
+
+impl TimestampPolicy {
+    /// Checks that a token being signed at time `now` carries a current timestamp.
+    pub(crate) fn check_issuance(
+        &self,
+        timestamp: i64,
+        now: i64,
+    ) -> Result<()> {
+        // the timestamp is chosen by the requester, so it may be anything
+        let skew = timestamp
+            .checked_sub(now)
+            .ok_or("Token timestamp is out of range.")?;
+
+        if skew
+            .checked_abs()
+            .map_or(true, |skew| skew > self.max_clock_skew)
+        {
+            return Err(format!(
+                "Token timestamp is {skew}s away from the current time (at most {}s allowed).",
+                self.max_clock_skew
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a token being reported at time `now` has not expired.
+    pub(crate) fn check_age(&self, timestamp: i64, now: i64) -> Result<()> {
+        let age = now
+            .checked_sub(timestamp)
+            .ok_or("Token timestamp is out of range.")?;
+
+        if age
+            .checked_neg()
+            .map_or(true, |skew| skew > self.max_clock_skew)
+        {
+            return Err("Token timestamp is in the future.".into());
+        }
+
+        if age > self.max_token_age {
+            return Err(format!(
+                "Token has expired: issued {age}s ago (at most {}s allowed).",
+                self.max_token_age
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// A request for a single token, as sent by a client to the coordinator.
+///
+/// Only the public half of the ephemeral key pair is included; the secret
+/// half never leaves the client.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct TokenRequest<C: Ciphersuite = Ristretto255> {
+    pub(crate) user_id: UserId,
+    pub(crate) pk_e: UserPublicKey<C>,
+
+    /// The randomness used to encrypt `user_id` into `x_1`.
+    ///
+    /// This is chosen by the client so that it can check that the issued
+    /// token really contains an encryption of its own id.
+    pub(crate) elgamal_randomness: C::Scalar,
+
+    /// The blinding of the commitment to `user_id` that the moderators are
+    /// sent instead of the id in [`SigningMode::ZeroKnowledge`], chosen by
+    /// the client so that it can open the commitment too.
+    ///
+    /// [`SigningMode::ZeroKnowledge`]: crate::SigningMode::ZeroKnowledge
+    pub(crate) id_blinding: C::Scalar,
+
+    /// The payload to encrypt into the token, if any, whose `account_id`
+    /// is `user_id`.
+    pub(crate) payload: Option<Payload>,
+
+    /// The randomness used to encrypt `payload`, chosen by the client
+    /// for the same reason as `elgamal_randomness`.
+    pub(crate) payload_randomness: C::Scalar,
+}
+
+impl<C: Ciphersuite> TokenRequest<C> {
+    /// Creates a request for a token belonging to `user_id` along with a fresh
+    /// ephemeral key pair.
+    ///
+    /// Returns the request and the secret half of the key pair, which must be
+    /// kept alongside the resulting [`SignedToken`] in order to send with it.
+    pub fn new<R: CryptoRng + RngCore>(
+        user_id: UserId,
+        rng: &mut R,
+    ) -> (Self, EphemeralSecretKey<C>) {
+        let sk_e = EphemeralSecretKey::random(rng);
+        let request = Self {
+            user_id,
+            pk_e: sk_e.public_key(),
+            elgamal_randomness: C::random_scalar(rng),
+            id_blinding: C::random_scalar(rng),
+            payload: None,
+            payload_randomness: C::random_scalar(rng),
+        };
+
+        (request, sk_e)
+    }
+
+    /// Creates a request for a token belonging to `payload.account_id`
+    /// that also carries `payload`, which is revealed if the token is
+    /// reported.
+    ///
+    /// The payload is revealed to the moderators when the token is signed,
+    /// as is the id, so these requests can't be signed in
+    /// [`SigningMode::ZeroKnowledge`].
+    ///
+    /// [`SigningMode::ZeroKnowledge`]: crate::SigningMode::ZeroKnowledge
+    pub fn with_payload<R: CryptoRng + RngCore>(
+        payload: Payload,
+        rng: &mut R,
+    ) -> (Self, EphemeralSecretKey<C>) {
+        let (mut request, sk_e) = Self::new(payload.account_id, rng);
+        request.payload = Some(payload);
+
+        (request, sk_e)
+    }
+
+    /// Encrypts the request's payload, if any, as it should appear in
+    /// the token.
+    pub(crate) fn encrypt_payload(
+        &self,
+        group_public_elgamal_key: &PublicKey<C>,
+    ) -> Result<Option<EncryptedPayload<C>>> {
+        self.payload
+            .as_ref()
+            .map(|payload| {
+                EncryptedPayload::encrypt(
+                    group_public_elgamal_key,
+                    payload,
+                    &self.payload_randomness,
+                    self.pk_e.as_ref(),
+                )
+            })
+            .transpose()
+    }
+
+    /// Checks that `token` was issued in response to this request, i.e.,
+    /// that it is signed by the moderators, contains the requested `pk_e`,
+    /// and that `x_1` is an encryption of the requester's id under the
+    /// moderators' group encryption key, as is the requested payload.
+    pub(crate) fn check_issued_token(
+        &self,
+        token: &SignedToken<C>,
+        group_public_key: &VerifyingKey<C::Frost>,
+        group_public_elgamal_key: &PublicKey<C>,
+    ) -> Result<()> {
+        token.verify(group_public_key)?;
+
+        if token.token.pk_e != self.pk_e {
+            return Err(
+                "Token does not contain the requested ephemeral key.".into()
+            );
+        }
+
+        if !token.token.x_1.is_encryption_of(
+            group_public_elgamal_key,
+            &self.user_id,
+            &self.elgamal_randomness,
+            self.pk_e.as_ref(),
+        ) {
+            return Err(
+                "Token does not contain an encryption of our id.".into()
+            );
+        }
+
+        if token.token.payload
+            != self.encrypt_payload(group_public_elgamal_key)?
+        {
+            return Err(
+                "Token does not contain an encryption of our payload.".into()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: Ciphersuite> Drop for TokenRequest<C> {
+    fn drop(&mut self) {
+        self.elgamal_randomness.zeroize();
+        self.id_blinding.zeroize();
+        self.payload_randomness.zeroize();
+    }
+}
+
+/// The secret half of a token's single-use sender key pair, `sk_e`.
+///
+/// The public half, `pk_e`, is embedded in the token and signed by the moderators.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct EphemeralSecretKey<C: Ciphersuite = Ristretto255>(C::Scalar);
+
+impl<C: Ciphersuite> EphemeralSecretKey<C> {
+    pub fn random<R: CryptoRng + RngCore>(rng: &mut R) -> Self {
+        Self(C::random_scalar(rng))
+    }
+
+    /// The public key `pk_e = sk_e * G` to be placed in the token.
+    pub fn public_key(&self) -> UserPublicKey<C> {
+        C::verifying_key_to_bytes(&VerifyingKey::from(&self.signing_key()))
+    }
+
+    /// Signs `message` with the ephemeral key.
+    pub(crate) fn sign(&self, message: &[u8]) -> Signature<C::Frost> {
+        self.signing_key().sign(rand::thread_rng(), message)
+    }
+
+    fn signing_key(&self) -> SigningKey<C::Frost> {
+        C::signing_key(&self.0)
+            .expect("Ephemeral secret key is not a valid FROST signing key.")
+    }
+}
+
+impl<C: Ciphersuite> Drop for EphemeralSecretKey<C> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
 }
 
 // (x1, t1, σ1,(pke, ske))
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        EphemeralSecretKey, SignedToken, TimestampPolicy, TokenRequest,
+        UnsignedToken,
+    };
+    use crate::{
+        ciphersuite::{Ciphersuite, Ed25519, Ristretto255},
+        elgamal::{generate_private_key_shares, PublicKey},
+        Result, UserId,
+    };
+    use chrono::Utc;
+    use frost_core::{frost, VerifyingKey};
+
+    /// Issues a single token using a trusted dealer and returns it alongside
+    /// its ephemeral secret key and the moderators' group verifying key.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn issue_token<C: Ciphersuite>(
+        user_id: UserId,
+    ) -> Result<(
+        SignedToken<C>,
+        EphemeralSecretKey<C>,
+        VerifyingKey<C::Frost>,
+    )> {
+        let (token_request, sk_e) =
+            TokenRequest::<C>::new(user_id, &mut rand::thread_rng());
+        let (token, group_public_key, _) =
+            issue_token_for_request(&token_request)?;
+
+        Ok((token, sk_e, group_public_key))
+    }
+
+    /// Issues a token for `token_request` using a trusted dealer and returns
+    /// it alongside the moderators' group verifying and encryption keys.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn issue_token_for_request<C: Ciphersuite>(
+        token_request: &TokenRequest<C>,
+    ) -> Result<(SignedToken<C>, VerifyingKey<C::Frost>, PublicKey<C>)> {
+        let mut rng = rand::thread_rng();
+
+        let n_mods = 5;
+        let signing_threshold = 3;
+
+        let (frost_secret_shares, frost_public_key) =
+            frost::keys::keygen_with_dealer::<C::Frost, _>(
+                n_mods,
+                signing_threshold,
+                &mut rng,
+            )?;
+        let (elgamal_public_key, _, _) =
+            generate_private_key_shares::<C, _>(&mut rng, n_mods as usize, 3);
+
+        let key_packages = frost_secret_shares
+            .into_iter()
+            .map(frost::keys::KeyPackage::try_from)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let token = UnsignedToken {
+            timestamp: Utc::now().timestamp(),
+            x_1: elgamal_public_key.encrypt(
+                &token_request.user_id,
+                &token_request.elgamal_randomness,
+            ),
+            pk_e: token_request.pk_e,
+            payload: token_request.encrypt_payload(&elgamal_public_key)?,
+        };
+
+        // sign the token with every moderator
+        let (nonces, commitments): (Vec<_>, Vec<_>) = key_packages
+            .iter()
+            .map(|key_package| {
+                frost::round1::commit(
+                    key_package.identifier,
+                    &key_package.secret_share,
+                    &mut rng,
+                )
+            })
+            .unzip();
+
+        let signing_package = frost::SigningPackage::new(
+            commitments,
+            bincode::serialize(&token)?,
+        );
+
+        let signature_shares = key_packages
+            .iter()
+            .zip(&nonces)
+            .map(|(key_package, nonces)| {
+                frost::round2::sign(&signing_package, nonces, key_package)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let signature = frost::aggregate(
+            &signing_package,
+            &signature_shares,
+            &frost_public_key,
+        )?;
+
+        Ok((
+            SignedToken { signature, token },
+            frost_public_key.group_public,
+            elgamal_public_key,
+        ))
+    }
+
+    fn token_verification<C: Ciphersuite>() -> Result<()> {
+        let user_id = UserId::random(&mut rand::thread_rng());
+        let (mut signed_token, _, group_public_key) =
+            issue_token::<C>(user_id)?;
+
+        assert!(
+            signed_token.verify(&group_public_key).is_ok(),
+            "Valid token failed to verify."
+        );
+
+        // tamper with the token
+        signed_token.token.timestamp += 1;
+
+        assert!(
+            signed_token.verify(&group_public_key).is_err(),
+            "Tampered token passed verification."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_verification() -> Result<()> {
+        token_verification::<Ristretto255>()
+    }
+
+    #[test]
+    fn test_ed25519_token_verification() -> Result<()> {
+        token_verification::<Ed25519>()
+    }
+
+    #[test]
+    fn test_timestamp_policy() {
+        let policy = TimestampPolicy {
+            max_clock_skew: 10,
+            max_token_age: 100,
+        };
+        let now = Utc::now().timestamp();
+
+        // issuance
+        assert!(policy.check_issuance(now, now).is_ok());
+        assert!(policy.check_issuance(now - 10, now).is_ok());
+        assert!(policy.check_issuance(now + 10, now).is_ok());
+        assert!(policy.check_issuance(now - 11, now).is_err());
+        assert!(policy.check_issuance(now + 11, now).is_err());
+
+        // reporting
+        assert!(policy.check_age(now - 100, now).is_ok());
+        assert!(policy.check_age(now + 10, now).is_ok());
+        assert!(policy.check_age(now - 101, now).is_err());
+        assert!(policy.check_age(now + 11, now).is_err());
+
+        // timestamps that would overflow are rejected, not wrapped
+        assert!(policy.check_issuance(i64::MIN, now).is_err());
+        assert!(policy.check_issuance(i64::MAX, -1).is_err());
+        assert!(policy.check_age(i64::MIN, now).is_err());
+        assert!(policy.check_age(i64::MAX, -1).is_err());
+        assert!(policy.check_age(0, i64::MIN).is_err());
+    }
+}
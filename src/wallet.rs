@@ -0,0 +1,233 @@
+use std::{fs, path::Path};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ciphersuite::{Ciphersuite, Ristretto255},
+    token::{EphemeralSecretKey, SignedToken, TimestampPolicy},
+    Batch, Result, UserPublicKey,
+};
+
+/// A client's store of tokens and their ephemeral secret keys.
+///
+/// Every token is handed out at most once. Tokens are considered usable until
+/// they are older than the moderators' `max_token_age`, after which messages
+/// sent with them could no longer be reported.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct TokenWallet<C: Ciphersuite = Ristretto255> {
+    entries: Vec<WalletEntry<C>>,
+    timestamp_policy: TimestampPolicy,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+struct WalletEntry<C: Ciphersuite> {
+    token: SignedToken<C>,
+    sk_e: EphemeralSecretKey<C>,
+    consumed: bool,
+}
+
+impl<C: Ciphersuite> WalletEntry<C> {
+    fn is_usable(&self, policy: &TimestampPolicy, now: i64) -> bool {
+        !self.consumed && !self.is_expired(policy, now)
+    }
+
+    fn is_expired(&self, policy: &TimestampPolicy, now: i64) -> bool {
+        now - self.token.token.timestamp > policy.max_token_age
+    }
+}
+
+impl<C: Ciphersuite> TokenWallet<C> {
+    /// Creates an empty wallet. `timestamp_policy` should match the one
+    /// enforced by the moderators.
+    pub fn new(timestamp_policy: TimestampPolicy) -> Self {
+        Self {
+            entries: Vec::new(),
+            timestamp_policy,
+        }
+    }
+
+    /// Adds a batch of freshly issued tokens to the wallet.
+    pub fn add(
+        &mut self,
+        tokens: Batch<(SignedToken<C>, EphemeralSecretKey<C>)>,
+    ) {
+        self.entries.extend(tokens.into_iter().map(|(token, sk_e)| {
+            WalletEntry {
+                token,
+                sk_e,
+                consumed: false,
+            }
+        }));
+    }
+
+    /// Takes the oldest usable token out of the wallet and marks it as consumed.
+    ///
+    /// Returns `None` if there are no usable tokens left.
+    pub fn take(&mut self) -> Option<(SignedToken<C>, EphemeralSecretKey<C>)> {
+        self.take_at(Utc::now().timestamp())
+    }
+
+    fn take_at(
+        &mut self,
+        now: i64,
+    ) -> Option<(SignedToken<C>, EphemeralSecretKey<C>)> {
+        let policy = self.timestamp_policy;
+        let entry = self
+            .entries
+            .iter_mut()
+            .filter(|entry| entry.is_usable(&policy, now))
+            .min_by_key(|entry| entry.token.token.timestamp)?;
+
+        entry.consumed = true;
+
+        Some((entry.token.clone(), entry.sk_e.clone()))
+    }
+
+    /// Marks the token with ephemeral key `pk_e` as consumed.
+    ///
+    /// Fails if the token is unknown or has already been used.
+    pub fn consume(&mut self, pk_e: &UserPublicKey<C>) -> Result<()> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.token.token.pk_e == *pk_e)
+            .ok_or("Token is not in the wallet.")?;
+
+        if entry.consumed {
+            return Err("Token has already been used.".into());
+        }
+
+        entry.consumed = true;
+
+        Ok(())
+    }
+
+    /// The number of tokens that are neither consumed nor expired.
+    pub fn remaining(&self) -> usize {
+        self.remaining_at(Utc::now().timestamp())
+    }
+
+    fn remaining_at(&self, now: i64) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_usable(&self.timestamp_policy, now))
+            .count()
+    }
+
+    /// Whether fewer than `low_watermark` usable tokens remain,
+    /// i.e., whether a new batch should be requested.
+    pub fn needs_refill(&self, low_watermark: usize) -> bool {
+        self.remaining() < low_watermark
+    }
+
+    /// Drops expired tokens from the wallet.
+    ///
+    /// Consumed tokens are kept until they expire so that reuse is still refused.
+    pub fn prune(&mut self) {
+        self.prune_at(Utc::now().timestamp())
+    }
+
+    fn prune_at(&mut self, now: i64) {
+        let policy = self.timestamp_policy;
+        self.entries.retain(|entry| !entry.is_expired(&policy, now));
+    }
+
+    /// Writes the wallet to disk.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, bincode::serialize(self)?)?;
+
+        Ok(())
+    }
+
+    /// Reads a wallet previously written with [`TokenWallet::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(bincode::deserialize(&fs::read(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenWallet;
+    use crate::{
+        ciphersuite::Ristretto255, token::tests::issue_token, Result,
+        TimestampPolicy, UserId,
+    };
+    use chrono::Utc;
+
+    fn wallet_with_tokens(n_tokens: usize) -> Result<TokenWallet> {
+        let user_id = UserId::random(&mut rand::thread_rng());
+
+        let mut wallet = TokenWallet::new(TimestampPolicy::default());
+        let tokens = (0..n_tokens)
+            .map(|_| {
+                issue_token::<Ristretto255>(user_id)
+                    .map(|(token, sk_e, _)| (token, sk_e))
+            })
+            .collect::<Result<_>>()?;
+        wallet.add(tokens);
+
+        Ok(wallet)
+    }
+
+    #[test]
+    fn test_single_use() -> Result<()> {
+        let mut wallet = wallet_with_tokens(2)?;
+        assert_eq!(wallet.remaining(), 2);
+
+        let (first, _) = wallet.take().expect("Wallet should have a token.");
+        let (second, _) = wallet.take().expect("Wallet should have a token.");
+
+        assert_ne!(
+            first.token.pk_e, second.token.pk_e,
+            "Wallet handed out the same token twice."
+        );
+        assert!(wallet.take().is_none(), "Wallet handed out a used token.");
+        assert!(wallet.consume(&first.token.pk_e).is_err());
+        assert_eq!(wallet.remaining(), 0);
+        assert!(wallet.needs_refill(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiry() -> Result<()> {
+        let mut wallet = wallet_with_tokens(3)?;
+        let later = Utc::now().timestamp()
+            + TimestampPolicy::default().max_token_age
+            + 1;
+
+        assert_eq!(wallet.remaining_at(later), 0);
+        assert!(wallet.take_at(later).is_none());
+
+        wallet.prune_at(later);
+        assert!(wallet.entries.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialization() -> Result<()> {
+        let mut wallet = wallet_with_tokens(2)?;
+        let (used, _) = wallet.take().expect("Wallet should have a token.");
+
+        // a unique path, so that concurrent test runs don't collide
+        let path = std::env::temp_dir().join(format!(
+            "cerberus-test-wallet-{:016x}",
+            rand::random::<u64>()
+        ));
+        wallet.save(&path)?;
+        let mut loaded = TokenWallet::<Ristretto255>::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(loaded.remaining(), 1);
+        assert!(
+            loaded.consume(&used.token.pk_e).is_err(),
+            "Consumed token was usable after reloading the wallet."
+        );
+
+        Ok(())
+    }
+}